@@ -0,0 +1,257 @@
+//! Agent Event Queue
+//!
+//! Buffers events from the agent actor and emits them to the webview in order.
+//! Snapshot-style events (stream text, thinking, transient status) are coalesced so a
+//! fast local model can't flood the IPC bridge - only the latest value is sent.
+
+use std::time::{Duration, Instant};
+use futures::StreamExt;
+use serde::Serialize;
+use serde_json::Value;
+use tauri::{AppHandle, Emitter};
+use tokio::sync::mpsc;
+
+/// What the agent is doing, emitted as `agent-status`
+/// Tagged by `state` so the frontend can switch on it and choose its own wording
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum AgentStatus {
+    Thinking,
+    Streaming,
+    ExecutingTool { name: String },
+    /// Several tool calls from one reply running in parallel
+    ExecutingTools { count: usize },
+    WaitingApproval { tool: String },
+    /// The model replied without the tool format and is being reminded of it
+    Nudging,
+    /// A transient provider error is being retried
+    Retrying,
+    LoadingModel,
+    Benchmarking,
+    Ready,
+    Cancelled,
+    MaxSteps,
+    Error { message: String },
+    /// Informational message that doesn't change what the agent is doing
+    Notice { message: String },
+}
+
+impl AgentStatus {
+    pub fn error(message: impl Into<String>) -> Self {
+        AgentStatus::Error { message: message.into() }
+    }
+    
+    pub fn notice(message: impl Into<String>) -> Self {
+        AgentStatus::Notice { message: message.into() }
+    }
+}
+
+/// Events whose payload replaces the previous one, so intermediate values can be dropped
+const COALESCED_EVENTS: &[&str] = &["agent-stream-chunk", "agent-thinking", "agent-status"];
+
+/// `agent-status` states carrying a message the user must see - always delivered
+const DELIVERED_STATUSES: &[&str] = &["notice", "error"];
+
+/// Pause between batches so rapid updates accumulate and coalesce (~one frame)
+const EMIT_INTERVAL: Duration = Duration::from_millis(16);
+
+/// Maximum events drained into a single batch
+const MAX_BATCH: usize = 256;
+
+/// A queued event awaiting emission
+#[derive(Debug, Clone)]
+struct QueuedEvent {
+    name: &'static str,
+    payload: Value,
+}
+
+/// Handle for queueing events; emission happens on a background task
+#[derive(Clone)]
+pub struct EventQueue {
+    tx: mpsc::UnboundedSender<QueuedEvent>,
+}
+
+impl EventQueue {
+    /// Start the emitter task for this app
+    pub fn spawn(app: AppHandle) -> Self {
+        let (tx, mut rx) = mpsc::unbounded_channel::<QueuedEvent>();
+        
+        tauri::async_runtime::spawn(async move {
+            while let Some(first) = rx.recv().await {
+                let mut batch = vec![first];
+                while batch.len() < MAX_BATCH {
+                    match rx.try_recv() {
+                        Ok(event) => batch.push(event),
+                        Err(_) => break,
+                    }
+                }
+                
+                for event in coalesce(batch) {
+                    if let Err(e) = app.emit(event.name, event.payload) {
+                        eprintln!("[EventQueue] Failed to emit {}: {}", event.name, e);
+                    }
+                }
+                
+                tokio::time::sleep(EMIT_INTERVAL).await;
+            }
+        });
+        
+        Self { tx }
+    }
+    
+    /// Queue an event; never blocks the caller
+    pub fn emit<S: Serialize>(&self, name: &'static str, payload: S) -> Result<(), String> {
+        let payload = serde_json::to_value(payload)
+            .map_err(|e| format!("Failed to serialize {}: {}", name, e))?;
+        self.tx.send(QueuedEvent { name, payload })
+            .map_err(|_| "Event queue closed".to_string())
+    }
+}
+
+/// Whether a later event of the same name may replace this one
+fn is_coalescible(event: &QueuedEvent) -> bool {
+    if !COALESCED_EVENTS.contains(&event.name) {
+        return false;
+    }
+    event.name != "agent-status"
+        || !event.payload.get("state").and_then(Value::as_str).map_or(false, |state| DELIVERED_STATUSES.contains(&state))
+}
+
+/// Drop coalescible events superseded by a later event of the same name
+/// Other events act as barriers, so the relative order the frontend sees is preserved
+fn coalesce(batch: Vec<QueuedEvent>) -> Vec<QueuedEvent> {
+    let mut output: Vec<QueuedEvent> = Vec::with_capacity(batch.len());
+    // Index in `output` where the current barrier-free run starts
+    let mut run_start = 0;
+    
+    for event in batch {
+        if is_coalescible(&event) {
+            if let Some(existing) = output[run_start..].iter_mut().find(|e| e.name == event.name) {
+                existing.payload = event.payload;
+                continue;
+            }
+        } else {
+            run_start = output.len() + 1;
+        }
+        output.push(event);
+    }
+    
+    output
+}
+
+/// Decides when buffered stream text should be flushed to the UI
+/// Flushes once enough characters are pending or the flush interval has elapsed
+pub(crate) struct StreamThrottle {
+    chunk_chars: usize,
+    flush_interval: Option<Duration>,
+    pending_chars: usize,
+    last_flush: Instant,
+}
+
+impl StreamThrottle {
+    pub(crate) fn from_settings() -> Self {
+        let settings = crate::state::settings::get();
+        Self {
+            chunk_chars: settings.stream_chunk_chars.max(1),
+            flush_interval: (settings.stream_flush_ms > 0).then(|| Duration::from_millis(settings.stream_flush_ms)),
+            pending_chars: 0,
+            last_flush: Instant::now(),
+        }
+    }
+    
+    /// Record newly buffered text
+    pub(crate) fn push(&mut self, text: &str) {
+        self.pending_chars += text.chars().count();
+    }
+    
+    pub(crate) fn has_pending(&self) -> bool {
+        self.pending_chars > 0
+    }
+    
+    /// Whether pending text should be flushed now
+    pub(crate) fn should_flush(&self) -> bool {
+        self.has_pending() && (self.pending_chars >= self.chunk_chars || self.time_until_flush() == Some(Duration::ZERO))
+    }
+    
+    /// Time left before pending text is flushed by the interval, if time-based flushing is enabled
+    fn time_until_flush(&self) -> Option<Duration> {
+        self.flush_interval.map(|interval| interval.saturating_sub(self.last_flush.elapsed()))
+    }
+    
+    pub(crate) fn flushed(&mut self) {
+        self.pending_chars = 0;
+        self.last_flush = Instant::now();
+    }
+    
+    /// Wait for the next stream item, returning `None` from the outer option if the flush interval elapses first
+    pub(crate) async fn next<S: futures::Stream + Unpin>(&self, stream: &mut S) -> Option<Option<S::Item>> {
+        match self.time_until_flush().filter(|_| self.has_pending()) {
+            Some(remaining) => tokio::time::timeout(remaining, stream.next()).await.ok(),
+            None => Some(stream.next().await),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn event(name: &'static str, payload: &str) -> QueuedEvent {
+        QueuedEvent { name, payload: Value::String(payload.to_string()) }
+    }
+    
+    #[test]
+    fn test_coalesce_keeps_latest_snapshot() {
+        let batch = vec![
+            event("agent-stream-chunk", "a"),
+            event("agent-thinking", "t1"),
+            event("agent-stream-chunk", "ab"),
+            event("agent-thinking", "t2"),
+        ];
+        let output = coalesce(batch);
+        
+        assert_eq!(output.len(), 2);
+        assert_eq!(output[0].payload, "ab");
+        assert_eq!(output[1].payload, "t2");
+    }
+    
+    #[test]
+    fn test_coalesce_respects_barriers() {
+        let batch = vec![
+            event("agent-stream-chunk", "a"),
+            event("agent-stream-end", ""),
+            event("agent-stream-chunk", "b"),
+        ];
+        let output = coalesce(batch);
+        
+        let names: Vec<&str> = output.iter().map(|e| e.name).collect();
+        assert_eq!(names, vec!["agent-stream-chunk", "agent-stream-end", "agent-stream-chunk"]);
+        assert_eq!(output[0].payload, "a");
+    }
+    
+    #[test]
+    fn test_coalesce_delivers_notices_and_errors() {
+        let status = |status: AgentStatus| QueuedEvent { name: "agent-status", payload: serde_json::to_value(status).unwrap() };
+        let batch = vec![
+            status(AgentStatus::Thinking),
+            status(AgentStatus::notice("Images ignored")),
+            status(AgentStatus::Streaming),
+            status(AgentStatus::error("Rate limited")),
+            status(AgentStatus::Retrying),
+            status(AgentStatus::Thinking),
+        ];
+        let output = coalesce(batch);
+        
+        let states: Vec<&str> = output.iter().map(|e| e.payload["state"].as_str().unwrap()).collect();
+        assert_eq!(states, vec!["thinking", "notice", "streaming", "error", "thinking"]);
+    }
+    
+    #[test]
+    fn test_agent_status_is_tagged() {
+        let status = serde_json::to_value(AgentStatus::ExecutingTool { name: "read_file".to_string() }).unwrap();
+        assert_eq!(status, serde_json::json!({ "state": "executing_tool", "name": "read_file" }));
+        
+        let ready = serde_json::to_value(AgentStatus::Ready).unwrap();
+        assert_eq!(ready, serde_json::json!({ "state": "ready" }));
+    }
+}
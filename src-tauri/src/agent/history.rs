@@ -1,8 +1,12 @@
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
+use serde_json::Value;
 use uuid::Uuid;
+use parking_lot::Mutex;
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 
 use crate::agent::memory::Message;
 
@@ -15,6 +19,57 @@ pub struct Conversation {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub mode: String,
+    /// ReAct step trace for debugging agent reasoning (turbo mode only)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub trace: Vec<TraceStep>,
+    /// User-assigned labels for organizing conversations
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub favorite: bool,
+    /// Progress of a turbo run still in flight - left set when the app dies mid-run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run_state: Option<RunState>,
+    /// Connection mode ("cloud" or "offline") the user picked while this conversation was open
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider_preference: Option<String>,
+}
+
+/// Minimal state of a running turbo task, saved after every step
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunState {
+    /// Last completed ReAct step
+    pub step: usize,
+    /// Tool invoked in the last step (or awaiting approval)
+    pub last_tool: Option<String>,
+    /// Whether the run was paused on a tool approval prompt
+    #[serde(default)]
+    pub awaiting_approval: bool,
+    pub started_at: DateTime<Utc>,
+}
+
+impl RunState {
+    /// State for a run that is just starting
+    pub fn start() -> Self {
+        Self {
+            step: 0,
+            last_tool: None,
+            awaiting_approval: false,
+            started_at: Utc::now(),
+        }
+    }
+}
+
+/// A single ReAct step recorded for debugging
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TraceStep {
+    pub step: usize,
+    pub thinking: Option<String>,
+    /// Tool invoked in this step (None for a final answer)
+    pub tool: Option<String>,
+    pub parameters: Option<Value>,
+    /// Length of the tool observation fed back to the model
+    pub observation_len: usize,
 }
 
 impl Conversation {
@@ -26,6 +81,11 @@ impl Conversation {
             created_at: Utc::now(),
             updated_at: Utc::now(),
             mode: mode.to_string(),
+            trace: Vec::new(),
+            tags: Vec::new(),
+            favorite: false,
+            run_state: None,
+            provider_preference: None,
         }
     }
 
@@ -56,6 +116,89 @@ impl Conversation {
     }
 }
 
+/// Parse an exported conversation (json or markdown) into a new conversation
+/// The import gets a fresh id so it never overwrites an existing chat
+pub fn import_conversation(data: &str, format: &str) -> Result<Conversation, String> {
+    let mut conversation = match format.to_lowercase().as_str() {
+        "json" => serde_json::from_str::<Conversation>(data)
+            .map_err(|e| format!("Invalid conversation JSON: {}", e))?,
+        "markdown" | "md" => parse_markdown_export(data)?,
+        _ => return Err(format!("Unsupported format: {}. Use 'json' or 'markdown'", format)),
+    };
+    
+    if conversation.messages.is_empty() {
+        return Err("Conversation has no messages".to_string());
+    }
+    if let Some(msg) = conversation.messages.iter().find(|m| m.role != "user" && m.role != "model") {
+        return Err(format!("Invalid message role '{}'", msg.role));
+    }
+    
+    conversation.id = Uuid::new_v4().to_string();
+    conversation.updated_at = Utc::now();
+    if conversation.title.trim().is_empty() {
+        conversation.title = "New Chat".to_string();
+        conversation.generate_title();
+    }
+    Ok(conversation)
+}
+
+/// Parse the markdown produced by `export_conversation`
+/// Sections are separated by `---` lines and start with **User**: or **Assistant**:
+fn parse_markdown_export(data: &str) -> Result<Conversation, String> {
+    let data = data.replace("\r\n", "\n");
+    let mut conversation = Conversation::new("chat");
+    let mut sections = data.split("\n---\n");
+    
+    let header = sections.next().unwrap_or("");
+    let title = header.lines()
+        .find_map(|line| line.strip_prefix("# "))
+        .ok_or("Missing '# Title' header")?;
+    conversation.title = title.trim().to_string();
+    
+    if let Some(created) = header.lines().find_map(|line| line.trim().strip_prefix("*Created: ")) {
+        let created = created.trim_end_matches('*').trim_end_matches(" UTC");
+        if let Ok(naive) = chrono::NaiveDateTime::parse_from_str(created, "%Y-%m-%d %H:%M:%S%.f") {
+            conversation.created_at = naive.and_utc();
+        }
+    }
+    
+    for section in sections {
+        let mut model_tier = None;
+        let section = section.trim_matches('\n');
+        let (role, content) = if let Some(rest) = section.strip_prefix("**User**:") {
+            ("user", rest)
+        } else if let Some(rest) = section.strip_prefix("**Assistant**") {
+            // Either "**Assistant**:" or "**Assistant** (27B):" with the model tier
+            let Some((label, content)) = rest.split_once(':') else {
+                return Err("Malformed **Assistant** section".to_string());
+            };
+            let tier = label.trim().trim_start_matches('(').trim_end_matches(')');
+            if !tier.is_empty() {
+                model_tier = Some(tier.to_string());
+            }
+            ("model", content)
+        } else {
+            // A `---` inside a message body - glue it back onto the previous message
+            if let Some(last) = conversation.messages.last_mut() {
+                if !section.trim().is_empty() {
+                    last.content.push_str("\n---\n");
+                    last.content.push_str(section);
+                }
+                continue;
+            }
+            if section.trim().is_empty() {
+                continue;
+            }
+            return Err("Expected a **User**: or **Assistant**: section".to_string());
+        };
+        let mut message = Message::new(role, content.trim());
+        message.model_tier = model_tier.take();
+        conversation.messages.push(message);
+    }
+    
+    Ok(conversation)
+}
+
 /// Metadata for conversation list (without full messages)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConversationMeta {
@@ -65,6 +208,34 @@ pub struct ConversationMeta {
     pub updated_at: DateTime<Utc>,
     pub message_count: usize,
     pub mode: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub favorite: bool,
+    /// A turbo run was still in progress when the conversation was last saved
+    #[serde(default)]
+    pub interrupted: bool,
+    /// The file couldn't be read - `repair_conversation` may recover part of it
+    #[serde(default)]
+    pub corrupt: bool,
+}
+
+impl ConversationMeta {
+    /// Placeholder listing entry for a file that failed to parse
+    fn corrupt(id: &str, modified: DateTime<Utc>) -> Self {
+        Self {
+            id: id.to_string(),
+            title: "Unreadable conversation".to_string(),
+            created_at: modified,
+            updated_at: modified,
+            message_count: 0,
+            mode: "chat".to_string(),
+            tags: Vec::new(),
+            favorite: false,
+            interrupted: false,
+            corrupt: true,
+        }
+    }
 }
 
 impl From<&Conversation> for ConversationMeta {
@@ -76,35 +247,277 @@ impl From<&Conversation> for ConversationMeta {
             updated_at: conv.updated_at,
             message_count: conv.messages.len(),
             mode: conv.mode.clone(),
+            tags: conv.tags.clone(),
+            favorite: conv.favorite,
+            interrupted: conv.run_state.is_some(),
+            corrupt: false,
+        }
+    }
+}
+
+/// One line of a conversation's `.jsonl` log
+/// Saves append new messages and trace steps instead of rewriting the whole file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "record", rename_all = "snake_case")]
+enum LogRecord {
+    /// Conversation metadata - the last one in the file wins
+    Meta(ConversationHeader),
+    Message(Message),
+    Trace(TraceStep),
+}
+
+/// Conversation fields other than messages and trace
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ConversationHeader {
+    id: String,
+    title: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    mode: String,
+    /// Only written by full rewrites, so the agent's appends never clobber UI edits
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tags: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    favorite: Option<bool>,
+    /// Written by every save, so a header without it means the run finished
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    run_state: Option<RunState>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    provider_preference: Option<String>,
+}
+
+impl ConversationHeader {
+    fn from_conversation(conv: &Conversation, with_labels: bool) -> Self {
+        Self {
+            id: conv.id.clone(),
+            title: conv.title.clone(),
+            created_at: conv.created_at,
+            updated_at: conv.updated_at,
+            mode: conv.mode.clone(),
+            tags: with_labels.then(|| conv.tags.clone()),
+            favorite: with_labels.then_some(conv.favorite),
+            run_state: conv.run_state.clone(),
+            provider_preference: conv.provider_preference.clone(),
+        }
+    }
+}
+
+/// Serialize records as newline-terminated JSON lines
+fn encode_log(records: impl Iterator<Item = LogRecord>) -> Result<String, String> {
+    let mut lines = String::new();
+    for record in records {
+        lines.push_str(&serde_json::to_string(&record).map_err(|e| format!("Failed to serialize: {}", e))?);
+        lines.push('\n');
+    }
+    Ok(lines)
+}
+
+/// Rebuild a conversation from its log, skipping lines that fail to parse
+/// (e.g. a line cut short by a crash mid-append)
+fn parse_log(content: &str) -> Option<Conversation> {
+    let mut conversation: Option<Conversation> = None;
+    let mut messages = Vec::new();
+    let mut trace = Vec::new();
+    
+    for line in content.lines().filter(|l| !l.trim().is_empty()) {
+        match serde_json::from_str::<LogRecord>(line) {
+            Ok(LogRecord::Meta(header)) => {
+                let conv = conversation.get_or_insert_with(|| Conversation::new(&header.mode));
+                conv.id = header.id;
+                conv.title = header.title;
+                conv.created_at = header.created_at;
+                conv.updated_at = header.updated_at;
+                conv.mode = header.mode;
+                if let Some(tags) = header.tags {
+                    conv.tags = tags;
+                }
+                if let Some(favorite) = header.favorite {
+                    conv.favorite = favorite;
+                }
+                conv.run_state = header.run_state;
+                conv.provider_preference = header.provider_preference;
+            }
+            Ok(LogRecord::Message(message)) => messages.push(message),
+            Ok(LogRecord::Trace(step)) => trace.push(step),
+            Err(e) => eprintln!("[HistoryManager] Skipping unreadable history line: {}", e),
+        }
+    }
+    
+    let mut conversation = conversation?;
+    conversation.messages = messages;
+    conversation.trace = trace;
+    Some(conversation)
+}
+
+/// Byte offset just past `"key":` at the first unescaped occurrence of the key
+fn find_json_key(content: &str, key: &str) -> Option<usize> {
+    let needle = format!("\"{}\"", key);
+    content.match_indices(&needle)
+        .filter(|(at, _)| !content[..*at].ends_with('\\'))
+        .find_map(|(at, _)| {
+            let after = at + needle.len();
+            let rest = content[after..].trim_start();
+            rest.strip_prefix(':').map(|_| content.len() - rest.len() + 1)
+        })
+}
+
+/// Parse the value of a top-level field from JSON that may be cut short
+fn salvage_field<T: serde::de::DeserializeOwned>(content: &str, key: &str) -> Option<T> {
+    let start = find_json_key(content, key)?;
+    serde_json::Deserializer::from_str(&content[start..]).into_iter::<T>().next()?.ok()
+}
+
+/// Every complete message before the point where a legacy `.json` file was cut off
+fn salvage_messages(content: &str) -> Vec<Message> {
+    let mut messages = Vec::new();
+    let Some(start) = find_json_key(content, "messages") else {
+        return messages;
+    };
+    let Some(mut rest) = content[start..].trim_start().strip_prefix('[') else {
+        return messages;
+    };
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == ',');
+        if rest.is_empty() || rest.starts_with(']') {
+            break;
+        }
+        let mut stream = serde_json::Deserializer::from_str(rest).into_iter::<Message>();
+        match stream.next() {
+            Some(Ok(message)) => {
+                messages.push(message);
+                rest = &rest[stream.byte_offset()..];
+            }
+            _ => break,
         }
     }
+    messages
+}
+
+/// Rebuild what can be read from a damaged conversation file
+/// Logs keep every readable message line (the header is what was lost); legacy JSON keeps
+/// the messages before the cut. Returns None when no message survived
+fn salvage_conversation(id: &str, content: &str, is_log: bool) -> Option<Conversation> {
+    let (title, mode, created_at, messages) = if is_log {
+        let messages: Vec<Message> = content.lines()
+            .filter_map(|line| match serde_json::from_str::<LogRecord>(line) {
+                Ok(LogRecord::Message(message)) => Some(message),
+                _ => None,
+            })
+            .collect();
+        (None, None, None, messages)
+    } else {
+        (
+            salvage_field::<String>(content, "title"),
+            salvage_field::<String>(content, "mode"),
+            salvage_field::<DateTime<Utc>>(content, "created_at"),
+            salvage_messages(content),
+        )
+    };
+    if messages.is_empty() {
+        return None;
+    }
+    
+    let mut conversation = Conversation::new(mode.as_deref().unwrap_or("chat"));
+    conversation.id = id.to_string();
+    conversation.title = title.unwrap_or_else(|| "Recovered conversation".to_string());
+    let first = messages.first().and_then(|m| m.timestamp);
+    let last = messages.iter().rev().find_map(|m| m.timestamp);
+    conversation.created_at = created_at.or(first).unwrap_or(conversation.created_at);
+    conversation.updated_at = last.unwrap_or(conversation.created_at);
+    conversation.messages = messages;
+    Some(conversation)
+}
+
+/// How long saved conversations are kept
+/// Favorited and tagged conversations are never pruned and don't count toward the limit
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Keep at most this many of the most recently updated conversations (None = no limit)
+    pub max_conversations: Option<usize>,
+    /// Delete conversations not updated for this many days (None = no limit)
+    pub max_age_days: Option<u64>,
+}
+
+impl RetentionPolicy {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_conversations.is_none() && self.max_age_days.is_none()
+    }
 }
 
 /// Manages chat history persistence
+/// Conversations are stored as append-only `.jsonl` logs; legacy `.json` files are
+/// still read and are migrated the first time they are saved
 pub struct HistoryManager {
     history_dir: std::path::PathBuf,
+    /// Message and trace counts already on disk per conversation, so saves can append
+    persisted: Mutex<HashMap<String, (usize, usize)>>,
 }
 
 impl HistoryManager {
     pub fn new(history_dir: &Path) -> Self {
         Self {
             history_dir: history_dir.to_path_buf(),
+            persisted: Mutex::new(HashMap::new()),
+        }
+    }
+    
+    fn log_path(&self, id: &str) -> PathBuf {
+        self.history_dir.join(format!("{}.jsonl", id))
+    }
+    
+    fn legacy_path(&self, id: &str) -> PathBuf {
+        self.history_dir.join(format!("{}.json", id))
+    }
+    
+    /// Read a conversation from either storage format
+    fn read_conversation_file(path: &Path) -> Option<Conversation> {
+        let content = fs::read_to_string(path).ok()?;
+        if path.extension().map_or(false, |e| e == "jsonl") {
+            parse_log(&content)
+        } else {
+            serde_json::from_str(&content).ok()
         }
     }
 
     /// List all conversations (metadata only)
     pub fn list_conversations(&self) -> Vec<ConversationMeta> {
+        self.list_conversations_filtered(None, false)
+    }
+
+    /// List conversations with a given tag and/or only favorites (metadata only)
+    pub fn list_conversations_filtered(&self, tag: Option<&str>, favorites_only: bool) -> Vec<ConversationMeta> {
         let mut conversations = Vec::new();
 
         if let Ok(entries) = fs::read_dir(&self.history_dir) {
             for entry in entries.flatten() {
                 let path = entry.path();
-                if path.extension().map_or(false, |e| e == "json") {
-                    if let Ok(content) = fs::read_to_string(&path) {
-                        if let Ok(conv) = serde_json::from_str::<Conversation>(&content) {
-                            conversations.push(ConversationMeta::from(&conv));
+                let is_log = path.extension().map_or(false, |e| e == "jsonl");
+                let is_legacy = path.extension().map_or(false, |e| e == "json");
+                // A legacy file next to its log is a leftover from an interrupted migration
+                if !is_log && !(is_legacy && !path.with_extension("jsonl").exists()) {
+                    continue;
+                }
+                match Self::read_conversation_file(&path) {
+                    Some(conv) => {
+                        if favorites_only && !conv.favorite {
+                            continue;
+                        }
+                        if tag.map_or(false, |tag| !conv.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))) {
+                            continue;
                         }
+                        conversations.push(ConversationMeta::from(&conv));
                     }
+                    // Listed rather than hidden, so the user can repair or delete it
+                    None if tag.is_none() && !favorites_only => {
+                        let Some(id) = path.file_stem().map(|s| s.to_string_lossy().to_string()) else {
+                            continue;
+                        };
+                        eprintln!("[HistoryManager] Unreadable conversation file: {}", path.display());
+                        let modified = entry.metadata().and_then(|m| m.modified()).map(DateTime::<Utc>::from).unwrap_or_else(|_| Utc::now());
+                        conversations.push(ConversationMeta::corrupt(&id, modified));
+                    }
+                    None => {}
                 }
             }
         }
@@ -116,35 +529,362 @@ impl HistoryManager {
 
     /// Load a conversation by ID
     pub fn load_conversation(&self, id: &str) -> Option<Conversation> {
-        let path = self.history_dir.join(format!("{}.json", id));
-        if path.exists() {
-            if let Ok(content) = fs::read_to_string(&path) {
-                return serde_json::from_str(&content).ok();
-            }
+        let log_path = self.log_path(id);
+        if log_path.exists() {
+            return Self::read_conversation_file(&log_path);
+        }
+        let legacy_path = self.legacy_path(id);
+        if legacy_path.exists() {
+            return Self::read_conversation_file(&legacy_path);
         }
         None
     }
 
+    /// Recover what can be read from a conversation file that fails to parse and rewrite it
+    /// as a valid log; the damaged file is kept alongside with a `.corrupt` extension
+    pub fn repair_conversation(&self, id: &str) -> Result<ConversationMeta, String> {
+        if let Some(conversation) = self.load_conversation(id) {
+            return Ok(ConversationMeta::from(&conversation));
+        }
+        let (path, is_log) = if self.log_path(id).exists() {
+            (self.log_path(id), true)
+        } else if self.legacy_path(id).exists() {
+            (self.legacy_path(id), false)
+        } else {
+            return Err(format!("Conversation {} not found", id));
+        };
+        
+        // Lossy, since a crash can also cut a multi-byte character in half
+        let bytes = fs::read(&path).map_err(|e| format!("Failed to read conversation: {}", e))?;
+        let conversation = salvage_conversation(id, &String::from_utf8_lossy(&bytes), is_log)
+            .ok_or_else(|| "No messages could be recovered from this conversation".to_string())?;
+        
+        fs::copy(&path, path.with_extension("corrupt"))
+            .map_err(|e| format!("Failed to back up damaged file: {}", e))?;
+        self.write_conversation(&conversation)?;
+        println!("[HistoryManager] Repaired {}: recovered {} messages", id, conversation.messages.len());
+        Ok(ConversationMeta::from(&conversation))
+    }
+
     /// Save a conversation
+    /// Only messages and trace steps added since the last save are appended; the file is
+    /// rewritten when history was truncated or this manager hasn't written it yet.
+    /// Tags and favorites are edited from the UI while the agent holds its own copy,
+    /// so the values already on disk win over the ones being saved
     pub fn save_conversation(&self, conversation: &Conversation) -> Result<(), String> {
-        let path = self.history_dir.join(format!("{}.json", conversation.id));
+        let persisted = self.persisted.lock().get(&conversation.id).copied();
+        if let Some((messages, trace)) = persisted {
+            if messages <= conversation.messages.len()
+                && trace <= conversation.trace.len()
+                && self.log_path(&conversation.id).exists()
+            {
+                return self.append_conversation(conversation, messages, trace);
+            }
+        }
         
-        let json = serde_json::to_string_pretty(conversation)
-            .map_err(|e| format!("Failed to serialize: {}", e))?;
+        match self.load_conversation(&conversation.id) {
+            Some(existing) => {
+                let mut conversation = conversation.clone();
+                conversation.tags = existing.tags;
+                conversation.favorite = existing.favorite;
+                self.write_conversation(&conversation)
+            }
+            None => self.write_conversation(conversation),
+        }
+    }
+
+    /// Append the messages and trace steps after the given counts, plus fresh metadata
+    fn append_conversation(&self, conversation: &Conversation, messages: usize, trace: usize) -> Result<(), String> {
+        let records = conversation.messages[messages..].iter().cloned().map(LogRecord::Message)
+            .chain(conversation.trace[trace..].iter().cloned().map(LogRecord::Trace))
+            .chain(std::iter::once(LogRecord::Meta(ConversationHeader::from_conversation(conversation, false))));
+        let lines = encode_log(records)?;
+        
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(self.log_path(&conversation.id))
+            .map_err(|e| format!("Failed to open history log: {}", e))?;
+        file.write_all(lines.as_bytes())
+            .map_err(|e| format!("Failed to write file: {}", e))?;
+        
+        self.persisted.lock().insert(conversation.id.clone(), (conversation.messages.len(), conversation.trace.len()));
+        Ok(())
+    }
+
+    /// Write a conversation to disk as-is, replacing any previous log or legacy file
+    fn write_conversation(&self, conversation: &Conversation) -> Result<(), String> {
+        let records = std::iter::once(LogRecord::Meta(ConversationHeader::from_conversation(conversation, true)))
+            .chain(conversation.messages.iter().cloned().map(LogRecord::Message))
+            .chain(conversation.trace.iter().cloned().map(LogRecord::Trace));
+        let lines = encode_log(records)?;
         
-        fs::write(&path, json)
+        fs::write(self.log_path(&conversation.id), lines)
             .map_err(|e| format!("Failed to write file: {}", e))?;
         
+        let legacy_path = self.legacy_path(&conversation.id);
+        if legacy_path.exists() {
+            fs::remove_file(&legacy_path)
+                .map_err(|e| format!("Failed to remove migrated history file: {}", e))?;
+        }
+        
+        self.persisted.lock().insert(conversation.id.clone(), (conversation.messages.len(), conversation.trace.len()));
         Ok(())
     }
 
+    /// Replace a conversation's tags (trimmed, empty and duplicate tags dropped)
+    pub fn tag_conversation(&self, id: &str, tags: Vec<String>) -> Result<ConversationMeta, String> {
+        let mut conversation = self.load_conversation(id)
+            .ok_or_else(|| format!("Conversation {} not found", id))?;
+        
+        let mut cleaned: Vec<String> = Vec::new();
+        for tag in tags {
+            let tag = tag.trim();
+            if !tag.is_empty() && !cleaned.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                cleaned.push(tag.to_string());
+            }
+        }
+        conversation.tags = cleaned;
+        
+        self.write_conversation(&conversation)?;
+        Ok(ConversationMeta::from(&conversation))
+    }
+
+    /// Flip a conversation's favorite flag, returning the new value
+    pub fn toggle_favorite(&self, id: &str) -> Result<bool, String> {
+        let mut conversation = self.load_conversation(id)
+            .ok_or_else(|| format!("Conversation {} not found", id))?;
+        
+        conversation.favorite = !conversation.favorite;
+        self.write_conversation(&conversation)?;
+        Ok(conversation.favorite)
+    }
+
+    /// Delete the conversations that fall outside a retention policy, returning how many
+    /// `keep_id` (the open conversation) is never deleted
+    pub fn apply_retention(&self, policy: &RetentionPolicy, keep_id: Option<&str>) -> usize {
+        if policy.is_unlimited() {
+            return 0;
+        }
+        
+        let cutoff = policy.max_age_days.map(|days| Utc::now() - chrono::Duration::days(days as i64));
+        // Most recent first, so the limit keeps the newest
+        let prunable = self.list_conversations()
+            .into_iter()
+            .filter(|meta| !meta.favorite && meta.tags.is_empty() && !meta.corrupt && Some(meta.id.as_str()) != keep_id);
+        
+        let mut pruned = 0;
+        for (index, meta) in prunable.enumerate() {
+            let over_limit = policy.max_conversations.map_or(false, |max| index >= max);
+            let too_old = cutoff.map_or(false, |cutoff| meta.updated_at < cutoff);
+            if !over_limit && !too_old {
+                continue;
+            }
+            match self.delete_conversation(&meta.id) {
+                Ok(()) => pruned += 1,
+                Err(e) => eprintln!("[History] Failed to prune {}: {}", meta.id, e),
+            }
+        }
+        
+        if pruned > 0 {
+            println!("[History] Pruned {} conversations by retention policy", pruned);
+        }
+        pruned
+    }
+
     /// Delete a conversation
     pub fn delete_conversation(&self, id: &str) -> Result<(), String> {
-        let path = self.history_dir.join(format!("{}.json", id));
-        if path.exists() {
-            fs::remove_file(&path)
-                .map_err(|e| format!("Failed to delete: {}", e))?;
+        for path in [self.log_path(id), self.legacy_path(id)] {
+            if path.exists() {
+                fs::remove_file(&path)
+                    .map_err(|e| format!("Failed to delete: {}", e))?;
+            }
         }
+        self.persisted.lock().remove(id);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_import_markdown_export() {
+        let md = "# Fix the bug\n\n*Created: 2025-03-01 10:20:30.123 UTC*\n\n---\n\n**User**:\n\nWhy does it crash?\n\n---\n\n**Assistant** (27B):\n\nLine one\n---\nLine two\n\n---\n\n";
+        let conversation = import_conversation(md, "markdown").unwrap();
+        
+        assert_eq!(conversation.title, "Fix the bug");
+        assert_eq!(conversation.messages.len(), 2);
+        assert_eq!(conversation.messages[0].role, "user");
+        assert_eq!(conversation.messages[1].content, "Line one\n---\nLine two");
+        assert_eq!(conversation.messages[1].model_tier.as_deref(), Some("27B"));
+    }
+
+    #[test]
+    fn test_import_rejects_malformed_input() {
+        assert!(import_conversation("{not json", "json").is_err());
+        assert!(import_conversation("no header here", "markdown").is_err());
+        assert!(import_conversation("# Empty\n", "markdown").is_err());
+    }
+
+    #[test]
+    fn test_favorite_survives_agent_save() {
+        let dir = std::env::temp_dir().join(format!("zox-history-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let manager = HistoryManager::new(&dir);
+        
+        let mut conversation = Conversation::new("chat");
+        manager.save_conversation(&conversation).unwrap();
+        assert!(manager.toggle_favorite(&conversation.id).unwrap());
+        manager.tag_conversation(&conversation.id, vec!["rust".into(), " Rust ".into(), "".into()]).unwrap();
+        
+        // The agent's in-memory copy has no tags or favorite flag
+        conversation.add_message(Message::new("user", "hello"));
+        manager.save_conversation(&conversation).unwrap();
+        
+        let favorites = manager.list_conversations_filtered(Some("RUST"), true);
+        assert_eq!(favorites.len(), 1);
+        assert_eq!(favorites[0].tags, vec!["rust".to_string()]);
+        assert_eq!(favorites[0].message_count, 1);
+        
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_saves_append_and_rewrite_after_truncation() {
+        let dir = std::env::temp_dir().join(format!("zox-history-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let manager = HistoryManager::new(&dir);
+        
+        let mut conversation = Conversation::new("turbo");
+        conversation.add_message(Message::new("user", "first"));
+        manager.save_conversation(&conversation).unwrap();
+        conversation.add_message(Message::new("model", "second"));
+        manager.save_conversation(&conversation).unwrap();
+        
+        let log = fs::read_to_string(dir.join(format!("{}.jsonl", conversation.id))).unwrap();
+        assert_eq!(log.lines().count(), 4, "one full write of 2 lines plus an appended message and meta");
+        assert_eq!(manager.load_conversation(&conversation.id).unwrap().messages.len(), 2);
+        
+        // Regenerating truncates history, which forces a rewrite
+        conversation.messages.truncate(1);
+        manager.save_conversation(&conversation).unwrap();
+        let loaded = manager.load_conversation(&conversation.id).unwrap();
+        assert_eq!(loaded.messages.len(), 1);
+        assert_eq!(loaded.messages[0].content, "first");
+        
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_legacy_json_is_loaded_and_migrated() {
+        let dir = std::env::temp_dir().join(format!("zox-history-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let manager = HistoryManager::new(&dir);
+        
+        let mut conversation = Conversation::new("chat");
+        conversation.add_message(Message::new("user", "old format"));
+        let legacy = dir.join(format!("{}.json", conversation.id));
+        fs::write(&legacy, serde_json::to_string_pretty(&conversation).unwrap()).unwrap();
+        
+        assert_eq!(manager.list_conversations().len(), 1);
+        let mut loaded = manager.load_conversation(&conversation.id).unwrap();
+        loaded.add_message(Message::new("model", "new format"));
+        manager.save_conversation(&loaded).unwrap();
+        
+        assert!(!legacy.exists());
+        assert_eq!(manager.load_conversation(&conversation.id).unwrap().messages.len(), 2);
+        assert_eq!(manager.list_conversations().len(), 1);
+        
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_truncated_legacy_file_is_listed_and_repaired() {
+        let dir = std::env::temp_dir().join(format!("zox-history-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let manager = HistoryManager::new(&dir);
+        
+        let mut conversation = Conversation::new("turbo");
+        conversation.title = "Half saved".to_string();
+        conversation.add_message(Message::new("user", "keep \"mode\": me"));
+        conversation.add_message(Message::new("model", "and me"));
+        conversation.add_message(Message::new("user", "lost in the crash"));
+        let json = serde_json::to_string_pretty(&conversation).unwrap();
+        let cut = json.find("lost in").unwrap();
+        fs::write(dir.join(format!("{}.json", conversation.id)), &json[..cut]).unwrap();
+        
+        let listed = manager.list_conversations();
+        assert_eq!(listed.len(), 1);
+        assert!(listed[0].corrupt);
+        assert!(manager.list_conversations_filtered(None, true).is_empty());
+        
+        let repaired = manager.repair_conversation(&conversation.id).unwrap();
+        assert!(!repaired.corrupt);
+        assert_eq!(repaired.message_count, 2);
+        assert_eq!(repaired.title, "Half saved");
+        let loaded = manager.load_conversation(&conversation.id).unwrap();
+        assert_eq!(loaded.messages[0].content, "keep \"mode\": me");
+        assert!(dir.join(format!("{}.corrupt", conversation.id)).exists());
+        
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_retention_skips_favorites_and_tags() {
+        let dir = std::env::temp_dir().join(format!("zox-history-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let manager = HistoryManager::new(&dir);
+        
+        // Oldest first: a favorite, a tagged one, then three plain conversations
+        let mut ids = Vec::new();
+        for days_ago in [40, 30, 20, 10, 0] {
+            let mut conversation = Conversation::new("chat");
+            conversation.updated_at = Utc::now() - chrono::Duration::days(days_ago);
+            manager.save_conversation(&conversation).unwrap();
+            ids.push(conversation.id);
+        }
+        manager.toggle_favorite(&ids[0]).unwrap();
+        manager.tag_conversation(&ids[1], vec!["keep".into()]).unwrap();
+        
+        let policy = RetentionPolicy { max_conversations: Some(1), max_age_days: None };
+        assert_eq!(manager.apply_retention(&policy, None), 2);
+        let remaining: Vec<String> = manager.list_conversations().into_iter().map(|m| m.id).collect();
+        assert_eq!(remaining, vec![ids[4].clone(), ids[1].clone(), ids[0].clone()]);
+        
+        // The open conversation survives an age limit that would otherwise remove it
+        let policy = RetentionPolicy { max_conversations: None, max_age_days: Some(0) };
+        assert_eq!(manager.apply_retention(&policy, Some(&ids[4])), 0);
+        assert_eq!(manager.list_conversations().len(), 3);
+        
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_run_state_tracks_latest_save() {
+        let dir = std::env::temp_dir().join(format!("zox-history-{}", Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let manager = HistoryManager::new(&dir);
+        
+        let mut conversation = Conversation::new("turbo");
+        conversation.add_message(Message::new("user", "refactor it"));
+        conversation.run_state = Some(RunState::start());
+        manager.save_conversation(&conversation).unwrap();
+        
+        // An appended step updates the saved state
+        conversation.add_message(Message::new("model", "reading files"));
+        conversation.run_state = Some(RunState { step: 1, last_tool: Some("read_file".into()), ..RunState::start() });
+        manager.save_conversation(&conversation).unwrap();
+        let loaded = manager.load_conversation(&conversation.id).unwrap();
+        assert_eq!(loaded.run_state.as_ref().map(|s| s.step), Some(1));
+        assert!(manager.list_conversations()[0].interrupted);
+        
+        // Finishing the run clears it even though older headers still carry a state
+        conversation.run_state = None;
+        manager.save_conversation(&conversation).unwrap();
+        assert!(manager.load_conversation(&conversation.id).unwrap().run_state.is_none());
+        assert!(!manager.list_conversations()[0].interrupted);
+        
+        fs::remove_dir_all(&dir).ok();
+    }
+}
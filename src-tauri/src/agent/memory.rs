@@ -1,9 +1,127 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::providers::{CompletionMetadata, ModelTier};
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Message {
     pub role: String,
     pub content: String,
+    /// Inline images attached to this message (vision-capable providers only)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub images: Vec<ImageAttachment>,
+    /// How the model response ended and its token usage (model messages only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub completion: Option<CompletionMetadata>,
+    /// When the message was added to the conversation
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<DateTime<Utc>>,
+    /// Model tier that produced the response, e.g. "27B" (model messages only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_tier: Option<String>,
+    /// Tool output fed back to the model - sent as a user message, but local templates give it a tool turn
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub observation: bool,
+}
+
+impl Message {
+    pub fn new(role: impl Into<String>, content: impl Into<String>) -> Self {
+        Self {
+            role: role.into(),
+            content: content.into(),
+            images: Vec::new(),
+            completion: None,
+            timestamp: None,
+            model_tier: None,
+            observation: false,
+        }
+    }
+
+    /// Tool output for the model, stored with the user role
+    pub fn observation(content: impl Into<String>) -> Self {
+        Self { observation: true, ..Self::new("user", content) }
+    }
+
+    /// Attach inline images to the message
+    pub fn with_images(mut self, images: Vec<ImageAttachment>) -> Self {
+        self.images = images;
+        self
+    }
+
+    /// Attach completion metadata to a model message
+    pub fn with_completion(mut self, completion: CompletionMetadata) -> Self {
+        self.completion = Some(completion);
+        self
+    }
+
+    /// Record which model tier produced a model message
+    pub fn with_model_tier(mut self, tier: Option<ModelTier>) -> Self {
+        self.model_tier = tier.map(|t| t.display_name().to_string());
+        self
+    }
+}
+
+/// Base64-encoded inline image
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ImageAttachment {
+    pub mime_type: String,
+    /// Base64-encoded image bytes
+    pub data: String,
+}
+
+/// A file attached to a prompt as inline context
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AttachedFile {
+    pub path: String,
+    pub content: String,
+}
+
+/// Point-in-time view of the context window for debugging
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ContextSnapshot {
+    pub messages: Vec<Message>,
+    /// Cached conversation summary, if one is active
+    pub summary: Option<String>,
+    pub estimated_tokens: usize,
+    pub max_tokens: usize,
+}
+
+/// How much of the context window is in use, emitted after each turn
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ContextBudget {
+    pub used_tokens: usize,
+    pub max_tokens: usize,
+    pub percent: u32,
+}
+
+/// Which messages are sent to the model once a conversation grows
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContextStrategy {
+    /// Everything up to 12 messages, then RAG context plus the last 8
+    #[default]
+    Hybrid,
+    /// The whole context window, for short coherent chats
+    Full,
+    /// Only the last 8 messages and no RAG search, for speed
+    Recent,
+    /// Extra RAG context plus the last 4 messages, for long research sessions
+    Rag,
+}
+
+impl ContextStrategy {
+    /// RAG chunks to search for and recent messages to keep with `message_count` in context
+    /// None means every message is sent as-is
+    pub fn selection(self, message_count: usize) -> Option<(usize, usize)> {
+        let (rag_chunks, recent) = match self {
+            Self::Full => return None,
+            Self::Hybrid if message_count <= 12 => return None,
+            Self::Hybrid => (5, 8),
+            Self::Recent => (0, 8),
+            Self::Rag => (10, 4),
+        };
+        (message_count > recent || rag_chunks > 0).then_some((rag_chunks, recent))
+    }
 }
 
 /// Manages conversation context with automatic pruning
@@ -49,6 +167,14 @@ impl ContextWindow {
         self.estimated_tokens = 0;
     }
 
+    /// Drop every message from `len` onwards and recompute the token estimate
+    pub fn truncate(&mut self, len: usize) {
+        self.history.truncate(len);
+        self.estimated_tokens = self.history.iter()
+            .map(|msg| msg.content.len() / 4 + 10)
+            .sum();
+    }
+
     /// Prune old messages to stay under token limit
     /// Removes messages in pairs to maintain conversation coherence
     fn prune(&mut self) {
@@ -91,6 +217,36 @@ impl ContextWindow {
         self.estimated_tokens
     }
 
+    /// Current token usage against the window limit
+    pub fn budget(&self) -> ContextBudget {
+        let percent = if self.max_tokens == 0 {
+            100
+        } else {
+            (self.estimated_tokens * 100 / self.max_tokens) as u32
+        };
+        ContextBudget {
+            used_tokens: self.estimated_tokens,
+            max_tokens: self.max_tokens,
+            percent,
+        }
+    }
+
+    /// Replace the middle of the history with a summary before pruning can drop it
+    /// Keeps the first message (the original task) and the last `keep_recent` messages
+    pub fn compact(&mut self, summary: &str, keep_recent: usize) {
+        if self.history.len() <= keep_recent + 1 {
+            return;
+        }
+        
+        let recent = self.history.split_off(self.history.len() - keep_recent);
+        self.history.truncate(1);
+        self.history.push(Message::new("user", format!("[Conversation Summary]\n{}\n[End Summary]", summary)));
+        self.history.extend(recent);
+        self.estimated_tokens = self.history.iter()
+            .map(|m| m.content.len() / 4 + 10)
+            .sum();
+    }
+
     /// Check if context has any messages
     pub fn is_empty(&self) -> bool {
         self.history.is_empty()
@@ -106,6 +262,16 @@ impl ContextWindow {
         self.history.last()
     }
 
+    /// Snapshot the current history and token estimate
+    pub fn snapshot(&self, summary: Option<String>) -> ContextSnapshot {
+        ContextSnapshot {
+            messages: self.history.clone(),
+            summary,
+            estimated_tokens: self.estimated_tokens,
+            max_tokens: self.max_tokens,
+        }
+    }
+
     /// Serialize history to JSON for storage
     pub fn to_json(&self) -> Result<String, serde_json::Error> {
         serde_json::to_string(&self.history)
@@ -129,14 +295,8 @@ mod tests {
     #[test]
     fn test_add_and_get() {
         let mut ctx = ContextWindow::new(10000);
-        ctx.add_message(Message {
-            role: "user".into(),
-            content: "Hello".into(),
-        });
-        ctx.add_message(Message {
-            role: "model".into(),
-            content: "Hi there!".into(),
-        });
+        ctx.add_message(Message::new("user", "Hello"));
+        ctx.add_message(Message::new("model", "Hi there!"));
         
         assert_eq!(ctx.len(), 2);
         assert_eq!(ctx.get_history()[0].role, "user");
@@ -148,21 +308,61 @@ mod tests {
         
         // Add messages that exceed the limit
         for i in 0..20 {
-            ctx.add_message(Message {
-                role: "user".into(),
-                content: format!("Message {} with some longer content to exceed tokens", i),
-            });
+            ctx.add_message(Message::new("user", format!("Message {} with some longer content to exceed tokens", i)));
         }
         
         // Should have pruned some messages
         assert!(ctx.len() < 20);
     }
 
+    #[test]
+    fn test_truncate() {
+        let mut ctx = ContextWindow::new(10000);
+        ctx.add_message(Message::new("user", "Hello"));
+        ctx.add_message(Message::new("model", "Hi there!"));
+        let tokens_after_first = Message::new("user", "Hello").content.len() / 4 + 10;
+        
+        ctx.truncate(1);
+        
+        assert_eq!(ctx.len(), 1);
+        assert_eq!(ctx.token_count(), tokens_after_first);
+    }
+
+    #[test]
+    fn test_budget_and_compact() {
+        let mut ctx = ContextWindow::new(1000);
+        ctx.add_message(Message::new("user", "Original task"));
+        for i in 0..10 {
+            ctx.add_message(Message::new("model", format!("Step {} {}", i, "x".repeat(200))));
+        }
+        let before = ctx.budget();
+        assert_eq!(before.used_tokens, ctx.token_count());
+        assert_eq!(before.percent, (ctx.token_count() * 100 / 1000) as u32);
+        
+        ctx.compact("Did ten steps", 3);
+        
+        assert_eq!(ctx.len(), 5);
+        assert_eq!(ctx.get_history()[0].content, "Original task");
+        assert!(ctx.get_history()[1].content.contains("Did ten steps"));
+        assert!(ctx.get_history()[4].content.starts_with("Step 9"));
+        assert!(ctx.budget().used_tokens < before.used_tokens);
+    }
+
     #[test]
     fn test_clear() {
         let mut ctx = ContextWindow::new(10000);
-        ctx.add_message(Message { role: "user".into(), content: "test".into() });
+        ctx.add_message(Message::new("user", "test"));
         ctx.clear();
         assert!(ctx.is_empty());
     }
+
+    #[test]
+    fn test_context_strategy_selection() {
+        assert_eq!(ContextStrategy::Hybrid.selection(12), None);
+        assert_eq!(ContextStrategy::Hybrid.selection(13), Some((5, 8)));
+        assert_eq!(ContextStrategy::Full.selection(100), None);
+        assert_eq!(ContextStrategy::Recent.selection(8), None);
+        assert_eq!(ContextStrategy::Recent.selection(20), Some((0, 8)));
+        assert_eq!(ContextStrategy::Rag.selection(3), Some((10, 4)));
+    }
 }
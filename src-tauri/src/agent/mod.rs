@@ -1,9 +1,14 @@
 pub mod actor;
+pub mod events;
 pub mod parser;
+pub mod react;
 pub mod memory;
 pub mod rag;
 pub mod summarizer;
+pub mod syntax_check;
 pub mod tools;
+#[cfg(test)]
+mod tools_test;
 pub mod workspace;
 pub mod history;
 
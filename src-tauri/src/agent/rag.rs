@@ -2,11 +2,21 @@
 //! 
 //! Simplified in-memory implementation with semantic search.
 //! Uses fastembed for embeddings and cosine similarity for search.
+//! The chunks are loaded from `index.json` in the RAG directory and saved back with `save`.
 
-use std::path::Path;
-use parking_lot::Mutex;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use once_cell::sync::Lazy;
+use parking_lot::{Condvar, Mutex, RwLock};
 use serde::{Deserialize, Serialize};
 use fastembed::TextEmbedding;
+use ignore::WalkBuilder;
+
+use crate::agent::tools::ALWAYS_HIDDEN_DIRS;
+use crate::agent::workspace::{root_name, SensitiveMatcher};
 
 /// A stored context chunk with embedding
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,18 +29,108 @@ pub struct ContextChunk {
     pub timestamp: i64,
 }
 
+/// Summary of what the semantic index holds
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RagStats {
+    pub chunk_count: usize,
+    /// Chunk counts keyed by type ("message", "tool_result", "file")
+    pub by_type: HashMap<String, usize>,
+    /// Unix timestamp of the oldest stored chunk
+    pub oldest_timestamp: Option<i64>,
+    /// Rough size of the chunks, embeddings and dedup hashes in memory
+    pub approximate_memory_bytes: usize,
+    pub skipped_duplicates: usize,
+}
+
+/// File in the RAG directory holding the saved chunks
+const INDEX_FILE: &str = "index.json";
+
+/// Files larger than this are skipped when indexing the workspace
+const MAX_INDEXED_FILE_BYTES: u64 = 256 * 1024;
+
+/// Outcome of a workspace re-index
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReindexSummary {
+    pub files: usize,
+    pub chunks: usize,
+    /// File chunks dropped before the walk
+    pub removed_chunks: usize,
+}
+
 /// In-memory context store
 struct ContextStore {
     chunks: Vec<ContextChunk>,
+    /// Content hashes of stored chunks for deduplication
+    content_hashes: HashSet<u64>,
+    /// Number of duplicate chunks skipped
+    skipped_duplicates: usize,
 }
 
 impl ContextStore {
     fn new() -> Self {
-        Self { chunks: Vec::new() }
+        Self {
+            chunks: Vec::new(),
+            content_hashes: HashSet::new(),
+            skipped_duplicates: 0,
+        }
+    }
+    
+    /// Store of previously saved chunks
+    fn from_chunks(chunks: Vec<ContextChunk>) -> Self {
+        let mut store = Self::new();
+        for chunk in chunks {
+            store.add(chunk);
+        }
+        store.skipped_duplicates = 0;
+        store
     }
     
-    fn add(&mut self, chunk: ContextChunk) {
+    /// Check whether identical content is already stored
+    fn contains(&self, hash: u64) -> bool {
+        self.content_hashes.contains(&hash)
+    }
+    
+    /// Add a chunk unless identical content is already stored
+    /// Returns false if the chunk was a duplicate
+    fn add(&mut self, chunk: ContextChunk) -> bool {
+        let hash = content_hash(&chunk.content);
+        if !self.content_hashes.insert(hash) {
+            self.skipped_duplicates += 1;
+            return false;
+        }
         self.chunks.push(chunk);
+        true
+    }
+    
+    /// Drop every chunk of one type, forgetting their hashes so the content can be stored again
+    /// Returns the number of chunks removed
+    fn remove_type(&mut self, chunk_type: &str) -> usize {
+        let before = self.chunks.len();
+        self.chunks.retain(|chunk| chunk.chunk_type != chunk_type);
+        self.content_hashes = self.chunks.iter().map(|chunk| content_hash(&chunk.content)).collect();
+        before - self.chunks.len()
+    }
+    
+    fn stats(&self) -> RagStats {
+        let mut stats = RagStats {
+            chunk_count: self.chunks.len(),
+            skipped_duplicates: self.skipped_duplicates,
+            approximate_memory_bytes: self.content_hashes.len() * std::mem::size_of::<u64>(),
+            ..Default::default()
+        };
+        
+        for chunk in &self.chunks {
+            *stats.by_type.entry(chunk.chunk_type.clone()).or_insert(0) += 1;
+            stats.oldest_timestamp = Some(stats.oldest_timestamp.map_or(chunk.timestamp, |t| t.min(chunk.timestamp)));
+            stats.approximate_memory_bytes += std::mem::size_of::<ContextChunk>()
+                + chunk.id.len()
+                + chunk.content.len()
+                + chunk.embedding.len() * std::mem::size_of::<f32>()
+                + chunk.chunk_type.len()
+                + chunk.source.len();
+        }
+        
+        stats
     }
     
     fn search(&self, query_embedding: &[f32], limit: usize) -> Vec<&ContextChunk> {
@@ -54,6 +154,13 @@ impl ContextStore {
     }
 }
 
+/// Hash of normalized chunk content (whitespace-trimmed)
+fn content_hash(content: &str) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    content.trim().hash(&mut hasher);
+    hasher.finish()
+}
+
 /// Cosine similarity between two vectors
 fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     if a.len() != b.len() || a.is_empty() {
@@ -71,27 +178,157 @@ fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     dot / (norm_a * norm_b)
 }
 
+/// Maximum number of embeddings kept in the cache
+const EMBED_CACHE_CAPACITY: usize = 512;
+
+/// Least-recently-used cache of embeddings keyed by a hash of the exact input text
+struct EmbeddingCache {
+    entries: HashMap<u64, Vec<f32>>,
+    /// Keys from least to most recently used
+    order: VecDeque<u64>,
+    capacity: usize,
+}
+
+impl EmbeddingCache {
+    fn new(capacity: usize) -> Self {
+        Self { entries: HashMap::new(), order: VecDeque::new(), capacity }
+    }
+    
+    fn get(&mut self, key: u64) -> Option<Vec<f32>> {
+        let embedding = self.entries.get(&key)?.clone();
+        self.touch(key);
+        Some(embedding)
+    }
+    
+    fn insert(&mut self, key: u64, embedding: Vec<f32>) {
+        if self.entries.insert(key, embedding).is_some() {
+            self.touch(key);
+            return;
+        }
+        self.order.push_back(key);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+    }
+    
+    /// Mark a key as most recently used
+    fn touch(&mut self, key: u64) {
+        if let Some(pos) = self.order.iter().position(|k| *k == key) {
+            self.order.remove(pos);
+        }
+        self.order.push_back(key);
+    }
+}
+
+/// Hash of the exact text passed to the embedder
+fn text_hash(text: &str) -> u64 {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Return the cached embedding for a text, computing and caching it on a miss
+fn embed_cached(
+    cache: &Mutex<EmbeddingCache>,
+    text: &str,
+    compute: impl FnOnce(&str) -> Result<Vec<f32>, String>,
+) -> Result<Vec<f32>, String> {
+    let key = text_hash(text);
+    if let Some(embedding) = cache.lock().get(key) {
+        return Ok(embedding);
+    }
+    
+    // The lock is not held while embedding, so a concurrent miss may compute twice
+    let embedding = compute(text)?;
+    cache.lock().insert(key, embedding.clone());
+    Ok(embedding)
+}
+
+/// Progress of the embedder initialization (the model is downloaded on first use)
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum RagInitStatus {
+    #[default]
+    NotStarted,
+    /// `started_at` is a unix timestamp
+    Initializing { started_at: i64 },
+    Ready,
+    Failed { message: String },
+    /// Given up by the user - RAG stays off for the rest of the session
+    Cancelled,
+}
+
+/// Init status, kept outside the actor so it can be read while a task is running
+static INIT_STATUS: Lazy<RwLock<RagInitStatus>> = Lazy::new(|| RwLock::new(RagInitStatus::NotStarted));
+
+/// Current state of the embedder initialization
+pub fn init_status() -> RagInitStatus {
+    INIT_STATUS.read().clone()
+}
+
+/// Give up on an initialization that is still running
+/// The embedder can't be interrupted mid-download, so it finishes in the background
+/// and its result is dropped. Returns false if nothing was initializing
+pub fn cancel_init() -> bool {
+    let mut status = INIT_STATUS.write();
+    if !matches!(*status, RagInitStatus::Initializing { .. }) {
+        return false;
+    }
+    println!("[RAG] Initialization cancelled");
+    *status = RagInitStatus::Cancelled;
+    true
+}
+
+/// Start creating a context manager on a blocking thread
+/// The embedder setup blocks (and may download the model), so it must not run on the actor's task
+pub fn spawn_init(data_dir: PathBuf) -> tokio::task::JoinHandle<Result<ContextManager, String>> {
+    *INIT_STATUS.write() = RagInitStatus::Initializing { started_at: chrono::Utc::now().timestamp() };
+    
+    tokio::task::spawn_blocking(move || {
+        let result = futures::executor::block_on(ContextManager::new(&data_dir));
+        let mut status = INIT_STATUS.write();
+        if *status != RagInitStatus::Cancelled {
+            *status = match &result {
+                Ok(_) => RagInitStatus::Ready,
+                Err(e) => RagInitStatus::Failed { message: e.clone() },
+            };
+        }
+        result
+    })
+}
+
 /// RAG Context Manager with in-memory storage
 pub struct ContextManager {
+    /// Directory the index is saved to
+    data_dir: PathBuf,
     store: Mutex<ContextStore>,
     embedder: Mutex<TextEmbedding>,
+    embed_cache: Mutex<EmbeddingCache>,
+    /// Set while a workspace re-index is running
+    reindexing: AtomicBool,
     initialized: bool,
 }
 
 impl ContextManager {
     /// Initialize the RAG context manager
-    pub async fn new(_data_dir: &Path) -> Result<Self, String> {
+    pub async fn new(data_dir: &Path) -> Result<Self, String> {
         println!("[RAG] Initializing embedder...");
         
         // Initialize embedding model (all-MiniLM-L6-v2)
         let embedder = TextEmbedding::try_new(Default::default())
             .map_err(|e| format!("Failed to initialize embedder: {}", e))?;
         
-        println!("[RAG] Context manager initialized (in-memory)");
+        let store = load_index(data_dir);
+        println!("[RAG] Context manager initialized ({} saved chunks)", store.chunks.len());
         
         Ok(Self {
-            store: Mutex::new(ContextStore::new()),
+            data_dir: data_dir.to_path_buf(),
+            store: Mutex::new(store),
             embedder: Mutex::new(embedder),
+            embed_cache: Mutex::new(EmbeddingCache::new(EMBED_CACHE_CAPACITY)),
+            reindexing: AtomicBool::new(false),
             initialized: true,
         })
     }
@@ -101,12 +338,31 @@ impl ContextManager {
         self.initialized
     }
     
+    /// Counts and approximate size of the indexed chunks
+    pub fn stats(&self) -> RagStats {
+        self.store.lock().stats()
+    }
+    
+    /// Write the chunks to the RAG directory, e.g. before the directory is backed up
+    pub fn save(&self) -> Result<(), String> {
+        let json = serde_json::to_vec(&self.store.lock().chunks)
+            .map_err(|e| format!("Failed to serialize RAG index: {}", e))?;
+        std::fs::write(self.data_dir.join(INDEX_FILE), json)
+            .map_err(|e| format!("Failed to save RAG index: {}", e))
+    }
+    
     /// Embed text using the embedding model
+    /// Repeated inputs (similar searches, re-indexing) are served from the cache
     fn embed(&self, text: &str) -> Result<Vec<f32>, String> {
         if text.trim().is_empty() {
             return Ok(vec![]);
         }
         
+        embed_cached(&self.embed_cache, text, |text| self.embed_uncached(text))
+    }
+    
+    /// Run the embedding model on a single text
+    fn embed_uncached(&self, text: &str) -> Result<Vec<f32>, String> {
         let mut embedder = self.embedder.lock();
         let embeddings = embedder.embed(vec![text.to_string()], None)
             .map_err(|e| format!("Embedding failed: {}", e))?;
@@ -124,6 +380,17 @@ impl ContextManager {
         content: &str,
         chunk_type: &str,
         source: &str,
+    ) -> Result<(), String> {
+        self.store_chunk_blocking(content, chunk_type, source)
+    }
+    
+    /// Store a chunk, embedding on the calling thread
+    /// Used by the background indexer so embedding never runs on the actor
+    fn store_chunk_blocking(
+        &self,
+        content: &str,
+        chunk_type: &str,
+        source: &str,
     ) -> Result<(), String> {
         if content.trim().is_empty() || content.len() < 10 {
             return Ok(()); // Skip trivial content
@@ -132,6 +399,17 @@ impl ContextManager {
         // Truncate for embedding (models have limits)
         let truncated: String = content.chars().take(512).collect();
         
+        // Skip duplicates before paying for an embedding
+        {
+            let mut store = self.store.lock();
+            if store.contains(content_hash(&truncated)) {
+                store.skipped_duplicates += 1;
+                println!("[RAG] Skipped duplicate {} chunk ({} duplicates skipped)", 
+                    chunk_type, store.skipped_duplicates);
+                return Ok(());
+            }
+        }
+        
         // Generate embedding
         let embedding = self.embed(&truncated)?;
         if embedding.is_empty() {
@@ -149,7 +427,11 @@ impl ContextManager {
         
         // Store in memory
         let mut store = self.store.lock();
-        store.add(chunk);
+        if !store.add(chunk) {
+            println!("[RAG] Skipped duplicate {} chunk ({} duplicates skipped)", 
+                chunk_type, store.skipped_duplicates);
+            return Ok(());
+        }
         
         println!("[RAG] Stored {} chunk ({} chars, {} total)", 
             chunk_type, content.len(), store.chunks.len());
@@ -209,6 +491,46 @@ impl ContextManager {
         Ok(contents)
     }
     
+    /// Replace all "file" chunks with a fresh walk of the workspace roots
+    /// Message and tool result chunks are kept. Blocks while embedding every chunk, so run
+    /// it on its own thread; `progress` is called with (files done, total files)
+    pub fn reindex_files(
+        &self,
+        roots: &[PathBuf],
+        chunk_chars: usize,
+        progress: impl Fn(usize, usize),
+    ) -> Result<ReindexSummary, String> {
+        if self.reindexing.swap(true, Ordering::SeqCst) {
+            return Err("Workspace re-index already running".to_string());
+        }
+        
+        let patterns = crate::state::settings::get().sensitive_patterns;
+        let files = collect_index_files(roots, &patterns);
+        let mut summary = ReindexSummary {
+            removed_chunks: self.store.lock().remove_type("file"),
+            ..Default::default()
+        };
+        println!("[RAG] Re-indexing {} files ({} old file chunks removed)", files.len(), summary.removed_chunks);
+        
+        for (done, (source, path)) in files.iter().enumerate() {
+            // Binary and non-UTF-8 files fail to read and are skipped
+            if let Ok(content) = std::fs::read_to_string(path) {
+                let chars: Vec<char> = content.chars().collect();
+                for chunk in chars.chunks(chunk_chars.max(1)) {
+                    match self.store_chunk_blocking(&chunk.iter().collect::<String>(), "file", source) {
+                        Ok(()) => summary.chunks += 1,
+                        Err(e) => println!("[RAG] Failed to index {}: {}", source, e),
+                    }
+                }
+                summary.files += 1;
+            }
+            progress(done + 1, files.len());
+        }
+        
+        self.reindexing.store(false, Ordering::SeqCst);
+        Ok(summary)
+    }
+    
     /// Get summarized context for a query
     pub async fn get_relevant_context(
         &self,
@@ -225,6 +547,188 @@ impl ContextManager {
     }
 }
 
+/// Files under the roots worth indexing, as (source label, path)
+/// Respects .gitignore and skips dependency/build directories, large files and anything
+/// matching the sensitive patterns, which the agent's tools cannot read either
+fn collect_index_files(roots: &[PathBuf], sensitive_patterns: &[String]) -> Vec<(String, PathBuf)> {
+    let mut files = Vec::new();
+    
+    for root in roots {
+        let sensitive = SensitiveMatcher::with_patterns(root, sensitive_patterns);
+        let walker = WalkBuilder::new(root)
+            .git_ignore(true)
+            .require_git(false)
+            .filter_entry({
+                let sensitive = sensitive.clone();
+                move |entry| {
+                    let is_dir = entry.file_type().map_or(false, |t| t.is_dir());
+                    !(is_dir && ALWAYS_HIDDEN_DIRS.contains(&entry.file_name().to_string_lossy().as_ref()))
+                        && !sensitive.matches(entry.path(), is_dir)
+                }
+            })
+            .build();
+        
+        for entry in walker.flatten() {
+            if !entry.file_type().map_or(false, |t| t.is_file()) {
+                continue;
+            }
+            if sensitive.matches(entry.path(), false) {
+                continue;
+            }
+            if entry.metadata().map_or(true, |m| m.len() > MAX_INDEXED_FILE_BYTES) {
+                continue;
+            }
+            
+            // Label with the root name when there are several, matching root-prefixed tool paths
+            let relative = entry.path().strip_prefix(root).unwrap_or(entry.path());
+            let source = if roots.len() > 1 {
+                Path::new(&root_name(root)).join(relative).display().to_string()
+            } else {
+                relative.display().to_string()
+            };
+            files.push((source, entry.path().to_path_buf()));
+        }
+    }
+    
+    files
+}
+
+/// Maximum number of chunks waiting to be embedded
+const MAX_PENDING_INDEX_JOBS: usize = 64;
+
+/// A chunk waiting to be embedded and stored
+#[derive(Debug, Clone)]
+pub struct IndexJob {
+    pub content: String,
+    pub chunk_type: &'static str,
+    pub source: String,
+}
+
+impl IndexJob {
+    pub fn message(conversation_id: &str, role: &str, content: &str) -> Self {
+        Self {
+            content: content.to_string(),
+            chunk_type: "message",
+            source: format!("{}:{}", conversation_id, role),
+        }
+    }
+    
+    pub fn file(path: &str, content: &str) -> Self {
+        Self {
+            content: content.to_string(),
+            chunk_type: "file",
+            source: path.to_string(),
+        }
+    }
+}
+
+/// Bounded queue of pending index jobs - drops the oldest job when full
+struct PendingJobs {
+    jobs: VecDeque<IndexJob>,
+    capacity: usize,
+    dropped: usize,
+    closed: bool,
+}
+
+impl PendingJobs {
+    fn new(capacity: usize) -> Self {
+        Self { jobs: VecDeque::new(), capacity, dropped: 0, closed: false }
+    }
+    
+    /// Queue a job, returning true if an older job was dropped to make room
+    fn push(&mut self, job: IndexJob) -> bool {
+        let dropped = if self.jobs.len() >= self.capacity {
+            self.jobs.pop_front();
+            self.dropped += 1;
+            true
+        } else {
+            false
+        };
+        self.jobs.push_back(job);
+        dropped
+    }
+}
+
+/// Background indexer that embeds and stores chunks off the actor's hot path
+/// The worker thread stops when the indexer is dropped
+pub struct RagIndexer {
+    pending: Arc<(Mutex<PendingJobs>, Condvar)>,
+    worker: Option<std::thread::JoinHandle<()>>,
+}
+
+impl RagIndexer {
+    /// Start the worker thread for a context manager
+    pub fn spawn(manager: Arc<ContextManager>) -> Self {
+        let pending = Arc::new((Mutex::new(PendingJobs::new(MAX_PENDING_INDEX_JOBS)), Condvar::new()));
+        let worker_pending = pending.clone();
+        
+        let worker = std::thread::spawn(move || {
+            let (lock, condvar) = &*worker_pending;
+            loop {
+                let job = {
+                    let mut queue = lock.lock();
+                    while queue.jobs.is_empty() && !queue.closed {
+                        condvar.wait(&mut queue);
+                    }
+                    match queue.jobs.pop_front() {
+                        Some(job) => job,
+                        None => break, // Closed and drained
+                    }
+                };
+                
+                if let Err(e) = manager.store_chunk_blocking(&job.content, job.chunk_type, &job.source) {
+                    println!("[RAG] Background indexing failed: {}", e);
+                }
+            }
+            println!("[RAG] Indexer stopped");
+        });
+        
+        Self { pending, worker: Some(worker) }
+    }
+    
+    /// Stop taking jobs and block until the queued ones are stored
+    pub fn finish(mut self) {
+        let worker = self.worker.take();
+        drop(self);
+        if let Some(worker) = worker {
+            let _ = worker.join();
+        }
+    }
+    
+    /// Queue a chunk for indexing without waiting for the embedding
+    pub fn enqueue(&self, job: IndexJob) {
+        let (lock, condvar) = &*self.pending;
+        let mut queue = lock.lock();
+        if queue.push(job) {
+            println!("[RAG] Index queue full, dropped oldest job ({} dropped)", queue.dropped);
+        }
+        condvar.notify_one();
+    }
+}
+
+impl Drop for RagIndexer {
+    fn drop(&mut self) {
+        let (lock, condvar) = &*self.pending;
+        lock.lock().closed = true;
+        condvar.notify_all();
+    }
+}
+
+/// Chunks saved in `data_dir`, or an empty store if there are none or they can't be read
+fn load_index(data_dir: &Path) -> ContextStore {
+    let path = data_dir.join(INDEX_FILE);
+    let Ok(bytes) = std::fs::read(&path) else {
+        return ContextStore::new();
+    };
+    match serde_json::from_slice::<Vec<ContextChunk>>(&bytes) {
+        Ok(chunks) => ContextStore::from_chunks(chunks),
+        Err(e) => {
+            println!("[RAG] Ignoring unreadable index {}: {}", path.display(), e);
+            ContextStore::new()
+        }
+    }
+}
+
 /// Lazy-initialized global RAG manager
 pub struct RagManager {
     context_manager: Option<ContextManager>,
@@ -267,3 +771,154 @@ impl Default for RagManager {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(content: &str) -> ContextChunk {
+        ContextChunk {
+            id: uuid::Uuid::new_v4().to_string(),
+            content: content.to_string(),
+            embedding: vec![1.0, 0.0],
+            chunk_type: "message".to_string(),
+            source: "test".to_string(),
+            timestamp: 0,
+        }
+    }
+
+    #[test]
+    fn test_duplicate_chunks_are_skipped() {
+        let mut store = ContextStore::new();
+        
+        assert!(store.add(chunk("fn main() { println!(\"hello\"); }")));
+        assert!(!store.add(chunk("fn main() { println!(\"hello\"); }")));
+        
+        assert_eq!(store.chunks.len(), 1);
+        assert_eq!(store.skipped_duplicates, 1);
+    }
+
+    #[test]
+    fn test_whitespace_only_difference_is_duplicate() {
+        let mut store = ContextStore::new();
+        
+        assert!(store.add(chunk("same content here")));
+        assert!(!store.add(chunk("  same content here\n")));
+        assert!(store.add(chunk("different content here")));
+        
+        assert_eq!(store.chunks.len(), 2);
+    }
+
+    #[test]
+    fn test_stats_count_chunks_by_type() {
+        let mut store = ContextStore::new();
+        store.add(chunk("first message chunk"));
+        let mut file_chunk = chunk("fn indexed_file() {}");
+        file_chunk.chunk_type = "file".to_string();
+        file_chunk.timestamp = -5;
+        store.add(file_chunk);
+        store.add(chunk("first message chunk"));
+        
+        let stats = store.stats();
+        assert_eq!(stats.chunk_count, 2);
+        assert_eq!(stats.by_type.get("message"), Some(&1));
+        assert_eq!(stats.by_type.get("file"), Some(&1));
+        assert_eq!(stats.oldest_timestamp, Some(-5));
+        assert_eq!(stats.skipped_duplicates, 1);
+        assert!(stats.approximate_memory_bytes > 0);
+    }
+
+    #[test]
+    fn test_remove_type_keeps_other_chunks() {
+        let mut store = ContextStore::new();
+        let mut file_chunk = chunk("fn indexed_file() {}");
+        file_chunk.chunk_type = "file".to_string();
+        store.add(chunk("user asked about indexing"));
+        store.add(file_chunk.clone());
+        
+        assert_eq!(store.remove_type("file"), 1);
+        assert_eq!(store.chunks.len(), 1);
+        assert_eq!(store.chunks[0].chunk_type, "message");
+        
+        // Removed content is no longer treated as a duplicate
+        assert!(store.add(file_chunk));
+    }
+
+    #[test]
+    fn test_pending_jobs_drop_oldest_when_full() {
+        let mut pending = PendingJobs::new(2);
+        
+        assert!(!pending.push(IndexJob::message("c", "user", "first")));
+        assert!(!pending.push(IndexJob::message("c", "user", "second")));
+        assert!(pending.push(IndexJob::message("c", "user", "third")));
+        
+        let contents: Vec<&str> = pending.jobs.iter().map(|j| j.content.as_str()).collect();
+        assert_eq!(contents, vec!["second", "third"]);
+        assert_eq!(pending.dropped, 1);
+    }
+
+    #[test]
+    fn test_identical_inputs_embed_once() {
+        let cache = Mutex::new(EmbeddingCache::new(8));
+        let calls = std::cell::Cell::new(0);
+        let compute = |_: &str| {
+            calls.set(calls.get() + 1);
+            Ok(vec![0.5, 0.5])
+        };
+        
+        assert_eq!(embed_cached(&cache, "how is auth handled?", compute).unwrap(), vec![0.5, 0.5]);
+        assert_eq!(embed_cached(&cache, "how is auth handled?", compute).unwrap(), vec![0.5, 0.5]);
+        assert_eq!(calls.get(), 1);
+        
+        embed_cached(&cache, "a different query", compute).unwrap();
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn test_embedding_cache_evicts_least_recently_used() {
+        let mut cache = EmbeddingCache::new(2);
+        cache.insert(1, vec![1.0]);
+        cache.insert(2, vec![2.0]);
+        assert!(cache.get(1).is_some());
+        cache.insert(3, vec![3.0]);
+        
+        assert!(cache.get(2).is_none());
+        assert!(cache.get(1).is_some());
+        assert!(cache.get(3).is_some());
+        assert_eq!(cache.entries.len(), 2);
+    }
+
+    #[test]
+    fn test_saved_index_loads_back() {
+        let dir = std::env::temp_dir().join(format!("zox-rag-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert_eq!(load_index(&dir).chunks.len(), 0);
+        
+        let chunks = vec![chunk("fn saved() {}"), chunk("fn also_saved() {}")];
+        std::fs::write(dir.join(INDEX_FILE), serde_json::to_vec(&chunks).unwrap()).unwrap();
+        let store = load_index(&dir);
+        assert_eq!(store.chunks.len(), 2);
+        assert!(store.contains(content_hash("fn saved() {}")));
+        
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_reindex_skips_sensitive_files() {
+        let root = std::env::temp_dir().join(format!("zox-rag-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("secrets")).unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {}").unwrap();
+        std::fs::write(root.join(".env"), "API_KEY=secret").unwrap();
+        std::fs::write(root.join("secrets/token.txt"), "secret").unwrap();
+        
+        let patterns = vec![".env".to_string(), "secrets/".to_string()];
+        let sources: Vec<String> = collect_index_files(&[root.clone()], &patterns)
+            .into_iter()
+            .map(|(source, _)| source.replace('\\', "/"))
+            .collect();
+        assert_eq!(sources, vec!["src/main.rs".to_string()]);
+        
+        std::fs::remove_dir_all(&root).ok();
+    }
+}
@@ -0,0 +1,616 @@
+//! ReAct Loop
+//!
+//! The turbo-mode loop: stream a response, run the tool calls it contains, feed the
+//! observations back and repeat until the model answers in text. Nothing here touches
+//! Tauri - the actor supplies events, persistence, approval and tool execution
+//! through `ReactHost`, so tests can drive the loop with a scripted provider.
+
+use std::time::Duration;
+use async_trait::async_trait;
+use serde_json::Value;
+
+use crate::agent::events::{AgentStatus, StreamThrottle};
+use crate::agent::history::TraceStep;
+use crate::agent::memory::Message;
+use crate::agent::parser::{clean_for_display, extract_thinking, format_observation, format_untrusted_observation, ParsedResponse, StreamEvent, StreamingParser, ToolCallData};
+use crate::agent::tools::{checked_args, get_all_tools, get_tool_by_name, unknown_tool_message};
+use crate::error::ZoxError;
+use crate::providers::{CompletionMetadata, CompletionResult, ModelProvider};
+
+/// Sent back to the model when a turbo reply has neither a tool call nor a <message>
+const TOOL_FORMAT_NUDGE: &str = "<observation>Your last reply contained no tool call. If the task is not finished, call a tool using <tool>name</tool> followed by <params>...</params>. If it is finished, wrap your final answer in <message></message>.</observation>";
+
+/// Shown in place of a blank bubble when the stream ends without any text
+pub const EMPTY_RESPONSE_MESSAGE: &str = "No response generated — the request may have been blocked";
+
+/// Consecutive denied tool calls before the run is stopped
+const MAX_CONSECUTIVE_DENIALS: usize = 3;
+
+/// Extra attempts after a retryable provider failure, on top of the client's own retries
+const PROVIDER_RETRIES: u32 = 1;
+
+/// Pause before retrying a provider request
+const PROVIDER_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Side effects the loop needs from whoever runs it
+#[async_trait]
+pub trait ReactHost: Send + Sync {
+    /// Provider that generates each step
+    fn provider(&self) -> &dyn ModelProvider;
+    
+    /// Queue an event for the frontend
+    fn emit(&self, name: &'static str, payload: Value);
+    
+    /// Report what the agent is doing as a structured `agent-status` event
+    fn emit_status(&self, status: AgentStatus) {
+        match serde_json::to_value(&status) {
+            Ok(payload) => self.emit("agent-status", payload),
+            Err(e) => eprintln!("[ReAct] Failed to serialize status {:?}: {}", status, e),
+        }
+    }
+    
+    /// Whether the user cancelled the run
+    fn is_cancelled(&self) -> bool;
+    
+    /// Messages to send to the model for the next step
+    async fn messages(&mut self) -> Vec<Message>;
+    
+    /// Add a message to the context window and the saved conversation
+    fn persist_message(&mut self, message: Message);
+    
+    /// Add an observation to the context window only
+    async fn store_observation(&mut self, content: String);
+    
+    /// Record a step in the conversation trace
+    fn record_trace(&mut self, step: TraceStep);
+    
+    /// Report why a completion ended and its token usage
+    fn report_completion(&mut self, completion: &CompletionMetadata);
+    
+    /// Ask the user to approve a tool call
+    async fn approve(&self, tool: &str, params: &Value) -> bool;
+    
+    /// Execute a known tool and return its output
+    async fn run_tool(&self, tool: &str, params: &Value) -> String;
+}
+
+/// Settings for a single run
+pub struct ReactConfig {
+    pub system_prompt: String,
+    pub max_steps: usize,
+    /// Times a reply with no tool call and no <message> is re-prompted with the tool format
+    pub max_nudges: u32,
+    /// Wrap tool output as untrusted data and mark instruction-like phrases in it
+    pub guard_tool_output: bool,
+}
+
+/// Outcome of running one tool call
+enum CallResult {
+    Output(String),
+    Denied,
+}
+
+/// Run the loop until the model answers, returning how the run ended:
+/// "complete", "empty", "max_steps", "denied_loop", "error" or "cancelled"
+/// The user's prompt must already be in the host's context
+pub async fn run_react_loop<H: ReactHost>(host: &mut H, config: &ReactConfig) -> &'static str {
+    let mut current_step = 0;
+    let mut consecutive_denials = 0;
+    let mut nudges_sent = 0;
+    
+    while current_step < config.max_steps && !host.is_cancelled() {
+        current_step += 1;
+        println!("[ReAct] Step {}/{}", current_step, config.max_steps);
+        
+        let tools = get_all_tools();
+        println!("[ReAct] Available tools: {}", tools.iter().map(|t| t.name.as_str()).collect::<Vec<_>>().join(", "));
+        
+        let messages = host.messages().await;
+        
+        let (full_response_text, final_parsed, completion) = match stream_step(host, &config.system_prompt, &messages).await {
+            Ok(step) => step,
+            Err(()) if host.is_cancelled() => {
+                finish_cancelled(host, "", CompletionMetadata::default());
+                return "cancelled";
+            }
+            Err(()) => return "error",
+        };
+        
+        if host.is_cancelled() {
+            finish_cancelled(host, &full_response_text, completion);
+            return "cancelled";
+        }
+        
+        if clean_for_display(&full_response_text).trim().is_empty() {
+            finish_empty_response(host, &completion);
+            return "empty";
+        }
+        
+        let step_thinking = extract_thinking(&full_response_text);
+        
+        host.report_completion(&completion);
+        let tier = host.provider().active_model();
+        host.persist_message(Message::new("model", full_response_text.as_str()).with_completion(completion).with_model_tier(tier));
+        
+        let calls = match final_parsed {
+            ParsedResponse::ToolCalls { calls, .. } | ParsedResponse::TextThenTools { calls, .. } => calls,
+            ParsedResponse::Text(text) => {
+                consecutive_denials = 0;
+                host.record_trace(TraceStep {
+                    step: current_step,
+                    thinking: step_thinking.clone(),
+                    tool: None,
+                    parameters: None,
+                    observation_len: 0,
+                });
+                
+                // Plain prose or unparseable XML usually means the model forgot the
+                // tool format mid-task rather than finished, so remind it before stopping
+                if nudges_sent < config.max_nudges && !full_response_text.contains("<message>") {
+                    nudges_sent += 1;
+                    println!("[ReAct] No tool call or <message> in reply, nudging ({}/{})", nudges_sent, config.max_nudges);
+                    host.emit_status(AgentStatus::Nudging);
+                    host.store_observation(TOOL_FORMAT_NUDGE.to_string()).await;
+                    continue;
+                }
+                
+                println!("[ReAct] Final Answer: {}", text);
+                host.emit("agent-message-complete", serde_json::json!({ "role": "model", "content": text }));
+                host.emit_status(AgentStatus::Ready);
+                host.emit("agent-stream-end", "complete".into());
+                return "complete";
+            }
+        };
+        
+        // Approvals are interactive, so several may be pending at once; the tools run in parallel
+        host.emit_status(AgentStatus::ExecutingTools { count: calls.len() });
+        let results = {
+            let host: &H = host;
+            futures::future::join_all(calls.into_iter().map(|call| run_call(host, call))).await
+        };
+        
+        // Process results sequentially to update context
+        for (tool, params, result) in results {
+            let observation = match &result {
+                CallResult::Output(output) if config.guard_tool_output => format_untrusted_observation(&tool, output),
+                CallResult::Output(output) => format_observation(output),
+                CallResult::Denied => format!("<observation>User DENIED the {} tool.</observation>", tool),
+            };
+            host.record_trace(TraceStep {
+                step: current_step,
+                thinking: step_thinking.clone(),
+                tool: Some(tool.clone()),
+                parameters: Some(params.clone()),
+                observation_len: observation.len(),
+            });
+            
+            match result {
+                CallResult::Denied => {
+                    consecutive_denials += 1;
+                    if consecutive_denials >= MAX_CONSECUTIVE_DENIALS {
+                        host.emit_status(AgentStatus::error("Stopped after too many denials"));
+                        host.emit("agent-stream-end", "denied_loop".into());
+                        return "denied_loop";
+                    }
+                    host.emit_status(AgentStatus::Thinking);
+                    host.store_observation(observation).await;
+                }
+                CallResult::Output(output) => {
+                    consecutive_denials = 0;
+                    host.emit("agent-tool-result", serde_json::json!({
+                        "tool": tool,
+                        "parameters": params,
+                        "result": output
+                    }));
+                    host.store_observation(observation).await;
+                }
+            }
+        }
+        host.emit_status(AgentStatus::Thinking);
+    }
+    
+    if host.is_cancelled() {
+        finish_cancelled(host, "", CompletionMetadata::default());
+        return "cancelled";
+    }
+    
+    host.emit_status(AgentStatus::MaxSteps);
+    host.emit("agent-stream-end", "max_steps".into());
+    "max_steps"
+}
+
+/// Start a completion, retrying transient failures (network, rate limit, server errors)
+/// Permanent failures like a rejected API key are surfaced straight away; returns None after reporting
+pub async fn request_completion<H: ReactHost + ?Sized>(host: &H, system_prompt: &str, messages: &[Message], is_turbo: bool) -> Option<CompletionResult> {
+    let mut retries = 0;
+    loop {
+        let result = if is_turbo {
+            host.provider().agent(system_prompt, messages).await
+        } else {
+            host.provider().chat(system_prompt, messages).await
+        };
+        
+        let error: ZoxError = match result {
+            Ok(completion) => {
+                // Reported after the request succeeds so a failover shows the tier actually used
+                if let Some(tier) = host.provider().active_model() {
+                    host.emit("active-model-changed", tier.display_name().into());
+                }
+                return Some(completion);
+            }
+            Err(e) => e,
+        };
+        
+        if error.is_retryable() && retries < PROVIDER_RETRIES && !host.is_cancelled() {
+            retries += 1;
+            println!("[ReAct] Provider error, retrying in {}s: {}", PROVIDER_RETRY_DELAY.as_secs(), error);
+            host.emit_status(AgentStatus::Retrying);
+            tokio::time::sleep(PROVIDER_RETRY_DELAY).await;
+            continue;
+        }
+        
+        println!("[ReAct] Provider error: {}", error);
+        if error.is_model_missing() {
+            // Lets the UI offer the Setup flow instead of a plain error bubble
+            host.emit("local-model-missing", Value::Null);
+        }
+        let message = error.user_message();
+        host.emit_status(AgentStatus::error(message.as_str()));
+        host.emit("agent-error", message.into());
+        host.emit("agent-streaming", false.into());
+        return None;
+    }
+}
+
+/// Stream one model response, emitting safe display text as it arrives
+/// Returns the raw response, its parsed form and completion metadata, or Err after reporting a failure
+async fn stream_step<H: ReactHost>(host: &H, system_prompt: &str, messages: &[Message]) -> Result<(String, ParsedResponse, CompletionMetadata), ()> {
+    let mut parser = StreamingParser::new_turbo();
+    let mut full_response_text = String::new();
+    let mut safe_display_text = String::new();
+    let mut throttle = StreamThrottle::from_settings();
+    let settings = crate::state::settings::get();
+    let (raw_stream, show_thinking) = (settings.debug_raw_stream, settings.show_thinking);
+    
+    host.emit("agent-streaming", true.into());
+    
+    let completion = match request_completion(host, system_prompt, messages, true).await {
+        Some(CompletionResult::Stream(mut stream, metadata)) => {
+            host.emit_status(AgentStatus::Streaming);
+            loop {
+                let result = match throttle.next(&mut stream).await {
+                    Some(Some(result)) => result,
+                    Some(None) => break,
+                    None => {
+                        // Flush interval elapsed with text still buffered
+                        host.emit("agent-stream-chunk", safe_display_text.as_str().into());
+                        throttle.flushed();
+                        if host.is_cancelled() {
+                            break;
+                        }
+                        continue;
+                    }
+                };
+                if host.is_cancelled() {
+                    break;
+                }
+                
+                match result {
+                    Ok(token) => {
+                        if raw_stream {
+                            host.emit("agent-raw-chunk", token.as_str().into());
+                        }
+                        
+                        // Feed parser for tool detection and safe text extraction
+                        for event in parser.feed(&token) {
+                            match event {
+                                StreamEvent::Text(text) => {
+                                    // The frontend REPLACES the content, so we send the full text
+                                    safe_display_text.push_str(&text);
+                                    throttle.push(&text);
+                                    if throttle.should_flush() {
+                                        host.emit("agent-stream-chunk", safe_display_text.as_str().into());
+                                        throttle.flushed();
+                                    }
+                                }
+                                StreamEvent::ToolCallComplete { tool, .. } => {
+                                    host.emit_status(AgentStatus::ExecutingTool { name: tool.clone() });
+                                }
+                            }
+                        }
+                        
+                        // Accumulate raw response for history
+                        full_response_text.push_str(&token);
+                        
+                        if show_thinking {
+                            if let Some(thinking) = extract_thinking(&full_response_text) {
+                                host.emit("agent-thinking", thinking.into());
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        println!("[ReAct] Stream error: {}", e);
+                        host.emit_status(AgentStatus::error(e.as_str()));
+                        host.emit("agent-streaming", false.into());
+                        return Err(());
+                    }
+                }
+            }
+            
+            if throttle.has_pending() {
+                host.emit("agent-stream-chunk", safe_display_text.as_str().into());
+            }
+            
+            let completion = metadata.lock().clone();
+            completion
+        }
+        None => return Err(()),
+    };
+    
+    host.emit("agent-streaming", false.into());
+    Ok((full_response_text, parser.finalize(), completion))
+}
+
+/// Approve (if required) and execute a single tool call
+async fn run_call<H: ReactHost>(host: &H, call: ToolCallData) -> (String, Value, CallResult) {
+    let ToolCallData { tool, parameters: params, .. } = call;
+    host.emit_status(AgentStatus::ExecutingTool { name: tool.clone() });
+    println!("[ReAct] Tool Call: {} params: {}", tool, params);
+    
+    let Some(tool_impl) = get_tool_by_name(&tool) else {
+        let message = unknown_tool_message(&tool);
+        return (tool, params, CallResult::Output(message));
+    };
+    
+    // Checked before asking, so the user is never asked to approve a call that can only fail
+    let params = match checked_args(tool_impl.as_ref(), &params.to_string()) {
+        Ok(args) => serde_json::from_str(&args).unwrap_or(params),
+        Err(message) => return (tool, params, CallResult::Output(message)),
+    };
+    
+    if tool_impl.requires_approval() && !host.approve(&tool, &params).await {
+        return (tool, params, CallResult::Denied);
+    }
+    
+    if tool == "read_file" || tool == "write_file" || tool == "replace_lines" || tool == "replace_in_file" || tool == "list_files" {
+        if let Some(path) = params.get("path").and_then(|p| p.as_str()) {
+            let action = if tool == "write_file" || tool == "replace_lines" || tool == "replace_in_file" { "write" } else { "read" };
+            host.emit("agent-file-access", serde_json::json!({"action": action, "path": path}));
+        }
+    }
+    
+    let output = host.run_tool(&tool, &params).await;
+    (tool, params, CallResult::Output(output))
+}
+
+/// End a run whose stream produced no text (safety block, empty candidate).
+/// The blank reply is not persisted so it can't confuse the next turn.
+pub fn finish_empty_response<H: ReactHost + ?Sized>(host: &mut H, completion: &CompletionMetadata) {
+    println!("[ReAct] Stream ended with an empty response");
+    host.report_completion(completion);
+    host.emit("agent-stream-chunk", EMPTY_RESPONSE_MESSAGE.into());
+    host.emit("agent-message-complete", serde_json::json!({ "role": "model", "content": EMPTY_RESPONSE_MESSAGE }));
+    host.emit_status(AgentStatus::Ready);
+    host.emit("agent-stream-end", "empty".into());
+}
+
+/// End a cancelled run, keeping whatever the model streamed before the cancel
+/// The partial reply is persisted so the saved conversation matches what the user saw
+pub fn finish_cancelled<H: ReactHost + ?Sized>(host: &mut H, partial: &str, completion: CompletionMetadata) {
+    println!("[ReAct] Run cancelled ({} chars streamed)", partial.len());
+    host.emit("agent-streaming", false.into());
+    if !clean_for_display(partial).trim().is_empty() {
+        host.report_completion(&completion);
+        let tier = host.provider().active_model();
+        host.persist_message(Message::new("model", partial).with_completion(completion).with_model_tier(tier));
+    }
+    host.emit_status(AgentStatus::Cancelled);
+    host.emit("agent-stream-end", "cancelled".into());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use parking_lot::Mutex;
+    use crate::providers::ProviderCapabilities;
+    
+    /// Provider that replays scripted responses, one per step
+    struct MockProvider {
+        responses: Mutex<VecDeque<String>>,
+        /// Set the flag when this chunk of a stream is delivered, like a user pressing stop
+        cancel_at: Option<(usize, Arc<AtomicBool>)>,
+    }
+    
+    impl MockProvider {
+        fn new(responses: &[&str]) -> Self {
+            Self { responses: Mutex::new(responses.iter().map(|r| r.to_string()).collect()), cancel_at: None }
+        }
+        
+        fn next_stream(&self) -> Result<CompletionResult, ZoxError> {
+            let response = self.responses.lock().pop_front().ok_or("No scripted response left")?;
+            // Split into small chunks so the loop sees a stream like a real provider's
+            let chars: Vec<char> = response.chars().collect();
+            let chunks: Vec<Result<String, String>> = chars.chunks(7).map(|c| Ok(c.iter().collect())).collect();
+            let cancel_at = self.cancel_at.clone();
+            let chunks = chunks.into_iter().enumerate().map(move |(i, chunk)| {
+                if let Some((at, flag)) = &cancel_at {
+                    if i == *at {
+                        flag.store(true, Ordering::SeqCst);
+                    }
+                }
+                chunk
+            });
+            let metadata = Arc::new(Mutex::new(CompletionMetadata::default()));
+            Ok(CompletionResult::Stream(Box::pin(futures::stream::iter(chunks)), metadata))
+        }
+    }
+    
+    #[async_trait]
+    impl ModelProvider for MockProvider {
+        fn capabilities(&self) -> ProviderCapabilities {
+            ProviderCapabilities {
+                supports_tools: true,
+                supports_native_tools: false,
+                supports_streaming: true,
+                supports_cascade: false,
+                supports_summarization: false,
+                supports_vision: false,
+                max_context_tokens: 8192,
+            }
+        }
+        
+        fn name(&self) -> &str {
+            "Mock"
+        }
+        
+        async fn chat(&self, _system_prompt: &str, _messages: &[Message]) -> Result<CompletionResult, ZoxError> {
+            self.next_stream()
+        }
+        
+        async fn agent(&self, _system_prompt: &str, _messages: &[Message]) -> Result<CompletionResult, ZoxError> {
+            self.next_stream()
+        }
+    }
+    
+    /// Host that records what the loop does instead of talking to Tauri
+    struct MockHost {
+        provider: MockProvider,
+        approve: bool,
+        cancelled: Arc<AtomicBool>,
+        context: Vec<Message>,
+        trace: Vec<TraceStep>,
+        events: Mutex<Vec<(&'static str, Value)>>,
+        executed: Mutex<Vec<String>>,
+    }
+    
+    impl MockHost {
+        fn new(responses: &[&str], approve: bool) -> Self {
+            Self {
+                provider: MockProvider::new(responses),
+                approve,
+                cancelled: Arc::new(AtomicBool::new(false)),
+                context: vec![Message::new("user", "do the task")],
+                trace: Vec::new(),
+                events: Mutex::new(Vec::new()),
+                executed: Mutex::new(Vec::new()),
+            }
+        }
+        
+        fn stream_end(&self) -> Option<Value> {
+            self.events.lock().iter().rev().find(|(name, _)| *name == "agent-stream-end").map(|(_, payload)| payload.clone())
+        }
+    }
+    
+    #[async_trait]
+    impl ReactHost for MockHost {
+        fn provider(&self) -> &dyn ModelProvider {
+            &self.provider
+        }
+        
+        fn emit(&self, name: &'static str, payload: Value) {
+            self.events.lock().push((name, payload));
+        }
+        
+        fn is_cancelled(&self) -> bool {
+            self.cancelled.load(Ordering::SeqCst)
+        }
+        
+        async fn messages(&mut self) -> Vec<Message> {
+            self.context.clone()
+        }
+        
+        fn persist_message(&mut self, message: Message) {
+            self.context.push(message);
+        }
+        
+        async fn store_observation(&mut self, content: String) {
+            self.context.push(Message::observation(content));
+        }
+        
+        fn record_trace(&mut self, step: TraceStep) {
+            self.trace.push(step);
+        }
+        
+        fn report_completion(&mut self, _completion: &CompletionMetadata) {}
+        
+        async fn approve(&self, _tool: &str, _params: &Value) -> bool {
+            self.approve
+        }
+        
+        async fn run_tool(&self, tool: &str, _params: &Value) -> String {
+            self.executed.lock().push(tool.to_string());
+            format!("output of {}", tool)
+        }
+    }
+    
+    fn config(max_steps: usize) -> ReactConfig {
+        ReactConfig { system_prompt: "test".to_string(), max_steps, max_nudges: 0, guard_tool_output: false }
+    }
+    
+    const READ_CALL: &str = "<thinking>look first</thinking>\n<tool>read_file</tool>\n<params>\n<path>src/main.rs</path>\n</params>";
+    const WRITE_CALL: &str = "<tool>write_file</tool>\n<params>\n<path>out.txt</path>\n<content>hi</content>\n</params>";
+    
+    #[tokio::test]
+    async fn test_tool_call_observation_then_answer() {
+        let mut host = MockHost::new(&[READ_CALL, "<message>All done</message>"], true);
+        let outcome = run_react_loop(&mut host, &config(5)).await;
+        
+        assert_eq!(outcome, "complete");
+        assert_eq!(*host.executed.lock(), vec!["read_file".to_string()]);
+        assert!(host.context.iter().any(|m| m.content == format_observation("output of read_file")));
+        assert_eq!(host.trace.len(), 2);
+        assert_eq!(host.trace[0].thinking.as_deref(), Some("look first"));
+        assert_eq!(host.trace[0].observation_len, format_observation("output of read_file").len());
+        assert_eq!(host.stream_end(), Some(Value::from("complete")));
+    }
+    
+    #[tokio::test]
+    async fn test_repeated_denials_stop_the_run() {
+        let mut host = MockHost::new(&[WRITE_CALL, WRITE_CALL, WRITE_CALL, "<message>unreachable</message>"], false);
+        let outcome = run_react_loop(&mut host, &config(10)).await;
+        
+        assert_eq!(outcome, "denied_loop");
+        assert!(host.executed.lock().is_empty());
+        assert_eq!(host.context.iter().filter(|m| m.content.contains("User DENIED the write_file tool")).count(), 2);
+        assert_eq!(host.stream_end(), Some(Value::from("denied_loop")));
+    }
+    
+    #[tokio::test]
+    async fn test_max_steps_ends_the_run() {
+        let mut host = MockHost::new(&[READ_CALL, READ_CALL, READ_CALL], true);
+        let outcome = run_react_loop(&mut host, &config(2)).await;
+        
+        assert_eq!(outcome, "max_steps");
+        assert_eq!(host.executed.lock().len(), 2);
+        assert_eq!(host.stream_end(), Some(Value::from("max_steps")));
+    }
+    
+    #[tokio::test]
+    async fn test_cancel_mid_stream_keeps_partial_reply() {
+        let mut host = MockHost::new(&["A long answer that the user stops halfway"], true);
+        host.provider.cancel_at = Some((2, host.cancelled.clone()));
+        let outcome = run_react_loop(&mut host, &config(5)).await;
+        
+        assert_eq!(outcome, "cancelled");
+        let last = host.context.last().unwrap();
+        assert_eq!((last.role.as_str(), last.content.as_str()), ("model", "A long answer "));
+        let events = host.events.lock();
+        let streaming = events.iter().rev().find(|(name, _)| *name == "agent-streaming").map(|(_, payload)| payload.clone());
+        assert_eq!(streaming, Some(Value::from(false)));
+        drop(events);
+        assert_eq!(host.stream_end(), Some(Value::from("cancelled")));
+    }
+    
+    #[tokio::test]
+    async fn test_prose_reply_is_nudged_once() {
+        let mut host = MockHost::new(&["I will look at the file now.", "<message>Checked it</message>"], true);
+        let config = ReactConfig { max_nudges: 1, ..config(5) };
+        let outcome = run_react_loop(&mut host, &config).await;
+        
+        assert_eq!(outcome, "complete");
+        assert!(host.context.iter().any(|m| m.content == TOOL_FORMAT_NUDGE));
+    }
+}
@@ -79,10 +79,7 @@ impl ContextSummarizer {
     
     /// Convert context string to messages format for summarizer
     fn to_messages(&self, context: &str) -> Vec<Message> {
-        vec![Message {
-            role: "user".to_string(),
-            content: context.to_string(),
-        }]
+        vec![Message::new("user", context)]
     }
     
     /// Summarize conversation messages directly (without RAG)
@@ -0,0 +1,204 @@
+//! Post-write syntax checks
+//!
+//! After the agent writes a file, an optional fast syntax check for its language is run
+//! and a short pass/fail note is appended to the tool observation, so a broken edit is
+//! caught on the next step instead of several steps later. Each language is opt-in via
+//! the `syntax_check_languages` setting and every check is bounded by a timeout.
+
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Lines of checker output kept in the observation
+const MAX_ERROR_LINES: usize = 12;
+
+/// How often a running checker is polled for exit
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// A syntax checker for one language
+struct Checker {
+    /// Name used in the `syntax_check_languages` setting
+    language: &'static str,
+    extensions: &'static [&'static str],
+    /// Build the command that checks `path` without modifying the workspace
+    command: fn(&Path) -> Command,
+    /// Pipe the file's contents to the checker instead of letting it open the path
+    reads_stdin: bool,
+}
+
+/// Python executable name on this platform
+#[cfg(target_os = "windows")]
+const PYTHON: &str = "python";
+#[cfg(not(target_os = "windows"))]
+const PYTHON: &str = "python3";
+
+const CHECKERS: &[Checker] = &[
+    Checker {
+        language: "rust",
+        extensions: &["rs"],
+        // A single file from a crate can't be type-checked on its own (its `mod` and `use`
+        // items don't resolve), so only parse it: rustfmt fails on syntax errors, and reading
+        // the source from stdin it neither rewrites the file nor follows `mod x;` into others
+        command: |_| {
+            let mut cmd = Command::new("rustfmt");
+            cmd.args(["--edition", "2021", "--emit", "stdout"]);
+            cmd
+        },
+        reads_stdin: true,
+    },
+    Checker {
+        language: "javascript",
+        extensions: &["js", "mjs", "cjs"],
+        command: |path| {
+            let mut cmd = Command::new("node");
+            cmd.arg("--check").arg(path);
+            cmd
+        },
+        reads_stdin: false,
+    },
+    Checker {
+        language: "python",
+        extensions: &["py"],
+        // Bytecode goes to the temp dir so no __pycache__ appears in the workspace
+        command: |path| {
+            let mut cmd = Command::new(PYTHON);
+            cmd.args(["-m", "py_compile"])
+                .arg(path)
+                .env("PYTHONPYCACHEPREFIX", std::env::temp_dir().join("zox-pycache"));
+            cmd
+        },
+        reads_stdin: false,
+    },
+];
+
+/// Outcome of running a checker
+#[derive(Debug, PartialEq)]
+enum CheckResult {
+    Passed,
+    Failed(String),
+    /// The checker couldn't run (not installed, timed out)
+    Skipped(String),
+}
+
+/// Find the checker for a file, if its language is enabled
+fn checker_for(path: &Path, enabled: &[String]) -> Option<&'static Checker> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    CHECKERS.iter().find(|checker| {
+        checker.extensions.contains(&extension.as_str())
+            && enabled.iter().any(|lang| lang.eq_ignore_ascii_case(checker.language))
+    })
+}
+
+/// Run a command with `input` on stdin, killing it if it doesn't finish within `timeout`
+/// stdout is discarded; stdin and stderr are serviced on their own threads, so a checker
+/// that reads or prints a lot can't block on a full pipe while it is being polled
+fn run_with_timeout(mut command: Command, input: Option<Vec<u8>>, timeout: Duration) -> CheckResult {
+    let program = command.get_program().to_string_lossy().to_string();
+    let stdin = if input.is_some() { Stdio::piped() } else { Stdio::null() };
+    let mut child = match command.stdin(stdin).stdout(Stdio::null()).stderr(Stdio::piped()).spawn() {
+        Ok(child) => child,
+        Err(e) => return CheckResult::Skipped(format!("{} unavailable: {}", program, e)),
+    };
+
+    if let (Some(input), Some(mut stdin)) = (input, child.stdin.take()) {
+        std::thread::spawn(move || {
+            let _ = stdin.write_all(&input);
+        });
+    }
+    let stderr_reader = child.stderr.take().map(|mut stderr| {
+        std::thread::spawn(move || {
+            let mut buf = Vec::new();
+            let _ = stderr.read_to_end(&mut buf);
+            buf
+        })
+    });
+
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return CheckResult::Skipped(format!("timed out after {}s", timeout.as_secs()));
+            }
+            Ok(None) => std::thread::sleep(POLL_INTERVAL),
+            Err(e) => return CheckResult::Skipped(format!("{} failed: {}", program, e)),
+        }
+    };
+
+    if status.success() {
+        return CheckResult::Passed;
+    }
+    let stderr = stderr_reader.and_then(|reader| reader.join().ok()).unwrap_or_default();
+    let stderr = String::from_utf8_lossy(&stderr);
+    let lines: Vec<&str> = stderr.lines().filter(|l| !l.trim().is_empty()).take(MAX_ERROR_LINES).collect();
+    CheckResult::Failed(lines.join("\n"))
+}
+
+/// Syntax-check a file the agent just wrote, returning a note for the observation
+/// Empty when the file's language has no checker or isn't enabled in settings
+pub fn check_written_file(path: &Path) -> String {
+    let settings = crate::state::settings::get();
+    let Some(checker) = checker_for(path, &settings.syntax_check_languages) else {
+        return String::new();
+    };
+
+    let input = if checker.reads_stdin {
+        match std::fs::read(path) {
+            Ok(bytes) => Some(bytes),
+            Err(e) => return format!("\nSyntax check ({}): skipped (failed to read file: {})", checker.language, e),
+        }
+    } else {
+        None
+    };
+
+    let timeout = Duration::from_secs(settings.syntax_check_timeout_secs.max(1));
+    let result = run_with_timeout((checker.command)(path), input, timeout);
+    println!("[SyntaxCheck] {} {}: {:?}", checker.language, path.display(), result);
+
+    match result {
+        CheckResult::Passed => format!("\nSyntax check ({}): passed", checker.language),
+        CheckResult::Failed(errors) => format!("\nSyntax check ({}): FAILED\n{}", checker.language, errors),
+        CheckResult::Skipped(reason) => format!("\nSyntax check ({}): skipped ({})", checker.language, reason),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checker_needs_language_enabled() {
+        let enabled = vec!["Python".to_string()];
+        assert_eq!(checker_for(Path::new("src/app.py"), &enabled).map(|c| c.language), Some("python"));
+        assert!(checker_for(Path::new("src/main.rs"), &enabled).is_none());
+        assert!(checker_for(Path::new("README"), &enabled).is_none());
+        assert!(checker_for(Path::new("src/app.py"), &[]).is_none());
+    }
+
+    #[test]
+    fn test_missing_checker_is_skipped() {
+        let result = run_with_timeout(Command::new("zox-no-such-checker"), None, Duration::from_secs(1));
+        assert!(matches!(result, CheckResult::Skipped(_)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_large_stderr_does_not_block_the_checker() {
+        // Far more than a pipe buffer holds, written before the checker exits
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "head -c 1000000 /dev/zero | tr '\\0' 'x' >&2; exit 1"]);
+        let result = run_with_timeout(cmd, None, Duration::from_secs(10));
+        assert!(matches!(result, CheckResult::Failed(_)), "{:?}", result);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_input_is_piped_to_stdin() {
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "grep -q ok || exit 1"]);
+        assert_eq!(run_with_timeout(cmd, Some(b"ok\n".to_vec()), Duration::from_secs(10)), CheckResult::Passed);
+    }
+}
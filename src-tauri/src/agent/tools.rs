@@ -1,6 +1,11 @@
+use std::net::{IpAddr, SocketAddr, ToSocketAddrs};
+use std::sync::Arc;
+use std::path::Path;
+use std::time::Duration;
 use serde::Serialize;
 use ignore::WalkBuilder;
-use crate::agent::workspace::WorkspaceManager;
+use crate::agent::syntax_check::check_written_file;
+use crate::agent::workspace::{SensitiveMatcher, WorkspaceManager};
 
 // --- Tool Trait ---
 pub trait Tool: Send + Sync {
@@ -17,42 +22,44 @@ struct ReadFileTool;
 impl Tool for ReadFileTool {
     fn name(&self) -> &str { "read_file" }
     fn description(&self) -> &str { "Read file content from the workspace" }
-    fn input_schema(&self) -> &str { r#"{"path": "string"}"# }
+    fn input_schema(&self) -> &str { r#"{"type": "object", "properties": {"path": {"type": "string"}}, "required": ["path"]}"# }
     fn execute(&self, args: &str, workspace: &WorkspaceManager) -> String {
         match serde_json::from_str::<serde_json::Value>(args) {
             Ok(v) => {
                 let rel_path = v["path"].as_str().unwrap_or(args);
                 // Sanitize: strip quotes and whitespace
                 let clean_path = rel_path.trim().trim_matches('"');
-                
-                match workspace.resolve_path(clean_path) {
-                    Ok(path) => {
-                        std::fs::read_to_string(&path)
-                            .unwrap_or_else(|e| format!("Error reading '{}': {}", path.display(), e))
-                    },
-                    Err(e) => format!("Error: {}", e)
-                }
+                read_workspace_file(clean_path, workspace)
             },
             Err(_) => {
                 // Fallback for non-JSON string
                 let clean_path = args.trim().trim_matches('"');
-                match workspace.resolve_path(clean_path) {
-                    Ok(path) => {
-                        std::fs::read_to_string(&path)
-                            .unwrap_or_else(|e| format!("Error reading '{}': {}", path.display(), e))
-                    },
-                    Err(e) => format!("Error: {}", e)
-                }
+                read_workspace_file(clean_path, workspace)
             }
         }
     }
 }
 
+/// Read a workspace file for read_file, refusing files matched by `sensitive_patterns`
+fn read_workspace_file(clean_path: &str, workspace: &WorkspaceManager) -> String {
+    match workspace.resolve_path(clean_path) {
+        Ok(path) if workspace.is_sensitive(&path) => {
+            println!("[ReadFileTool] Refused sensitive file: {}", path.display());
+            format!("Access to '{}' is restricted by policy", clean_path)
+        }
+        Ok(path) => {
+            std::fs::read_to_string(&path)
+                .unwrap_or_else(|e| format!("Error reading '{}': {}", path.display(), e))
+        },
+        Err(e) => format!("Error: {}", e)
+    }
+}
+
 struct WriteFileTool;
 impl Tool for WriteFileTool {
     fn name(&self) -> &str { "write_file" }
     fn description(&self) -> &str { "Write content to a file in the workspace" }
-    fn input_schema(&self) -> &str { r#"{"path": "string", "content": "string"}"# }
+    fn input_schema(&self) -> &str { r#"{"type": "object", "properties": {"path": {"type": "string"}, "content": {"type": "string"}}, "required": ["path", "content"]}"# }
     fn requires_approval(&self) -> bool { true }
     fn execute(&self, args: &str, workspace: &WorkspaceManager) -> String {
         match serde_json::from_str::<serde_json::Value>(args) {
@@ -78,9 +85,9 @@ impl Tool for WriteFileTool {
                             }
                         }
                         
-                        match std::fs::write(&path, content) {
-                            Ok(_) => format!("Successfully wrote {} bytes to {}", content.len(), path.display()),
-                            Err(e) => format!("Error writing to '{}': {}", path.display(), e)
+                        match workspace.write_with_snapshot(&path, content) {
+                            Ok(_) => format!("Successfully wrote {} bytes to {}{}", content.len(), path.display(), check_written_file(&path)),
+                            Err(e) => format!("Error: {}", e)
                         }
                     },
                     Err(e) => format!("Error: {}", e)
@@ -96,7 +103,7 @@ struct ReplaceLinesToolInner;
 impl Tool for ReplaceLinesToolInner {
     fn name(&self) -> &str { "replace_lines" }
     fn description(&self) -> &str { "Replace specific line range in a file. Lines are 1-indexed." }
-    fn input_schema(&self) -> &str { r#"{"path": "string", "start_line": "number", "end_line": "number", "new_content": "string"}"# }
+    fn input_schema(&self) -> &str { r#"{"type": "object", "properties": {"path": {"type": "string"}, "start_line": {"type": "integer"}, "end_line": {"type": "integer"}, "new_content": {"type": "string"}}, "required": ["path", "start_line", "new_content"]}"# }
     fn requires_approval(&self) -> bool { true }
     fn execute(&self, args: &str, workspace: &WorkspaceManager) -> String {
         match serde_json::from_str::<serde_json::Value>(args) {
@@ -124,40 +131,107 @@ impl Tool for ReplaceLinesToolInner {
                             Err(e) => return format!("Error reading file: {}", e)
                         };
                         
-                        let lines: Vec<&str> = content.lines().collect();
-                        let total_lines = lines.len();
-                        
-                        if start_line > total_lines {
-                            return format!("Error: start_line {} exceeds file length {}", start_line, total_lines);
-                        }
-                        
-                        // Build new content
-                        let mut result = Vec::new();
-                        
-                        // Lines before the replacement
-                        for i in 0..(start_line - 1) {
-                            result.push(lines[i].to_string());
-                        }
-                        
-                        // Add the new content
-                        for line in new_content.lines() {
-                            result.push(line.to_string());
-                        }
+                        let (final_content, end_idx, line_count) = match replace_line_range(&content, start_line, end_line, new_content) {
+                            Ok(result) => result,
+                            Err(e) => return format!("Error: {}", e)
+                        };
                         
-                        // Lines after the replacement
-                        let end_idx = std::cmp::min(end_line, total_lines);
-                        for i in end_idx..total_lines {
-                            result.push(lines[i].to_string());
+                        match workspace.write_with_snapshot(&path, &final_content) {
+                            Ok(_) => format!(
+                                "Replaced lines {}-{} in {}. File now has {} lines.{}", 
+                                start_line, end_idx, path.display(), line_count, check_written_file(&path)
+                            ),
+                            Err(e) => format!("Error: {}", e)
                         }
+                    },
+                    Err(e) => format!("Error: {}", e)
+                }
+            },
+            Err(e) => format!("Invalid JSON args: {}", e)
+        }
+    }
+}
+
+/// Replace lines `start_line..=end_line` (1-indexed) of `content` with `new_content`
+/// The file's dominant line ending and its trailing newline, if any, are kept so an edit
+/// doesn't rewrite every line of a CRLF file
+/// Returns the new content, the last replaced line and the new line count
+pub fn replace_line_range(content: &str, start_line: usize, end_line: usize, new_content: &str) -> Result<(String, usize, usize), String> {
+    let lines: Vec<&str> = content.lines().collect();
+    let total_lines = lines.len();
+    if start_line > total_lines {
+        return Err(format!("start_line {} exceeds file length {}", start_line, total_lines));
+    }
+    
+    let crlf = content.matches("\r\n").count();
+    let line_ending = if crlf * 2 > content.matches('\n').count() { "\r\n" } else { "\n" };
+    
+    let end_idx = end_line.min(total_lines);
+    let result: Vec<&str> = lines[..start_line - 1].iter().copied()
+        .chain(new_content.lines())
+        .chain(lines[end_idx..].iter().copied())
+        .collect();
+    
+    let mut final_content = result.join(line_ending);
+    if content.ends_with('\n') && !result.is_empty() {
+        final_content.push_str(line_ending);
+    }
+    Ok((final_content, end_idx, result.len()))
+}
+
+/// Replace exact occurrences of `find` in `content`
+/// Without `all`, `find` must occur exactly once so the edit can't land in the wrong place
+/// Returns the new content and the number of replacements
+pub fn replace_occurrences(content: &str, find: &str, replace: &str, all: bool) -> Result<(String, usize), String> {
+    if find.is_empty() {
+        return Err("'find' cannot be empty".to_string());
+    }
+    
+    let count = content.matches(find).count();
+    match count {
+        0 => Err("'find' text not found in file".to_string()),
+        1 => Ok((content.replacen(find, replace, 1), 1)),
+        _ if all => Ok((content.replace(find, replace), count)),
+        _ => Err(format!(
+            "'find' text matches {} places - include more surrounding text to make it unique, or set all to true",
+            count
+        )),
+    }
+}
+
+/// Find-and-replace exact text in a file - sturdier than line numbers for small edits
+struct ReplaceInFileTool;
+impl Tool for ReplaceInFileTool {
+    fn name(&self) -> &str { "replace_in_file" }
+    fn description(&self) -> &str { "Replace exact text in a file (first unique match, or every match with all)" }
+    fn input_schema(&self) -> &str { r#"{"type": "object", "properties": {"path": {"type": "string"}, "find": {"type": "string"}, "replace": {"type": "string"}, "all": {"type": "boolean"}}, "required": ["path", "find", "replace"]}"# }
+    fn requires_approval(&self) -> bool { true }
+    fn execute(&self, args: &str, workspace: &WorkspaceManager) -> String {
+        match serde_json::from_str::<serde_json::Value>(args) {
+            Ok(v) => {
+                let rel_path = match v["path"].as_str() {
+                    Some(p) => p,
+                    None => return "Error: 'path' field required".to_string()
+                };
+                let find = v["find"].as_str().unwrap_or("");
+                let replace = v["replace"].as_str().unwrap_or("");
+                let all = v["all"].as_bool().unwrap_or_else(|| v["all"].as_str() == Some("true"));
+                
+                match workspace.resolve_path(rel_path) {
+                    Ok(path) => {
+                        let content = match std::fs::read_to_string(&path) {
+                            Ok(c) => c,
+                            Err(e) => return format!("Error reading file: {}", e)
+                        };
                         
-                        let final_content = result.join("\n");
+                        let (new_content, count) = match replace_occurrences(&content, find, replace, all) {
+                            Ok(result) => result,
+                            Err(e) => return format!("Error: {} in {}", e, path.display())
+                        };
                         
-                        match std::fs::write(&path, &final_content) {
-                            Ok(_) => format!(
-                                "Replaced lines {}-{} in {}. File now has {} lines.", 
-                                start_line, end_idx, path.display(), result.len()
-                            ),
-                            Err(e) => format!("Error writing file: {}", e)
+                        match workspace.write_with_snapshot(&path, &new_content) {
+                            Ok(_) => format!("Replaced {} occurrence(s) in {}{}", count, path.display(), check_written_file(&path)),
+                            Err(e) => format!("Error: {}", e)
                         }
                     },
                     Err(e) => format!("Error: {}", e)
@@ -168,12 +242,31 @@ impl Tool for ReplaceLinesToolInner {
     }
 }
 
+/// Matching lines shown per file before search_project moves on to the next file
+const SEARCH_MATCHES_PER_FILE: usize = 5;
+
+/// Find the lines of `content` containing `query` (case-insensitive)
+/// Returns the total number of matching lines and the first `limit` of them as (line number, text)
+pub fn find_line_matches(content: &str, query: &str, limit: usize) -> (usize, Vec<(usize, String)>) {
+    let query = query.to_lowercase();
+    let mut total = 0;
+    let mut shown = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        if line.to_lowercase().contains(&query) {
+            total += 1;
+            if shown.len() < limit {
+                shown.push((index + 1, line.trim().chars().take(100).collect()));
+            }
+        }
+    }
+    (total, shown)
+}
 
 struct SearchProjectTool;
 impl Tool for SearchProjectTool {
     fn name(&self) -> &str { "search_project" }
     fn description(&self) -> &str { "Search the workspace for text patterns" }
-    fn input_schema(&self) -> &str { r#"{"query": "string"}"# }
+    fn input_schema(&self) -> &str { r#"{"type": "object", "properties": {"query": {"type": "string"}}, "required": ["query"]}"# }
     fn execute(&self, args: &str, workspace: &WorkspaceManager) -> String {
         match serde_json::from_str::<serde_json::Value>(args) {
             Ok(v) => {
@@ -183,61 +276,80 @@ impl Tool for SearchProjectTool {
                     return "Error: 'query' cannot be empty".to_string();
                 }
                 
-                let path = workspace.get_workspace_dir();
-                println!("[SearchTool] Searching in: {} for query: '{}'", path.display(), query);
-                
-                let walker = WalkBuilder::new(path)
-                    .hidden(false)
-                    .git_ignore(true)
-                    .build();
+                let roots = workspace.get_roots();
+                // With several roots, results carry the root name so they can be passed back to other tools
+                let multi_root = roots.len() > 1;
                 
                 let mut output = String::new();
                 let mut match_count = 0;
-                let max_matches = 50;
+                let mut files_matched = 0;
+                let max_matches = crate::state::settings::get().search_max_matches.max(1);
                 let mut files_scanned = 0;
                 
-                for entry in walker.flatten() {
-                    let file_path = entry.path();
-                    if !file_path.is_file() { continue; }
-                    files_scanned += 1;
+                'roots: for path in &roots {
+                    println!("[SearchTool] Searching in: {} for query: '{}'", path.display(), query);
+                
+                    let sensitive = SensitiveMatcher::for_root(path);
+                    let walker = WalkBuilder::new(path)
+                        .hidden(false)
+                        .git_ignore(true)
+                        .filter_entry(move |entry| {
+                            !sensitive.matches(entry.path(), entry.file_type().map_or(false, |t| t.is_dir()))
+                        })
+                        .build();
+                
+                    for entry in walker.flatten() {
+                        let file_path = entry.path();
+                        if !file_path.is_file() { continue; }
+                        files_scanned += 1;
 
-                    let relative = file_path.strip_prefix(path).unwrap_or(file_path);
-                    let relative_str = relative.to_string_lossy();
+                        let relative = file_path.strip_prefix(path).unwrap_or(file_path);
+                        let relative = if multi_root {
+                            Path::new(&crate::agent::workspace::root_name(path)).join(relative)
+                        } else {
+                            relative.to_path_buf()
+                        };
+                        let relative_str = relative.to_string_lossy();
                     
-                    // Check filename match
-                    if relative_str.to_lowercase().contains(&query.to_lowercase()) {
-                        if match_count >= max_matches { break; }
-                        output.push_str(&format!(
-                            "Filename match: {}\n",
-                            relative.display()
-                        ));
-                        match_count += 1;
-                    }
+                        // Check filename match
+                        if relative_str.to_lowercase().contains(&query.to_lowercase()) {
+                            output.push_str(&format!(
+                                "Filename match: {}\n",
+                                relative.display()
+                            ));
+                            match_count += 1;
+                            if match_count >= max_matches { break 'roots; }
+                        }
                     
-                    if let Ok(content) = std::fs::read_to_string(file_path) {
-                        for (line_num, line) in content.lines().enumerate() {
-                            if match_count >= max_matches { break; }
-                            
-                            if line.to_lowercase().contains(&query.to_lowercase()) {
-                                output.push_str(&format!(
-                                    "{}:{}:{}\n",
-                                    relative.display(),
-                                    line_num + 1,
-                                    line.chars().take(100).collect::<String>()
-                                ));
-                                match_count += 1;
+                        // A few lines per file, so one file full of hits doesn't hide the others
+                        if let Ok(content) = std::fs::read_to_string(file_path) {
+                            let limit = SEARCH_MATCHES_PER_FILE.min(max_matches - match_count);
+                            let (total, shown) = find_line_matches(&content, query, limit);
+                            if total > 0 {
+                                files_matched += 1;
+                                output.push_str(&format!("{} ({} matches):\n", relative.display(), total));
+                                for (line_num, text) in &shown {
+                                    output.push_str(&format!("  {}: {}\n", line_num, text));
+                                }
+                                if total > shown.len() {
+                                    output.push_str(&format!("  ... {} more in this file\n", total - shown.len()));
+                                }
+                                match_count += shown.len();
                             }
                         }
+                        if match_count >= max_matches { break 'roots; }
                     }
-                    if match_count >= max_matches { break; }
                 }
                 
-                println!("[SearchTool] Scanned {} files, found {} matches", files_scanned, match_count);
+                println!("[SearchTool] Scanned {} files, showing {} matches from {} files", files_scanned, match_count, files_matched);
 
                 if output.is_empty() {
-                    format!("No matches found for '{}' (scanned {} files in {})", query, files_scanned, path.display())
+                    let searched: Vec<String> = roots.iter().map(|r| r.display().to_string()).collect();
+                    format!("No matches found for '{}' (scanned {} files in {})", query, files_scanned, searched.join(", "))
+                } else if match_count >= max_matches {
+                    format!("Showing the first {} matches (limit reached, narrow the query to see more):\n{}", match_count, output)
                 } else {
-                    format!("Found {} matches:\n{}", match_count, output)
+                    format!("Found {} matches in {} files:\n{}", match_count, files_matched, output)
                 }
             },
             Err(e) => format!("Invalid JSON: {}", e)
@@ -245,90 +357,469 @@ impl Tool for SearchProjectTool {
     }
 }
 
+/// Directories never worth listing for the model, even when not gitignored
+pub(crate) const ALWAYS_HIDDEN_DIRS: &[&str] = &[".git", ".hg", ".svn", "node_modules", "target", "dist", "build", "__pycache__", ".venv"];
+
 struct ListFilesTool;
 impl Tool for ListFilesTool {
     fn name(&self) -> &str { "list_files" }
-    fn description(&self) -> &str { "List files and directories" }
-    fn input_schema(&self) -> &str { r#"{"path": "string"}"# }
+    fn description(&self) -> &str { "List files and directories (respects .gitignore)" }
+    fn input_schema(&self) -> &str { r#"{"type": "object", "properties": {"path": {"type": "string"}, "show_hidden": {"type": "boolean"}}}"# }
     fn execute(&self, args: &str, workspace: &WorkspaceManager) -> String {
-        let rel_path = match serde_json::from_str::<serde_json::Value>(args) {
-            Ok(v) => v["path"].as_str().unwrap_or(".").to_string(),
-            Err(_) => ".".to_string()
+        let (rel_path, show_hidden) = match serde_json::from_str::<serde_json::Value>(args) {
+            Ok(v) => (
+                v["path"].as_str().unwrap_or(".").to_string(),
+                v["show_hidden"].as_bool().unwrap_or_else(|| v["show_hidden"].as_str() == Some("true")),
+            ),
+            Err(_) => (".".to_string(), false)
         };
         
+        // With several roots, the top level is the set of roots themselves
+        let roots = workspace.get_roots();
+        if roots.len() > 1 && matches!(rel_path.trim(), "" | "." | "./") {
+            let items: Vec<String> = roots.iter()
+                .map(|root| format!("📁 {} ({})", crate::agent::workspace::root_name(root), root.display()))
+                .collect();
+            return format!("Workspace roots (prefix paths with a root name):\n{}", items.join("\n"));
+        }
+        
         match workspace.resolve_path(&rel_path) {
             Ok(path) => {
                 if !path.is_dir() {
                     return format!("Error: '{}' is not a directory", path.display());
                 }
+                if workspace.is_sensitive(&path) {
+                    return format!("Access to '{}' is restricted by policy", rel_path);
+                }
                 
-                match std::fs::read_dir(&path) {
-                    Ok(entries) => {
-                        let mut output = format!("Contents of {}:\n", path.display());
-                        let mut items = Vec::new();
-                        
-                        for entry in entries.flatten() {
-                            let name = entry.file_name().to_string_lossy().to_string();
-                            let file_type = entry.file_type().ok();
-                            
-                            let prefix = if file_type.map_or(false, |t| t.is_dir()) {
-                                "📁 "
-                            } else {
-                                "📄 "
-                            };
-                            
-                            items.push(format!("{}{}", prefix, name));
+                let sensitive = roots.iter()
+                    .find(|root| path.starts_with(root))
+                    .map(|root| SensitiveMatcher::for_root(root));
+                let walker = WalkBuilder::new(&path)
+                    .max_depth(Some(1))
+                    .hidden(!show_hidden)
+                    .git_ignore(true)
+                    .require_git(false)
+                    .filter_entry(move |entry| {
+                        let is_dir = entry.file_type().map_or(false, |t| t.is_dir());
+                        if is_dir && ALWAYS_HIDDEN_DIRS.contains(&entry.file_name().to_string_lossy().as_ref()) {
+                            return false;
                         }
-                        
-                        items.sort();
-                        output.push_str(&items.join("\n"));
-                        output
+                        !sensitive.as_ref().map_or(false, |s| s.matches(entry.path(), is_dir))
+                    })
+                    .build();
+                
+                let mut output = format!("Contents of {}:\n", path.display());
+                let mut items = Vec::new();
+                
+                for entry in walker {
+                    let entry = match entry {
+                        Ok(entry) => entry,
+                        Err(e) => return format!("Error reading directory: {}", e)
+                    };
+                    // Depth 0 is the listed directory itself
+                    if entry.depth() == 0 {
+                        continue;
                     }
-                    Err(e) => format!("Error reading directory: {}", e)
+                    
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    let prefix = if entry.file_type().map_or(false, |t| t.is_dir()) {
+                        "📁 "
+                    } else {
+                        "📄 "
+                    };
+                    
+                    items.push(format!("{}{}", prefix, name));
                 }
+                
+                items.sort();
+                output.push_str(&items.join("\n"));
+                output
             }
             Err(e) => format!("Error: {}", e)
         }
     }
 }
 
+/// Maximum bytes downloaded by fetch_url before the body is cut off
+const MAX_FETCH_BYTES: usize = 1024 * 1024;
+
+/// Maximum characters of page text returned to the model
+const MAX_FETCH_CHARS: usize = 20_000;
+
+/// Client for fetch_url - redirects are re-checked against the SSRF rules, and host names
+/// go through `PublicOnlyResolver` so the address connected to is the one checked
+/// The proxy setting is ignored: a proxy resolves the host itself, bypassing the resolver
+fn fetch_client() -> Result<reqwest::Client, String> {
+    reqwest::Client::builder()
+        .no_proxy()
+        .dns_resolver(Arc::new(PublicOnlyResolver))
+        .timeout(Duration::from_secs(15))
+        .redirect(reqwest::redirect::Policy::custom(|attempt| {
+            if attempt.previous().len() >= 5 {
+                attempt.error("too many redirects")
+            } else if let Err(e) = check_fetch_url(attempt.url()) {
+                attempt.error(e)
+            } else {
+                attempt.follow()
+            }
+        }))
+        .user_agent("ZOX-Agent/1.0")
+        .build()
+        .map_err(|e| format!("failed to create HTTP client: {}", e))
+}
+
+/// True for loopback, private, link-local, multicast and other non-public addresses
+pub fn is_private_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let octets = v4.octets();
+            v4.is_private() || v4.is_loopback() || v4.is_link_local() || v4.is_multicast()
+                || v4.is_broadcast() || v4.is_documentation()
+                // "This network" 0.0.0.0/8 and reserved 240.0.0.0/4
+                || octets[0] == 0 || octets[0] >= 240
+                // Carrier-grade NAT 100.64.0.0/10
+                || (octets[0] == 100 && (octets[1] & 0xc0) == 64)
+        }
+        IpAddr::V6(v6) => {
+            // IPv4-mapped (::ffff:a.b.c.d) and IPv4-compatible (::a.b.c.d) addresses reach the IPv4 host
+            if let Some(v4) = v6.to_ipv4() {
+                return is_private_ip(IpAddr::V4(v4));
+            }
+            let first = v6.segments()[0];
+            v6.is_loopback() || v6.is_unspecified() || v6.is_multicast()
+                // Unique local fc00::/7 and link-local fe80::/10
+                || (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+/// Resolver for fetch_url that drops private and local addresses
+/// Filtering where the connection is made means a name cannot pass a check and then
+/// resolve somewhere else when connecting (DNS rebinding)
+struct PublicOnlyResolver;
+
+impl reqwest::dns::Resolve for PublicOnlyResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs = tokio::task::spawn_blocking(move || resolve_public(&host)).await??;
+            Ok::<reqwest::dns::Addrs, Box<dyn std::error::Error + Send + Sync>>(Box::new(addrs.into_iter()))
+        })
+    }
+}
+
+/// Look up a host, failing if any of its addresses is private
+/// The port is filled in by the connector
+fn resolve_public(host: &str) -> Result<Vec<SocketAddr>, String> {
+    let addrs: Vec<SocketAddr> = (host, 0)
+        .to_socket_addrs()
+        .map_err(|e| format!("could not resolve '{}': {}", host, e))?
+        .collect();
+    if addrs.is_empty() {
+        return Err(format!("could not resolve '{}'", host));
+    }
+    if addrs.iter().any(|addr| is_private_ip(addr.ip())) {
+        return Err(format!("'{}' points to a private or local address", host));
+    }
+    Ok(addrs)
+}
+
+/// Allow only http(s) URLs that do not name a local host or a private address
+/// Host names are checked when they are resolved, by `PublicOnlyResolver`
+fn check_fetch_url(url: &reqwest::Url) -> Result<(), String> {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(format!("unsupported scheme '{}' (only http and https)", url.scheme()));
+    }
+    let host = url.host_str().ok_or("URL has no host")?;
+    if host.eq_ignore_ascii_case("localhost") || host.ends_with(".localhost") {
+        return Err("localhost is not allowed".to_string());
+    }
+    
+    // IP literals are connected to directly, without the resolver
+    if let Ok(ip) = host.trim_matches(|c| c == '[' || c == ']').parse::<IpAddr>() {
+        if is_private_ip(ip) {
+            return Err(format!("'{}' is a private or local address", host));
+        }
+    }
+    Ok(())
+}
+
+/// Reduce HTML to readable text: drop scripts/styles and tags, decode common entities
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len() / 2);
+    let lower = html.to_ascii_lowercase();
+    let mut pos = 0;
+    
+    while let Some(offset) = html[pos..].find('<') {
+        let start = pos + offset;
+        text.push_str(&html[pos..start]);
+        
+        // Skip the contents of non-visible elements entirely
+        let skipped = ["script", "style", "noscript", "svg", "head"].iter().find_map(|tag| {
+            let open = format!("<{}", tag);
+            let close = format!("</{}>", tag);
+            let next = lower.as_bytes().get(start + open.len()).copied();
+            (lower[start..].starts_with(&open) && matches!(next, Some(b'>' | b' ' | b'\t' | b'\n' | b'\r')))
+                .then(|| lower[start..].find(&close).map_or(html.len(), |end| start + end + close.len()))
+        });
+        if let Some(end) = skipped {
+            pos = end;
+            continue;
+        }
+        
+        let Some(tag_end) = html[start..].find('>') else {
+            pos = html.len();
+            break;
+        };
+        // Block-level tags become line breaks so paragraphs stay separated
+        let tag = lower[start + 1..start + tag_end].trim_start_matches('/');
+        if ["p", "div", "br", "li", "tr", "h1", "h2", "h3", "h4", "h5", "h6", "pre", "section", "article"]
+            .iter()
+            .any(|block| tag == *block || tag.starts_with(&format!("{} ", block)) || tag.starts_with(&format!("{}/", block)))
+        {
+            text.push('\n');
+        }
+        pos = start + tag_end + 1;
+    }
+    text.push_str(&html[pos.min(html.len())..]);
+    
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    
+    // Collapse whitespace within lines and drop blank runs
+    text.lines()
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Download a URL's body up to MAX_FETCH_BYTES and return it as readable text
+async fn fetch_url_text(url: reqwest::Url) -> Result<String, String> {
+    let mut response = fetch_client()?.get(url)
+        .send()
+        .await
+        .map_err(|e| format!("request failed: {}", e))?;
+    
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("HTTP {}", status));
+    }
+    let is_html = response.headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map_or(false, |v| v.contains("html"));
+    
+    let mut body = Vec::new();
+    let mut truncated = false;
+    while let Some(chunk) = response.chunk().await.map_err(|e| format!("read failed: {}", e))? {
+        body.extend_from_slice(&chunk);
+        if body.len() >= MAX_FETCH_BYTES {
+            body.truncate(MAX_FETCH_BYTES);
+            truncated = true;
+            break;
+        }
+    }
+    
+    let raw = String::from_utf8_lossy(&body);
+    let mut text = if is_html { html_to_text(&raw) } else { raw.into_owned() };
+    if text.chars().count() > MAX_FETCH_CHARS {
+        text = text.chars().take(MAX_FETCH_CHARS).collect();
+        truncated = true;
+    }
+    if truncated {
+        text.push_str("\n... [content truncated]");
+    }
+    Ok(text)
+}
+
+struct FetchUrlTool;
+impl Tool for FetchUrlTool {
+    fn name(&self) -> &str { "fetch_url" }
+    fn description(&self) -> &str { "Fetch a web page and return its text content" }
+    fn input_schema(&self) -> &str { r#"{"type": "object", "properties": {"url": {"type": "string"}}, "required": ["url"]}"# }
+    fn requires_approval(&self) -> bool { true }
+    fn execute(&self, args: &str, _workspace: &WorkspaceManager) -> String {
+        let raw_url = match serde_json::from_str::<serde_json::Value>(args) {
+            Ok(v) => v["url"].as_str().unwrap_or("").trim().to_string(),
+            Err(e) => return format!("Invalid JSON: {}", e)
+        };
+        
+        let url = match reqwest::Url::parse(&raw_url) {
+            Ok(url) => url,
+            Err(e) => return format!("Error: Invalid URL '{}': {}", raw_url, e)
+        };
+        
+        // Tools are synchronous but run on the async runtime; DNS and HTTP happen off the reactor
+        let result = tokio::task::block_in_place(|| {
+            check_fetch_url(&url)?;
+            tokio::runtime::Handle::current().block_on(fetch_url_text(url))
+        });
+        
+        match result {
+            Ok(text) if text.trim().is_empty() => format!("Fetched {} but it contained no readable text", raw_url),
+            Ok(text) => format!("Content of {}:\n{}", raw_url, text),
+            Err(e) => format!("Error fetching '{}': {}", raw_url, e)
+        }
+    }
+}
+
 pub fn get_tool_by_name(name: &str) -> Option<Box<dyn Tool>> {
     match name {
         "read_file" => Some(Box::new(ReadFileTool)),
         "write_file" => Some(Box::new(WriteFileTool)),
         "replace_lines" => Some(Box::new(ReplaceLinesToolInner)),
+        "replace_in_file" => Some(Box::new(ReplaceInFileTool)),
         "search_project" => Some(Box::new(SearchProjectTool)),
         "list_files" => Some(Box::new(ListFilesTool)),
+        "fetch_url" => Some(Box::new(FetchUrlTool)),
         _ => None
     }
 }
 
+/// Validate tool parameters against the tool's JSON Schema, then execute it
+/// Malformed parameters produce a precise error observation instead of silent defaults
+pub fn execute_tool(tool: &dyn Tool, args: &str, workspace: &WorkspaceManager) -> String {
+    match checked_args(tool, args) {
+        Ok(args) => tool.execute(&args, workspace),
+        Err(message) => message,
+    }
+}
+
+/// Arguments to run a tool with, or the error observation for the model if they are invalid
+pub fn checked_args(tool: &dyn Tool, args: &str) -> Result<String, String> {
+    validate_args(tool.input_schema(), args)
+        .map_err(|e| format!("Error: Invalid parameters for '{}': {}", tool.name(), e))
+}
+
+/// Lightweight JSON Schema check: object type, required fields and property types
+/// Returns the arguments to execute with. XML parameters arrive as text, so integer and
+/// number fields are parsed here; numbers a native call sends for string fields (e.g. a
+/// port to find) are turned into strings, since the model often leaves them unquoted
+pub fn validate_args(schema: &str, args: &str) -> Result<String, String> {
+    let schema: serde_json::Value = serde_json::from_str(schema)
+        .map_err(|e| format!("invalid tool schema: {}", e))?;
+    let mut args: serde_json::Value = serde_json::from_str(args)
+        .map_err(|_| "parameters must be a JSON object".to_string())?;
+    let args = args.as_object_mut().ok_or("parameters must be a JSON object")?;
+    
+    if let Some(required) = schema["required"].as_array() {
+        for field in required.iter().filter_map(|f| f.as_str()) {
+            if args.get(field).map_or(true, |v| v.is_null()) {
+                return Err(format!("missing required field '{}'", field));
+            }
+        }
+    }
+    
+    if let Some(properties) = schema["properties"].as_object() {
+        for (field, spec) in properties {
+            let (Some(value), Some(expected)) = (args.get_mut(field), spec["type"].as_str()) else {
+                continue;
+            };
+            if value.is_null() {
+                continue;
+            }
+            if expected == "string" && value.is_number() {
+                *value = serde_json::Value::String(value.to_string());
+            }
+            if let Some(text) = value.as_str().map(str::trim) {
+                let parsed = match expected {
+                    "integer" => text.parse::<i64>().ok().map(serde_json::Value::from),
+                    "number" => text.parse::<i64>().ok().map(serde_json::Value::from)
+                        .or_else(|| text.parse::<f64>().ok().and_then(serde_json::Number::from_f64).map(serde_json::Value::Number)),
+                    _ => None,
+                };
+                if let Some(parsed) = parsed {
+                    *value = parsed;
+                }
+            }
+            
+            let matches = match expected {
+                "string" => value.is_string(),
+                "integer" => value.is_i64() || value.is_u64(),
+                "number" => value.is_number(),
+                // XML params arrive as text, so accept "true"/"false" too
+                "boolean" => value.is_boolean() || matches!(value.as_str(), Some("true" | "false")),
+                "array" => value.is_array(),
+                "object" => value.is_object(),
+                _ => true,
+            };
+            if !matches {
+                return Err(format!("field '{}' must be of type {}, got {}", field, expected, json_type_name(value)));
+            }
+        }
+    }
+    
+    Ok(serde_json::Value::Object(args.clone()).to_string())
+}
+
+fn json_type_name(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "boolean",
+        serde_json::Value::Number(_) => "number",
+        serde_json::Value::String(_) => "string",
+        serde_json::Value::Array(_) => "array",
+        serde_json::Value::Object(_) => "object",
+    }
+}
+
+/// Observation returned when the model asks for a tool that doesn't exist
+/// Lists the valid tool names so the model can correct itself
+pub fn unknown_tool_message(name: &str) -> String {
+    let available: Vec<String> = get_all_tools().into_iter().map(|t| t.name).collect();
+    format!("Error: Unknown tool '{}'. Available tools: {}", name.trim(), available.join(", "))
+}
+
 pub fn get_all_tools() -> Vec<ToolDefinition> {
     vec![
         ToolDefinition {
             name: "read_file".into(),
             description: "Read content of a file. Path is relative to workspace.".into(),
-            input_schema: r#"{"type": "object", "properties": {"path": {"type": "string"}}}"#.into(),
+            input_schema: r#"{"type": "object", "properties": {"path": {"type": "string"}}, "required": ["path"]}"#.into(),
+            requires_approval: false,
         },
         ToolDefinition {
             name: "write_file".into(),
             description: "Write content to a file. Creates directories if needed. Requires APPROVAL.".into(),
-            input_schema: r#"{"type": "object", "properties": {"path": {"type": "string"}, "content": {"type": "string"}}}"#.into(),
+            input_schema: r#"{"type": "object", "properties": {"path": {"type": "string"}, "content": {"type": "string"}}, "required": ["path", "content"]}"#.into(),
+            requires_approval: true,
         },
         ToolDefinition {
             name: "replace_lines".into(),
             description: "Replace specific line range in a file. Lines are 1-indexed. Use for precise edits. Requires APPROVAL.".into(),
-            input_schema: r#"{"type": "object", "properties": {"path": {"type": "string"}, "start_line": {"type": "number"}, "end_line": {"type": "number"}, "new_content": {"type": "string"}}}"#.into(),
+            input_schema: r#"{"type": "object", "properties": {"path": {"type": "string"}, "start_line": {"type": "integer"}, "end_line": {"type": "integer"}, "new_content": {"type": "string"}}, "required": ["path", "start_line", "new_content"]}"#.into(),
+            requires_approval: true,
+        },
+        ToolDefinition {
+            name: "replace_in_file".into(),
+            description: "Replace exact text in a file. 'find' must match once unless all is true. Requires APPROVAL.".into(),
+            input_schema: r#"{"type": "object", "properties": {"path": {"type": "string"}, "find": {"type": "string"}, "replace": {"type": "string"}, "all": {"type": "boolean"}}, "required": ["path", "find", "replace"]}"#.into(),
+            requires_approval: true,
         },
         ToolDefinition {
             name: "search_project".into(),
             description: "Search the workspace for a text pattern.".into(),
-            input_schema: r#"{"type": "object", "properties": {"query": {"type": "string"}}}"#.into(),
+            input_schema: r#"{"type": "object", "properties": {"query": {"type": "string"}}, "required": ["query"]}"#.into(),
+            requires_approval: false,
         },
         ToolDefinition {
             name: "list_files".into(),
-            description: "List files and directories in a path.".into(),
-            input_schema: r#"{"type": "object", "properties": {"path": {"type": "string"}}}"#.into(),
+            description: "List files and directories in a path, respecting .gitignore. Set show_hidden to include dotfiles.".into(),
+            input_schema: r#"{"type": "object", "properties": {"path": {"type": "string"}, "show_hidden": {"type": "boolean"}}}"#.into(),
+            requires_approval: false,
+        },
+        ToolDefinition {
+            name: "fetch_url".into(),
+            description: "Fetch a public http(s) URL and return its text content (requires approval).".into(),
+            input_schema: r#"{"type": "object", "properties": {"url": {"type": "string"}}, "required": ["url"]}"#.into(),
+            requires_approval: true,
         },
     ]
 }
@@ -338,4 +829,6 @@ pub struct ToolDefinition {
     pub name: String,
     pub description: String,
     pub input_schema: String, 
+    /// Whether the user must approve each call before it runs
+    pub requires_approval: bool,
 }
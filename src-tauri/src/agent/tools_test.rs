@@ -4,10 +4,8 @@
 
 #[cfg(test)]
 mod tests {
-    use crate::agent::tools::{get_all_tools, get_tool_by_name, Tool};
+    use crate::agent::tools::{find_line_matches, get_all_tools, get_tool_by_name, html_to_text, is_private_ip, replace_line_range, replace_occurrences, unknown_tool_message, validate_args, Tool};
     use crate::agent::workspace::WorkspaceManager;
-    use std::path::PathBuf;
-    use tempfile::tempdir;
     
     fn create_test_workspace() -> WorkspaceManager {
         // Create a minimal workspace for testing
@@ -28,6 +26,14 @@ mod tests {
         assert!(tool_names.contains(&"search_project"), "Should have search_project tool");
     }
     
+    #[test]
+    fn test_tool_definitions_match_approval_requirements() {
+        for definition in get_all_tools() {
+            let tool = get_tool_by_name(&definition.name).unwrap();
+            assert_eq!(definition.requires_approval, tool.requires_approval(), "{} approval flag differs", definition.name);
+        }
+    }
+    
     #[test]
     fn test_get_tool_by_name_found() {
         let tool = get_tool_by_name("read_file");
@@ -45,6 +51,16 @@ mod tests {
         assert!(tool.is_none(), "Nonexistent tool should return None");
     }
     
+    #[test]
+    fn test_unknown_tool_message_lists_available_tools() {
+        let message = unknown_tool_message("read_files");
+        assert!(message.contains("'read_files'"), "Should name the unknown tool");
+        for tool_def in get_all_tools() {
+            assert!(message.contains(&tool_def.name), 
+                    "Should list available tool {}", tool_def.name);
+        }
+    }
+    
     #[test]
     fn test_read_file_tool_requires_approval() {
         let tool = get_tool_by_name("read_file").unwrap();
@@ -63,6 +79,62 @@ mod tests {
         assert!(tool.requires_approval(), "replace_lines should require approval");
     }
     
+    #[test]
+    fn test_replace_in_file_tool_requires_approval() {
+        let tool = get_tool_by_name("replace_in_file").unwrap();
+        assert!(tool.requires_approval(), "replace_in_file should require approval");
+        assert!(get_all_tools().iter().any(|t| t.name == "replace_in_file"));
+    }
+    
+    #[test]
+    fn test_replace_occurrences() {
+        let content = "let a = 1;\nlet b = 1;\n";
+        
+        let (replaced, count) = replace_occurrences(content, "a = 1", "a = 2", false).unwrap();
+        assert_eq!(replaced, "let a = 2;\nlet b = 1;\n");
+        assert_eq!(count, 1);
+        
+        // Ambiguous without all, replaced everywhere with it
+        assert!(replace_occurrences(content, "= 1", "= 3", false).is_err());
+        let (replaced, count) = replace_occurrences(content, "= 1", "= 3", true).unwrap();
+        assert_eq!(replaced, "let a = 3;\nlet b = 3;\n");
+        assert_eq!(count, 2);
+        
+        assert!(replace_occurrences(content, "missing", "x", true).is_err());
+        assert!(replace_occurrences(content, "", "x", true).is_err());
+    }
+    
+    #[test]
+    fn test_replace_lines_keeps_crlf_endings() {
+        let content = "fn a() {}\r\nfn b() {}\r\nfn c() {}\r\n";
+        let (replaced, end, count) = replace_line_range(content, 2, 2, "fn b2() {}\nfn b3() {}").unwrap();
+        assert_eq!(replaced, "fn a() {}\r\nfn b2() {}\r\nfn b3() {}\r\nfn c() {}\r\n");
+        assert_eq!((end, count), (2, 4));
+    }
+    
+    #[test]
+    fn test_replace_lines_without_trailing_newline() {
+        let content = "one\ntwo\nthree";
+        let (replaced, _, _) = replace_line_range(content, 3, 3, "3").unwrap();
+        assert_eq!(replaced, "one\ntwo\n3");
+        
+        let (replaced, _, _) = replace_line_range("one\ntwo\n", 1, 1, "1").unwrap();
+        assert_eq!(replaced, "1\ntwo\n");
+        
+        assert!(replace_line_range(content, 4, 4, "x").is_err());
+    }
+    
+    #[test]
+    fn test_find_line_matches_counts_beyond_limit() {
+        let content = "fn Alpha() {}\nlet x = alpha();\nbeta\n    alpha again\n";
+        
+        let (total, shown) = find_line_matches(content, "ALPHA", 2);
+        assert_eq!(total, 3);
+        assert_eq!(shown, vec![(1, "fn Alpha() {}".to_string()), (2, "let x = alpha();".to_string())]);
+        
+        assert_eq!(find_line_matches(content, "gamma", 5), (0, vec![]));
+    }
+    
     #[test]
     fn test_read_file_invalid_json() {
         let tool = get_tool_by_name("read_file").unwrap();
@@ -103,6 +175,107 @@ mod tests {
                 "Should return error for missing query");
     }
     
+    #[test]
+    fn test_fetch_url_rejects_non_http_scheme() {
+        let tool = get_tool_by_name("fetch_url").unwrap();
+        let workspace = create_test_workspace();
+        
+        let result = tool.execute(r#"{"url": "file:///etc/passwd"}"#, &workspace);
+        assert!(result.contains("unsupported scheme"), "Got: {}", result);
+    }
+    
+    #[test]
+    fn test_is_private_ip() {
+        assert!(is_private_ip("127.0.0.1".parse().unwrap()));
+        assert!(is_private_ip("10.1.2.3".parse().unwrap()));
+        assert!(is_private_ip("169.254.169.254".parse().unwrap()));
+        assert!(is_private_ip("::1".parse().unwrap()));
+        assert!(is_private_ip("::ffff:192.168.0.1".parse().unwrap()));
+        assert!(is_private_ip("::ffff:127.0.0.1".parse().unwrap()));
+        assert!(is_private_ip("::127.0.0.1".parse().unwrap()));
+        assert!(is_private_ip("0.1.2.3".parse().unwrap()));
+        assert!(is_private_ip("224.0.0.251".parse().unwrap()));
+        assert!(is_private_ip("ff02::1".parse().unwrap()));
+        assert!(!is_private_ip("93.184.216.34".parse().unwrap()));
+        assert!(!is_private_ip("2606:4700::1111".parse().unwrap()));
+    }
+    
+    #[test]
+    fn test_html_to_text() {
+        let html = "<html><head><title>x</title></head><body><script>var a = 1;</script><h1>Title</h1><p>Hello &amp; welcome</p></body></html>";
+        assert_eq!(html_to_text(html), "Title\nHello & welcome");
+    }
+    
+    #[test]
+    fn test_validate_args_accepts_text_booleans() {
+        let tool = get_tool_by_name("list_files").unwrap();
+        assert!(validate_args(tool.input_schema(), r#"{"path": ".", "show_hidden": "true"}"#).is_ok());
+        assert!(validate_args(tool.input_schema(), r#"{"path": ".", "show_hidden": "yes"}"#).is_err());
+    }
+    
+    #[test]
+    fn test_validate_args_coerces_numbers_for_string_fields() {
+        let tool = get_tool_by_name("replace_in_file").unwrap();
+        let args = validate_args(tool.input_schema(), r#"{"path": "config.toml", "find": 8080, "replace": 1.2}"#).unwrap();
+        let args: serde_json::Value = serde_json::from_str(&args).unwrap();
+        assert_eq!(args["find"], "8080");
+        assert_eq!(args["replace"], "1.2");
+        
+        let (content, count) = replace_occurrences("port = 8080\n", args["find"].as_str().unwrap(), args["replace"].as_str().unwrap(), false).unwrap();
+        assert_eq!((content.as_str(), count), ("port = 1.2\n", 1));
+        
+        // Integer fields still reject text that isn't a number
+        let tool = get_tool_by_name("replace_lines").unwrap();
+        assert!(validate_args(tool.input_schema(), r#"{"path": "a.rs", "start_line": "three", "new_content": 7}"#).is_err());
+    }
+    
+    #[test]
+    fn test_validate_args_parses_text_only_for_numeric_fields() {
+        let tool = get_tool_by_name("replace_lines").unwrap();
+        let args = validate_args(tool.input_schema(), r#"{"path": "a.rs", "start_line": "3", "new_content": "0.50"}"#).unwrap();
+        let args: serde_json::Value = serde_json::from_str(&args).unwrap();
+        assert_eq!(args["start_line"], 3);
+        assert_eq!(args["new_content"], "0.50");
+        
+        let tool = get_tool_by_name("replace_in_file").unwrap();
+        let args = validate_args(tool.input_schema(), r#"{"path": "v.txt", "find": "1.10", "replace": "007"}"#).unwrap();
+        let args: serde_json::Value = serde_json::from_str(&args).unwrap();
+        assert_eq!(args["find"], "1.10");
+        assert_eq!(args["replace"], "007");
+    }
+    
+    #[test]
+    fn test_validate_args_missing_required_field() {
+        let tool = get_tool_by_name("write_file").unwrap();
+        let result = validate_args(tool.input_schema(), r#"{"content": "hello"}"#);
+        assert_eq!(result, Err("missing required field 'path'".to_string()));
+    }
+    
+    #[test]
+    fn test_validate_args_wrong_type() {
+        let tool = get_tool_by_name("replace_lines").unwrap();
+        let result = validate_args(tool.input_schema(), 
+            r#"{"path": "a.rs", "start_line": "three", "new_content": "x"}"#);
+        assert_eq!(result, Err("field 'start_line' must be of type integer, got string".to_string()));
+    }
+    
+    #[test]
+    fn test_validate_args_accepts_valid_and_optional_fields() {
+        let tool = get_tool_by_name("list_files").unwrap();
+        assert!(validate_args(tool.input_schema(), r#"{}"#).is_ok());
+        
+        let tool = get_tool_by_name("replace_lines").unwrap();
+        assert!(validate_args(tool.input_schema(), 
+            r#"{"path": "a.rs", "start_line": 1, "end_line": 2, "new_content": "x"}"#).is_ok());
+    }
+    
+    #[test]
+    fn test_validate_args_rejects_non_object() {
+        let tool = get_tool_by_name("read_file").unwrap();
+        assert!(validate_args(tool.input_schema(), "not valid json").is_err());
+        assert!(validate_args(tool.input_schema(), r#"["a.rs"]"#).is_err());
+    }
+    
     #[test]
     fn test_tool_descriptions_not_empty() {
         for tool_def in get_all_tools() {
@@ -1,8 +1,396 @@
 use std::path::{Path, PathBuf};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use parking_lot::{Mutex, RwLock};
+use once_cell::sync::Lazy;
 use notify::{Watcher, RecursiveMode};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use tauri::{AppHandle, Emitter};
 use std::fs;
 
+/// Maximum number of file edits that can be undone
+const MAX_UNDO_DEPTH: usize = 20;
+
+/// Project instruction files, in priority order
+const PROJECT_INSTRUCTION_FILES: &[&str] = &["ZOX.md", "AGENTS.md", ".cursorrules"];
+
+/// Maximum size of project instructions injected into the system prompt
+const MAX_PROJECT_INSTRUCTIONS_BYTES: usize = 8 * 1024;
+
+/// File count above which a workspace root is flagged as very large
+const LARGE_WORKSPACE_FILES: usize = 20_000;
+
+/// System directories the agent must never be rooted at
+const SYSTEM_DIRS: &[&str] = &[
+    "/bin", "/boot", "/dev", "/etc", "/lib", "/proc", "/sbin", "/sys", "/usr", "/var",
+    "/System", "/Library", "/Applications",
+    "C:\\Windows", "C:\\Program Files", "C:\\Program Files (x86)", "C:\\ProgramData",
+];
+
+/// Windows device names that refer to devices rather than files, with or without an extension
+#[cfg(windows)]
+const RESERVED_DEVICE_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL",
+    "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8", "COM9",
+    "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Reject paths that could reach a Windows device or alternate data stream:
+/// reserved device names (`NUL`, `con.txt`), `\\.\` device paths, and `:` anywhere
+/// but a leading drive letter (`file.txt:stream`). Both separator styles are checked
+/// since model-supplied paths use either
+#[cfg(windows)]
+pub fn check_reserved_path(path: &str) -> Result<(), String> {
+    if path.starts_with(r"\\.\") || path.starts_with("//./") {
+        return Err(format!("Path '{}' refers to a device", path));
+    }
+    // Verbatim prefix from canonicalized Windows paths
+    let trimmed = path.strip_prefix(r"\\?\").unwrap_or(path);
+    
+    for (index, component) in trimmed.split(['/', '\\']).enumerate() {
+        let is_drive = index == 0
+            && component.len() == 2
+            && component.as_bytes()[0].is_ascii_alphabetic()
+            && component.ends_with(':');
+        if component.contains(':') && !is_drive {
+            return Err(format!("Path '{}' contains ':' (alternate data streams are not allowed)", path));
+        }
+        
+        // Windows ignores the extension and trailing dots/spaces when matching device names
+        let stem = component.split('.').next().unwrap_or("").trim_end_matches([' ', '.']);
+        if RESERVED_DEVICE_NAMES.iter().any(|name| name.eq_ignore_ascii_case(stem)) {
+            return Err(format!("Path '{}' uses the reserved device name '{}'", path, component));
+        }
+    }
+    
+    Ok(())
+}
+
+/// Device names and alternate data streams only exist on Windows; elsewhere `:` and
+/// `nul` are ordinary file name characters
+#[cfg(not(windows))]
+pub fn check_reserved_path(_path: &str) -> Result<(), String> {
+    Ok(())
+}
+
+/// Result of checking a candidate workspace root
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct WorkspacePathCheck {
+    /// Canonical path that was checked
+    pub path: String,
+    /// Set when the path must be rejected outright (filesystem root, home dir, system dir)
+    pub rejected: Option<String>,
+    /// Concerns the user must explicitly confirm before using the path
+    pub warnings: Vec<String>,
+    pub requires_confirmation: bool,
+}
+
+/// Reject roots where the agent could do serious damage: `/`, `C:\`, `$HOME` itself, system dirs
+pub fn check_dangerous_root(path: &Path) -> Result<(), String> {
+    if path.parent().is_none() {
+        return Err(format!("'{}' is a filesystem root", path.display()));
+    }
+    if dirs::home_dir().map_or(false, |home| path == home) {
+        return Err(format!("'{}' is your home directory", path.display()));
+    }
+    if SYSTEM_DIRS.iter().any(|dir| path == Path::new(dir)) {
+        return Err(format!("'{}' is a system directory", path.display()));
+    }
+    Ok(())
+}
+
+/// Resolve a candidate root to the canonical path it is checked and stored as,
+/// so `..` segments and symlinks can't slip a dangerous directory past the checks
+fn canonical_root(path: &Path) -> Result<PathBuf, String> {
+    let canonical = fs::canonicalize(path)
+        .map_err(|e| format!("Cannot access '{}': {}", path.display(), e))?;
+    // On Windows canonicalize yields a verbatim \\?\C:\... path, which never equals C:\Windows
+    Ok(PathBuf::from(canonical.to_string_lossy().trim_start_matches(r"\\?\").to_string()))
+}
+
+/// Check whether a directory is a safe workspace root
+/// Dangerous roots are rejected; risky ones produce warnings that need confirmation
+pub fn validate_workspace_root(path: &Path) -> Result<WorkspacePathCheck, String> {
+    let path = canonical_root(path)?;
+    if !path.is_dir() {
+        return Err(format!("'{}' is not a directory", path.display()));
+    }
+    
+    let rejected = check_dangerous_root(&path).err();
+    let mut warnings = Vec::new();
+    
+    if rejected.is_none() {
+        if dirs::home_dir().map_or(false, |home| home.starts_with(&path)) {
+            warnings.push("This directory contains your home directory".to_string());
+        }
+        if SYSTEM_DIRS.iter().any(|dir| path.starts_with(dir)) {
+            warnings.push("This directory is inside a system directory".to_string());
+        }
+        
+        // Count files (respecting .gitignore) but stop as soon as the limit is hit
+        let file_count = ignore::WalkBuilder::new(&path)
+            .build()
+            .flatten()
+            .filter(|entry| entry.file_type().map_or(false, |t| t.is_file()))
+            .take(LARGE_WORKSPACE_FILES + 1)
+            .count();
+        if file_count > LARGE_WORKSPACE_FILES {
+            warnings.push(format!("This directory is very large (over {} files)", LARGE_WORKSPACE_FILES));
+        }
+    }
+    
+    Ok(WorkspacePathCheck {
+        path: path.display().to_string(),
+        requires_confirmation: rejected.is_some() || !warnings.is_empty(),
+        rejected,
+        warnings,
+    })
+}
+
+/// Matches the `sensitive_patterns` setting against paths in one workspace root
+/// Uses gitignore syntax, so `*.pem` matches at any depth and `secrets/` a whole directory
+#[derive(Clone)]
+pub struct SensitiveMatcher {
+    root: PathBuf,
+    matcher: Gitignore,
+}
+
+impl SensitiveMatcher {
+    /// Build a matcher from the current settings
+    pub fn for_root(root: &Path) -> Self {
+        Self::with_patterns(root, &crate::state::settings::get().sensitive_patterns)
+    }
+
+    pub fn with_patterns(root: &Path, patterns: &[String]) -> Self {
+        let mut builder = GitignoreBuilder::new(root);
+        for pattern in patterns {
+            if let Err(e) = builder.add_line(None, pattern) {
+                println!("[WorkspaceManager] Ignoring invalid sensitive pattern '{}': {}", pattern, e);
+            }
+        }
+        let matcher = builder.build().unwrap_or_else(|e| {
+            println!("[WorkspaceManager] Failed to build sensitive patterns: {}", e);
+            Gitignore::empty()
+        });
+        Self { root: root.to_path_buf(), matcher }
+    }
+
+    /// Whether the path, or a directory containing it, is restricted
+    /// Paths outside the root never match
+    pub fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        let Ok(relative) = path.strip_prefix(&self.root) else {
+            return false;
+        };
+        if relative.as_os_str().is_empty() {
+            return false;
+        }
+        self.matcher.matched_path_or_any_parents(relative, is_dir).is_ignore()
+    }
+}
+
+/// Check that every pattern parses, for validating the setting before it is saved
+pub fn validate_sensitive_patterns(patterns: &[String]) -> Result<(), String> {
+    let mut builder = GitignoreBuilder::new("");
+    for pattern in patterns {
+        builder.add_line(None, pattern).map_err(|e| format!("Invalid pattern '{}': {}", pattern, e))?;
+    }
+    Ok(())
+}
+
+/// Resolve a path within a single root
+/// Validates that the path doesn't escape the root using component normalization
+fn resolve_in_root(workspace_dir: &Path, relative_path: &str, original: &str) -> Result<PathBuf, String> {
+    let path = Path::new(relative_path);
+
+    // 1. Normalize the path logic to remove components like '.' and '..'
+    // We do this purely logically so we don't depend on the file existing
+    let mut components = workspace_dir.components().map(|c| c.as_os_str()).collect::<Vec<_>>();
+
+    // Handle absolute paths (must start with workspace)
+    let path_to_resolve = if path.is_absolute() {
+        if path.starts_with(workspace_dir) {
+           // It's absolute and potentially inside, but might have '..' later
+           // We'll parse its components starting from root
+           path
+        } else {
+           // Absolute but outside? Treat as filename in workspace for safety
+           // That name may itself be a link out of the root, so it is checked like any other path
+           let filename = path.file_name().ok_or("Invalid path")?;
+           let result = workspace_dir.join(filename);
+           check_symlink_escape(workspace_dir, &result, original)?;
+           return Ok(result);
+        }
+    } else {
+        path
+    };
+
+    // If it was relative, we essentially join it to workspace
+    // If it was absolute-in-workspace, we just process its components
+    // But to be consistent, let's just process the relative part if absolute
+
+    let path_components = if path_to_resolve.is_absolute() {
+         // Strip the workspace prefix first? 
+         // Actually, easier: reset components to root and push ALL components of path_to_resolve
+         // Then normalize
+         components.clear();
+         path_to_resolve.components()
+    } else {
+         path.components()
+    };
+
+    for component in path_components {
+        match component {
+            std::path::Component::Prefix(_) => {
+                // Start over if we hit a prefix (should handle absolute case if logic above allows)
+                // But we handled absolute above.
+            }, 
+            std::path::Component::RootDir => {
+                // If we encounter root dir in iteration, it means we are resetting to root
+                // This implies an absolute path. 
+                if components.is_empty() {
+                     components.push(std::ffi::OsStr::new("/")); // Unix simplified
+                }
+                // If we are appending to workspace, we shouldn't hit RootDir unless path was absolute
+            }, 
+            std::path::Component::CurDir => {}, // Ignore .
+            std::path::Component::ParentDir => {
+                // Pop last component if possible
+                if components.len() > workspace_dir.components().count() {
+                     components.pop();
+                } else {
+                    // Attempt to pop workspace dir part - FORBIDDEN
+                    return Err(format!("Path '{}' escapes workspace directory", original));
+                }
+            },
+            std::path::Component::Normal(c) => {
+                components.push(c);
+            }
+        }
+    }
+
+    // Reassemble
+    let mut result = PathBuf::new();
+    for c in components {
+        result.push(c);
+    }
+
+    // Final Verify (Paranoid Check)
+    if !result.starts_with(workspace_dir) {
+         return Err(format!("Path '{}' resolved to outside workspace", original));
+    }
+
+    check_symlink_escape(workspace_dir, &result, original)?;
+    Ok(result)
+}
+
+/// Reject a resolved path that leaves the root through a symlink
+/// The deepest existing part of the path is canonicalized, so a new file inside a linked
+/// directory is caught as well as a link to an existing file
+fn check_symlink_escape(workspace_dir: &Path, resolved: &Path, original: &str) -> Result<(), String> {
+    let Ok(root) = fs::canonicalize(workspace_dir) else {
+        // Nothing exists under a missing root, so there is no link to follow
+        return Ok(());
+    };
+    
+    // symlink_metadata also finds dangling links, which a write would follow
+    let Some(existing) = resolved.ancestors().find(|p| fs::symlink_metadata(p).is_ok()) else {
+        return Ok(());
+    };
+    let canonical = fs::canonicalize(existing)
+        .map_err(|_| format!("Path '{}' goes through a broken symlink", original))?;
+    if !canonical.starts_with(&root) {
+        return Err(format!("Path '{}' resolves outside the workspace through a symlink", original));
+    }
+    Ok(())
+}
+
+/// Folder name used to address a root in root-prefixed paths
+pub fn root_name(root: &Path) -> String {
+    root.file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| root.display().to_string())
+}
+
+/// Pick the root a path belongs to, returning it with the path to resolve inside it
+/// Absolute paths go to the root containing them; relative paths go to the root named
+/// by their first component, or to the primary root when no name matches
+fn select_root<'a>(roots: &'a [PathBuf], path: &'a str) -> (&'a Path, &'a str) {
+    let primary = roots[0].as_path();
+    
+    if Path::new(path).is_absolute() {
+        let root = roots.iter()
+            .filter(|root| Path::new(path).starts_with(root))
+            .max_by_key(|root| root.components().count())
+            .map_or(primary, |root| root.as_path());
+        return (root, path);
+    }
+    
+    if roots.len() > 1 {
+        let trimmed = path.trim_start_matches("./").trim_start_matches(".\\");
+        let (first, rest) = trimmed.split_once(['/', '\\']).unwrap_or((trimmed, ""));
+        if let Some(root) = roots.iter().find(|root| root_name(root).eq_ignore_ascii_case(first)) {
+            return (root.as_path(), if rest.is_empty() { "." } else { rest });
+        }
+    }
+    
+    (primary, path)
+}
+
+/// Snapshot of a file taken before the agent modified it
+#[derive(Debug, Clone)]
+pub struct EditSnapshot {
+    pub path: PathBuf,
+    /// Previous content, or None if the file did not exist
+    pub previous: Option<Vec<u8>>,
+}
+
+/// Snapshot a file's bytes, then write the new content
+/// A file that exists but cannot be read is not written, since the edit could not be undone
+fn snapshot_and_write(path: &Path, content: &[u8]) -> Result<EditSnapshot, String> {
+    let previous = match fs::read(path) {
+        Ok(bytes) => Some(bytes),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+        Err(e) => return Err(format!("Failed to snapshot '{}' before writing: {}", path.display(), e)),
+    };
+    
+    fs::write(path, content).map_err(|e| format!("Failed to write '{}': {}", path.display(), e))?;
+    Ok(EditSnapshot { path: path.to_path_buf(), previous })
+}
+
+/// Message delivered to a watcher thread
+enum WatchMessage {
+    Event(notify::Event),
+    Stop,
+}
+
+/// A running file watcher thread and the channel used to stop it
+struct WatcherHandle {
+    stop_tx: std::sync::mpsc::Sender<WatchMessage>,
+    thread: JoinHandle<()>,
+}
+
+impl WatcherHandle {
+    /// Signal the thread to stop and wait for it to exit
+    fn stop(self) {
+        let _ = self.stop_tx.send(WatchMessage::Stop);
+        let _ = self.thread.join();
+    }
+}
+
+/// Running watchers keyed by watched directory - at most one per root
+/// WorkspaceManager is cloned and recreated freely, so ownership lives here
+static WATCHERS: Lazy<Mutex<HashMap<PathBuf, WatcherHandle>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Stop every running file watcher (called on app shutdown)
+pub fn stop_all_watchers() {
+    let watchers: Vec<(PathBuf, WatcherHandle)> = WATCHERS.lock().drain().collect();
+    for (dir, handle) in watchers {
+        handle.stop();
+        println!("[WorkspaceManager] File watcher stopped for {}", dir.display());
+    }
+}
+
 /// Manages the ZOX data directories:
 /// - workspace: Agent-edited files
 /// - data: RAG/LanceDB storage
@@ -11,37 +399,23 @@ use std::fs;
 pub struct WorkspaceManager {
     /// Root directory for all ZOX data
     root_dir: PathBuf,
-    /// Workspace directory for agent-edited files
-    workspace_dir: PathBuf,
+    /// Directories the agent may access - the first is the primary workspace
+    /// Shared across clones so a root change reaches the actor and the commands
+    roots: Arc<RwLock<Vec<PathBuf>>>,
     /// Data directory for RAG/LanceDB
     data_dir: PathBuf,
     /// History directory for chat conversations
     history_dir: PathBuf,
-    app_handle: AppHandle,
+    /// Receives file change events (None in tests, where no watcher is started)
+    app_handle: Option<AppHandle>,
+    /// Undo stack of file snapshots - shared across clones
+    undo_stack: Arc<Mutex<VecDeque<EditSnapshot>>>,
 }
 
 impl WorkspaceManager {
-    pub fn new(app_handle: AppHandle) -> Self {
-        // Get app data directory: %APPDATA%\zox on Windows (not zox\ZOX)
-        let root_dir = if cfg!(target_os = "windows") {
-            // Use APPDATA directly to avoid the qualifier/organization/app nesting
-            if let Ok(appdata) = std::env::var("APPDATA") {
-                PathBuf::from(appdata).join("zox")
-            } else {
-                PathBuf::from("./zox_data")
-            }
-        } else if cfg!(target_os = "macos") {
-            // ~/Library/Application Support/zox
-            dirs::data_dir()
-                .unwrap_or_else(|| PathBuf::from("."))
-                .join("zox")
-        } else {
-            // Linux: ~/.local/share/zox
-            dirs::data_dir()
-                .unwrap_or_else(|| PathBuf::from("."))
-                .join("zox")
-        };
-
+    /// Lay out the workspace, data and history directories under `root_dir`
+    /// (the directory from `resolve_app_data_dir`)
+    pub fn new(app_handle: AppHandle, root_dir: PathBuf) -> Self {
         let workspace_dir = root_dir.join("workspace");
         let data_dir = root_dir.join("data");
         let history_dir = root_dir.join("history");
@@ -58,10 +432,29 @@ impl WorkspaceManager {
 
         Self {
             root_dir,
-            workspace_dir,
+            roots: Arc::new(RwLock::new(vec![workspace_dir])),
             data_dir,
             history_dir,
-            app_handle,
+            app_handle: Some(app_handle),
+            undo_stack: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    /// Workspace in a fresh temporary directory, without an app or file watchers
+    #[cfg(test)]
+    pub fn empty() -> Self {
+        static NEXT_ID: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let id = NEXT_ID.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        let root_dir = std::env::temp_dir().join(format!("zox-workspace-test-{}-{}", std::process::id(), id));
+        let workspace_dir = root_dir.join("workspace");
+        let _ = fs::create_dir_all(&workspace_dir);
+        Self {
+            data_dir: root_dir.join("data"),
+            history_dir: root_dir.join("history"),
+            roots: Arc::new(RwLock::new(vec![workspace_dir])),
+            root_dir,
+            app_handle: None,
+            undo_stack: Arc::new(Mutex::new(VecDeque::new())),
         }
     }
 
@@ -70,9 +463,50 @@ impl WorkspaceManager {
         &self.root_dir
     }
 
-    /// Get the workspace directory for agent-edited files
-    pub fn get_workspace_dir(&self) -> &Path {
-        &self.workspace_dir
+    /// Get the primary workspace directory for agent-edited files
+    pub fn get_workspace_dir(&self) -> PathBuf {
+        self.roots.read()[0].clone()
+    }
+
+    /// Get every workspace root, primary first
+    pub fn get_roots(&self) -> Vec<PathBuf> {
+        self.roots.read().clone()
+    }
+
+    /// Replace the workspace roots (the first becomes the primary)
+    /// Every root must be an existing, non-dangerous directory, and root folder names must
+    /// be unique since paths like `frontend/src/App.tsx` select a root by name
+    pub fn set_roots(&self, roots: Vec<PathBuf>) -> Result<(), String> {
+        if roots.is_empty() {
+            return Err("At least one workspace root is required".to_string());
+        }
+        
+        let roots = roots.iter().map(|root| canonical_root(root)).collect::<Result<Vec<_>, _>>()?;
+        let mut names: Vec<String> = Vec::new();
+        for root in &roots {
+            if !root.is_dir() {
+                return Err(format!("Not a directory: {}", root.display()));
+            }
+            check_dangerous_root(root)?;
+            
+            let name = root_name(root);
+            if names.iter().any(|n| n.eq_ignore_ascii_case(&name)) {
+                return Err(format!("Two workspace roots are named '{}'", name));
+            }
+            names.push(name);
+        }
+        
+        let watching = self.roots.read().iter().any(|root| WATCHERS.lock().contains_key(root));
+        if watching {
+            self.stop_watcher();
+        }
+        *self.roots.write() = roots;
+        if watching {
+            self.start_watcher();
+        }
+        
+        println!("[WorkspaceManager] Workspace roots: {}", names.join(", "));
+        Ok(())
     }
 
     /// Get the data directory for RAG/LanceDB
@@ -86,132 +520,324 @@ impl WorkspaceManager {
     }
 
     /// Resolve a relative path to an absolute path within the workspace
-    /// Validates that the path doesn't escape the workspace using component normalization
+    /// With several roots, a path starting with a root's folder name (`backend/main.rs`)
+    /// resolves inside that root; other relative paths resolve inside the primary root
     pub fn resolve_path(&self, relative_path: &str) -> Result<PathBuf, String> {
-        let path = Path::new(relative_path);
-        
-        // 1. Normalize the path logic to remove components like '.' and '..'
-        // We do this purely logically so we don't depend on the file existing
-        let mut components = self.workspace_dir.components().map(|c| c.as_os_str()).collect::<Vec<_>>();
-        
-        // Handle absolute paths (must start with workspace)
-        let path_to_resolve = if path.is_absolute() {
-            if path.starts_with(&self.workspace_dir) {
-               // It's absolute and potentially inside, but might have '..' later
-               // We'll parse its components starting from root
-               path
-            } else {
-               // Absolute but outside? Treat as filename in workspace for safety
-               let filename = path.file_name().ok_or("Invalid path")?;
-               return Ok(self.workspace_dir.join(filename));
-            }
-        } else {
-            path
-        };
+        check_reserved_path(relative_path)?;
+        let roots = self.roots.read().clone();
+        let (root, path) = select_root(&roots, relative_path);
+        resolve_in_root(root, path, relative_path)
+    }
 
-        // If it was relative, we essentially join it to workspace
-        // If it was absolute-in-workspace, we just process its components
-        // But to be consistent, let's just process the relative part if absolute
-        
-        let path_components = if path_to_resolve.is_absolute() {
-             // Strip the workspace prefix first? 
-             // Actually, easier: reset components to root and push ALL components of path_to_resolve
-             // Then normalize
-             components.clear();
-             path_to_resolve.components()
-        } else {
-             path.components()
-        };
+    /// Whether a resolved path is hidden from the agent by the `sensitive_patterns` setting
+    pub fn is_sensitive(&self, path: &Path) -> bool {
+        let roots = self.roots.read().clone();
+        roots.iter()
+            .filter(|root| path.starts_with(root))
+            .any(|root| SensitiveMatcher::for_root(root).matches(path, path.is_dir()))
+    }
 
-        for component in path_components {
-            match component {
-                std::path::Component::Prefix(_) => {
-                    // Start over if we hit a prefix (should handle absolute case if logic above allows)
-                    // But we handled absolute above.
-                }, 
-                std::path::Component::RootDir => {
-                    // If we encounter root dir in iteration, it means we are resetting to root
-                    // This implies an absolute path. 
-                    if components.is_empty() {
-                         components.push(std::ffi::OsStr::new("/")); // Unix simplified
-                    }
-                    // If we are appending to workspace, we shouldn't hit RootDir unless path was absolute
-                }, 
-                std::path::Component::CurDir => {}, // Ignore .
-                std::path::Component::ParentDir => {
-                    // Pop last component if possible
-                    if components.len() > self.workspace_dir.components().count() {
-                         components.pop();
-                    } else {
-                        // Attempt to pop workspace dir part - FORBIDDEN
-                        return Err(format!("Path '{}' escapes workspace directory", relative_path));
-                    }
-                },
-                std::path::Component::Normal(c) => {
-                    components.push(c);
-                }
-            }
+    /// Write a file, keeping its previous content so the edit can be undone
+    /// The snapshot is only recorded once the write succeeds
+    pub fn write_with_snapshot(&self, path: &Path, content: impl AsRef<[u8]>) -> Result<(), String> {
+        let snapshot = snapshot_and_write(path, content.as_ref())?;
+        let mut stack = self.undo_stack.lock();
+        stack.push_back(snapshot);
+        if stack.len() > MAX_UNDO_DEPTH {
+            stack.pop_front();
         }
+        Ok(())
+    }
 
-        // Reassemble
-        let mut result = PathBuf::new();
-        for c in components {
-            result.push(c);
-        }
+    /// Restore the most recently modified file to its previous content
+    /// Returns the restored path
+    pub fn undo_last_edit(&self) -> Result<PathBuf, String> {
+        let snapshot = self.undo_stack.lock().pop_back()
+            .ok_or_else(|| "Nothing to undo".to_string())?;
 
-        // Final Verify (Paranoid Check)
-        if !result.starts_with(&self.workspace_dir) {
-             return Err(format!("Path '{}' resolved to outside workspace", relative_path));
+        match &snapshot.previous {
+            Some(content) => fs::write(&snapshot.path, content)
+                .map_err(|e| format!("Failed to restore '{}': {}", snapshot.path.display(), e))?,
+            None => {
+                // File was created by the edit - remove it
+                if snapshot.path.exists() {
+                    fs::remove_file(&snapshot.path)
+                        .map_err(|e| format!("Failed to remove '{}': {}", snapshot.path.display(), e))?;
+                }
+            }
         }
 
-        Ok(result)
+        println!("[WorkspaceManager] Undid edit to {}", snapshot.path.display());
+        Ok(snapshot.path)
     }
 
-    /// Start watching the workspace for file changes
-    pub fn start_watcher(&self) {
-        let workspace_dir = self.workspace_dir.clone();
-        let app_handle = self.app_handle.clone();
+    /// Number of edits that can currently be undone
+    pub fn undo_depth(&self) -> usize {
+        self.undo_stack.lock().len()
+    }
 
-        std::thread::spawn(move || {
-            let (tx, rx) = std::sync::mpsc::channel();
+    /// Read the first project instructions file found in the workspace root
+    /// Returns the file name and its contents, truncated to a safe size
+    pub fn read_project_instructions(&self) -> Option<(String, String)> {
+        for name in PROJECT_INSTRUCTION_FILES {
+            let path = self.get_workspace_dir().join(name);
+            let Ok(mut content) = fs::read_to_string(&path) else {
+                continue;
+            };
             
-            let mut watcher = match notify::recommended_watcher(move |res: Result<notify::Event, notify::Error>| {
-                if let Ok(event) = res {
-                    let _ = tx.send(event);
-                }
-            }) {
-                Ok(w) => w,
-                Err(e) => {
-                    eprintln!("[WorkspaceManager] Failed to create watcher: {}", e);
-                    return;
+            if content.trim().is_empty() {
+                continue;
+            }
+            
+            if content.len() > MAX_PROJECT_INSTRUCTIONS_BYTES {
+                let mut end = MAX_PROJECT_INSTRUCTIONS_BYTES;
+                while !content.is_char_boundary(end) {
+                    end -= 1;
                 }
-            };
+                content.truncate(end);
+                println!("[WorkspaceManager] {} truncated to {} bytes", name, end);
+            }
+            
+            return Some((name.to_string(), content));
+        }
+        None
+    }
 
-            if let Err(e) = watcher.watch(&workspace_dir, RecursiveMode::Recursive) {
-                eprintln!("[WorkspaceManager] Failed to watch workspace: {}", e);
+    /// Start watching every workspace root for file changes
+    /// Roots that already have a running watcher are left alone
+    pub fn start_watcher(&self) {
+        let Some(app_handle) = &self.app_handle else {
+            return;
+        };
+        for root in self.get_roots() {
+            start_root_watcher(root, app_handle.clone());
+        }
+    }
+    
+    /// Stop the watchers for all roots, e.g. before switching to different roots
+    pub fn stop_watcher(&self) {
+        for root in self.get_roots() {
+            let handle = WATCHERS.lock().remove(&root);
+            if let Some(handle) = handle {
+                handle.stop();
+                println!("[WorkspaceManager] File watcher stopped for {}", root.display());
+            }
+        }
+    }
+}
+
+/// Start a watcher thread for one root unless one is already running
+fn start_root_watcher(workspace_dir: PathBuf, app_handle: AppHandle) {
+    let mut watchers = WATCHERS.lock();
+    if let Some(existing) = watchers.get(&workspace_dir) {
+        if !existing.thread.is_finished() {
+            return;
+        }
+    }
+    
+    let watched_dir = workspace_dir.clone();
+    let (tx, rx) = std::sync::mpsc::channel();
+    let stop_tx = tx.clone();
+
+    let thread = std::thread::spawn(move || {
+        let mut watcher = match notify::recommended_watcher(move |res: Result<notify::Event, notify::Error>| {
+            if let Ok(event) = res {
+                let _ = tx.send(WatchMessage::Event(event));
+            }
+        }) {
+            Ok(w) => w,
+            Err(e) => {
+                eprintln!("[WorkspaceManager] Failed to create watcher: {}", e);
                 return;
             }
+        };
+
+        if let Err(e) = watcher.watch(&watched_dir, RecursiveMode::Recursive) {
+            eprintln!("[WorkspaceManager] Failed to watch workspace: {}", e);
+            return;
+        }
+
+        println!("[WorkspaceManager] File watcher started for {}", watched_dir.display());
 
-            println!("[WorkspaceManager] File watcher started for {}", workspace_dir.display());
-
-            // Process events
-            loop {
-                match rx.recv() {
-                    Ok(event) => {
-                        if event.kind.is_modify() || event.kind.is_create() || event.kind.is_remove() {
-                            let paths: Vec<String> = event.paths
-                                .iter()
-                                .filter_map(|p| p.to_str().map(String::from))
-                                .collect();
-                            
-                            if !paths.is_empty() {
-                                let _ = app_handle.emit("workspace-file-change", &paths);
-                            }
+        // Process events until stopped
+        loop {
+            match rx.recv() {
+                Ok(WatchMessage::Event(event)) => {
+                    if event.kind.is_modify() || event.kind.is_create() || event.kind.is_remove() {
+                        let paths: Vec<String> = event.paths
+                            .iter()
+                            .filter_map(|p| p.to_str().map(String::from))
+                            .collect();
+                        
+                        if !paths.is_empty() {
+                            let _ = app_handle.emit("workspace-file-change", &paths);
                         }
                     }
-                    Err(_) => break,
                 }
+                Ok(WatchMessage::Stop) | Err(_) => break,
             }
-        });
+        }
+    });
+    
+    watchers.insert(workspace_dir, WatcherHandle { stop_tx, thread });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filesystem_root_is_rejected() {
+        let root = if cfg!(windows) { Path::new("C:\\") } else { Path::new("/") };
+        assert!(check_dangerous_root(root).is_err());
+    }
+
+    #[test]
+    fn test_home_dir_is_rejected() {
+        if let Some(home) = dirs::home_dir() {
+            assert!(check_dangerous_root(&home).is_err());
+            assert!(check_dangerous_root(&home.join("projects")).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_set_roots_checks_the_canonical_path() {
+        let workspace = WorkspaceManager::empty();
+        let root = workspace.get_roots()[0].clone();
+        fs::create_dir_all(root.join("sub")).unwrap();
+        
+        workspace.set_roots(vec![root.join("sub").join("..")]).unwrap();
+        assert_eq!(workspace.get_roots(), vec![fs::canonicalize(&root).unwrap()]);
+        
+        // `/home/me/../me` is the home directory even though it doesn't compare equal to it
+        if let Some(home) = dirs::home_dir().filter(|home| fs::canonicalize(home).ok().as_ref() == Some(home)) {
+            let home_via_parent = home.join("..").join(home.file_name().unwrap());
+            assert!(workspace.set_roots(vec![home_via_parent]).is_err());
+        }
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn test_reserved_device_names_are_rejected() {
+        for path in ["NUL", "con.txt", "src/aux", "src\\Com1.log", "docs/LPT9 .md", "\\\\.\\PhysicalDrive0"] {
+            assert!(check_reserved_path(path).is_err(), "{} should be rejected", path);
+        }
+        for path in ["console.rs", "src/null.rs", "icon.png", "COM10.txt", "src/main.rs"] {
+            assert!(check_reserved_path(path).is_ok(), "{} should be allowed", path);
+        }
+    }
+
+    #[test]
+    #[cfg(windows)]
+    fn test_colon_only_allowed_as_drive_letter() {
+        assert!(check_reserved_path("C:\\Users\\me\\zox\\file.txt").is_ok());
+        assert!(check_reserved_path("\\\\?\\C:\\Users\\me\\file.txt").is_ok());
+        assert!(check_reserved_path("/home/me/zox/file.txt").is_ok());
+        assert!(check_reserved_path("file.txt:stream").is_err());
+        assert!(check_reserved_path("src\\file.txt:$DATA").is_err());
+        assert!(check_reserved_path("/home/me/a:b").is_err());
+        assert!(check_reserved_path("C:file.txt").is_err());
+    }
+
+    #[test]
+    #[cfg(not(windows))]
+    fn test_colons_and_device_names_are_plain_names_off_windows() {
+        for path in ["notes/12:30 standup.md", "/home/me/a:b", "src/aux.rs", "NUL"] {
+            assert!(check_reserved_path(path).is_ok(), "{} should be allowed", path);
+        }
+    }
+
+    #[test]
+    fn test_root_prefixed_paths_select_their_root() {
+        let base = std::env::temp_dir().join("zox-roots");
+        let roots = vec![base.join("frontend"), base.join("backend")];
+        
+        assert_eq!(select_root(&roots, "backend/main.rs"), (roots[1].as_path(), "main.rs"));
+        assert_eq!(select_root(&roots, "Frontend\\src\\App.tsx"), (roots[0].as_path(), "src\\App.tsx"));
+        assert_eq!(select_root(&roots, "backend"), (roots[1].as_path(), "."));
+        assert_eq!(select_root(&roots, "src/App.tsx"), (roots[0].as_path(), "src/App.tsx"));
+        
+        let absolute = base.join("backend").join("lib.rs");
+        let absolute = absolute.to_str().unwrap();
+        assert_eq!(select_root(&roots, absolute).0, roots[1].as_path());
+        
+        // A single root never treats the first component as a root name
+        assert_eq!(select_root(&roots[1..], "backend/main.rs"), (roots[1].as_path(), "backend/main.rs"));
+    }
+
+    #[test]
+    fn test_paths_cannot_escape_their_root() {
+        let base = std::env::temp_dir().join("zox-roots");
+        let roots = vec![base.join("frontend"), base.join("backend")];
+        
+        let (root, path) = select_root(&roots, "backend/src/../main.rs");
+        assert_eq!(resolve_in_root(root, path, "backend/src/../main.rs").unwrap(), roots[1].join("main.rs"));
+        
+        // Climbing out of one root into a sibling root is still an escape
+        let (root, path) = select_root(&roots, "frontend/../backend/main.rs");
+        assert!(resolve_in_root(root, path, "frontend/../backend/main.rs").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinks_cannot_escape_root() {
+        let base = std::env::temp_dir().join(format!("zox-symlink-{}", uuid::Uuid::new_v4()));
+        let root = base.join("workspace");
+        let outside = base.join("outside");
+        fs::create_dir_all(root.join("src")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        fs::write(outside.join("secret.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("link")).unwrap();
+        std::os::unix::fs::symlink(outside.join("secret.txt"), root.join("secret.txt")).unwrap();
+        std::os::unix::fs::symlink(outside.join("missing.txt"), root.join("dangling.txt")).unwrap();
+        
+        for path in ["link/secret.txt", "link/new.txt", "link", "secret.txt", "dangling.txt"] {
+            assert!(resolve_in_root(&root, path, path).is_err(), "{} should be rejected", path);
+        }
+        assert_eq!(resolve_in_root(&root, "src/new.rs", "src/new.rs").unwrap(), root.join("src/new.rs"));
+        
+        // An absolute path outside the root falls back to its file name, which must not be a link out
+        for path in ["/elsewhere/secret.txt", "/elsewhere/link", "/elsewhere/dangling.txt"] {
+            assert!(resolve_in_root(&root, path, path).is_err(), "{} should be rejected", path);
+        }
+        assert_eq!(resolve_in_root(&root, "/elsewhere/notes.md", "/elsewhere/notes.md").unwrap(), root.join("notes.md"));
+        
+        fs::remove_dir_all(&base).ok();
+    }
+
+    #[test]
+    fn test_snapshot_keeps_bytes_and_failed_writes_are_not_recorded() {
+        let dir = std::env::temp_dir().join(format!("zox-undo-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        
+        // Not valid UTF-8, so a text snapshot would have lost it
+        let file = dir.join("data.bin");
+        fs::write(&file, [0xff, 0xfe, 0x00, 0x41]).unwrap();
+        let snapshot = snapshot_and_write(&file, b"replaced").unwrap();
+        assert_eq!(snapshot.previous, Some(vec![0xff, 0xfe, 0x00, 0x41]));
+        assert_eq!(fs::read(&file).unwrap(), b"replaced");
+        
+        let created = snapshot_and_write(&dir.join("new.txt"), b"x").unwrap();
+        assert_eq!(created.previous, None);
+        
+        assert!(snapshot_and_write(&dir.join("missing/new.txt"), b"x").is_err());
+        
+        fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn test_default_sensitive_patterns() {
+        let root = std::env::temp_dir().join("zox-sensitive");
+        let patterns = crate::state::settings::AppSettings::default().sensitive_patterns;
+        let matcher = SensitiveMatcher::with_patterns(&root, &patterns);
+        
+        for path in [".env", "api/.env.local", "certs/server.pem", "secrets/db.json", "config/secrets/token"] {
+            assert!(matcher.matches(&root.join(path), false), "{} should be restricted", path);
+        }
+        for path in [".env.example", "src/env.rs", "docs/keys.md"] {
+            assert!(!matcher.matches(&root.join(path), false), "{} should be visible", path);
+        }
+        assert!(matcher.matches(&root.join("secrets"), true));
+        assert!(!matcher.matches(Path::new("/elsewhere/.env"), false));
     }
 }
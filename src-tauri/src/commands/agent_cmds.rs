@@ -1,7 +1,18 @@
-use tauri::{State, AppHandle};
+use tauri::{State, AppHandle, Emitter};
 use crate::state::app_state::AppState;
 use crate::agent::actor::AgentCommand;
-use crate::agent::history::{HistoryManager, ConversationMeta};
+use crate::agent::history::{Conversation, HistoryManager, ConversationMeta, RetentionPolicy};
+use crate::agent::memory::{AttachedFile, ContextSnapshot, ImageAttachment};
+use crate::agent::rag::{self, RagInitStatus, RagStats};
+use crate::agent::tools::{get_all_tools, ToolDefinition};
+use crate::agent::workspace::{validate_workspace_root, WorkspacePathCheck};
+use crate::providers::ProviderCapabilities;
+use crate::providers::local::LocalInferenceStats;
+use base64::Engine;
+use serde::Serialize;
+
+/// Maximum size of a single attached image (Gemini inline data limit is 20MB per request)
+const MAX_IMAGE_BYTES: usize = 10 * 1024 * 1024;
 
 /// Start an agent task with the given prompt and mode
 #[tauri::command]
@@ -9,21 +20,59 @@ pub async fn start_agent_task(
     task: String,
     is_turbo: bool,
     state: State<'_, AppState>,
-    _app: AppHandle
+    app: AppHandle
+) -> Result<(), String> {
+    start_agent_task_with_files(task, Vec::new(), is_turbo, state, app).await
+}
+
+/// Start an agent task with files attached as context ahead of the prompt
+#[tauri::command]
+pub async fn start_agent_task_with_files(
+    task: String,
+    files: Vec<AttachedFile>,
+    is_turbo: bool,
+    state: State<'_, AppState>,
+    app: AppHandle
 ) -> Result<(), String> {
     let mode = if is_turbo { "turbo" } else { "chat" };
-    println!("[Command] start_agent_task: mode={}, prompt={}", mode, &task[..task.len().min(50)]);
+    println!("[Command] start_agent_task: mode={}, files={}, prompt={}", mode, files.len(), task.chars().take(50).collect::<String>());
+    
+    begin_task(&state, &app)?;
+    let images = state.take_pending_images();
     
     let tx = state.agent_tx.lock().await;
     tx.send(AgentCommand::StartTask { 
         prompt: task, 
-        mode: mode.to_string() 
+        mode: mode.to_string(),
+        images,
+        files,
     })
         .await
-        .map_err(|e| format!("Failed to send command: {}", e))?;
+        .map_err(|e| {
+            state.finish_task();
+            format!("Failed to send command: {}", e)
+        })?;
+    Ok(())
+}
+
+/// Mark the agent busy, or reject the request if a task is already running
+/// The actor clears the flag when the task finishes
+fn begin_task(state: &AppState, app: &AppHandle) -> Result<(), String> {
+    if !state.try_begin_task() {
+        println!("[Command] Agent is busy, rejecting new task");
+        app.emit("agent-busy", true).ok();
+        return Err("Agent is already running a task".to_string());
+    }
+    app.emit("agent-busy-changed", true).ok();
     Ok(())
 }
 
+/// Check whether the agent is running a task (e.g. to disable the send button)
+#[tauri::command]
+pub fn is_agent_busy(state: State<'_, AppState>) -> bool {
+    state.is_agent_busy()
+}
+
 /// Cancel the current agent task
 #[tauri::command]
 pub async fn cancel_agent_task(
@@ -31,7 +80,9 @@ pub async fn cancel_agent_task(
 ) -> Result<(), String> {
     println!("[Command] cancel_agent_task");
     
-    state.send_approval(false);
+    // Deny every waiting approval now - concurrent tool calls may each be waiting, and
+    // the actor only handles Cancel once the running task has returned
+    state.approval_state.write().cancel();
     
     let tx = state.agent_tx.lock().await;
     tx.send(AgentCommand::Cancel)
@@ -40,15 +91,75 @@ pub async fn cancel_agent_task(
     Ok(())
 }
 
+/// Re-run the last user prompt, replacing the response it produced
+#[tauri::command]
+pub async fn regenerate_last(
+    state: State<'_, AppState>,
+    app: AppHandle
+) -> Result<(), String> {
+    println!("[Command] regenerate_last");
+    
+    begin_task(&state, &app)?;
+    
+    let tx = state.agent_tx.lock().await;
+    tx.send(AgentCommand::RegenerateLast)
+        .await
+        .map_err(|e| {
+            state.finish_task();
+            format!("Failed to send command: {}", e)
+        })?;
+    Ok(())
+}
+
+/// Continue a turbo run that was cut off by a crash, or discard it (`discard: true`)
+/// Interrupted conversations are flagged `interrupted` in the history list; a continued
+/// run becomes the current conversation (`conversation-resumed`) and streams as usual
+#[tauri::command]
+pub async fn resume_interrupted_run(
+    id: String,
+    discard: Option<bool>,
+    state: State<'_, AppState>,
+    app: AppHandle
+) -> Result<(), String> {
+    let discard = discard.unwrap_or(false);
+    println!("[Command] resume_interrupted_run: id={} discard={}", id, discard);
+    
+    begin_task(&state, &app)?;
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+    
+    {
+        let tx = state.agent_tx.lock().await;
+        tx.send(AgentCommand::ResumeRun { id, discard, reply: reply_tx })
+            .await
+            .map_err(|e| {
+                state.finish_task();
+                format!("Failed to send command: {}", e)
+            })?;
+    }
+    
+    match tokio::time::timeout(std::time::Duration::from_secs(30), reply_rx).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(_)) => Err("Agent dropped the resume request".to_string()),
+        Err(_) => Err("Timeout waiting for agent".to_string()),
+    }
+}
+
 /// Send user feedback for tool approval
+/// `request_id` comes from the approval request event; stale ids are ignored
 #[tauri::command]
 pub async fn send_user_feedback(
     approved: bool,
+    request_id: Option<u64>,
     state: State<'_, AppState>
 ) -> Result<(), String> {
-    println!("[Command] send_user_feedback: approved={}", approved);
+    println!("[Command] send_user_feedback: approved={} request_id={:?}", approved, request_id);
     
-    if state.send_approval(approved) {
+    let delivered = match request_id {
+        Some(id) => state.send_approval_for(id, approved),
+        None => state.send_approval(approved),
+    };
+    
+    if delivered {
         Ok(())
     } else {
         println!("[Command] Warning: No pending approval to respond to");
@@ -56,6 +167,342 @@ pub async fn send_user_feedback(
     }
 }
 
+/// Override the system prompt for a mode ("chat" or "turbo")
+/// The override is persisted in settings and applied to the running agent
+#[tauri::command]
+pub async fn set_system_prompt(
+    mode: String,
+    prompt: String,
+    state: State<'_, AppState>
+) -> Result<(), String> {
+    update_system_prompt(mode, Some(prompt), &state).await
+}
+
+/// Restore the built-in system prompt for a mode
+#[tauri::command]
+pub async fn reset_system_prompt(
+    mode: String,
+    state: State<'_, AppState>
+) -> Result<(), String> {
+    update_system_prompt(mode, None, &state).await
+}
+
+async fn update_system_prompt(mode: String, prompt: Option<String>, state: &AppState) -> Result<(), String> {
+    if mode != "chat" && mode != "turbo" {
+        return Err(format!("Invalid mode: {}", mode));
+    }
+    if prompt.as_deref().map_or(false, |p| p.trim().is_empty()) {
+        return Err("System prompt cannot be empty".to_string());
+    }
+    
+    let mut settings = crate::state::settings::get();
+    match &prompt {
+        Some(prompt) => { settings.system_prompt_overrides.insert(mode.clone(), prompt.clone()); }
+        None => { settings.system_prompt_overrides.remove(&mode); }
+    }
+    crate::state::settings::update(settings)?;
+    
+    let tx = state.agent_tx.lock().await;
+    tx.send(AgentCommand::SetSystemPrompt { mode, prompt })
+        .await
+        .map_err(|e| format!("Failed to send command: {}", e))
+}
+
+/// Inspect the context window the agent will send to the model
+/// Answered by the actor between tasks, so this fails if a task is running
+#[tauri::command]
+pub async fn get_context_messages(
+    state: State<'_, AppState>
+) -> Result<ContextSnapshot, String> {
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+    
+    {
+        let tx = state.agent_tx.lock().await;
+        tx.send(AgentCommand::GetContext { reply: reply_tx })
+            .await
+            .map_err(|e| format!("Failed to send command: {}", e))?;
+    }
+    
+    match tokio::time::timeout(std::time::Duration::from_secs(5), reply_rx).await {
+        Ok(Ok(snapshot)) => Ok(snapshot),
+        Ok(Err(_)) => Err("Agent dropped the context request".to_string()),
+        Err(_) => Err("Agent is busy - try again when the current task finishes".to_string()),
+    }
+}
+
+/// Summarize older messages in the context window now, returning the summary
+/// Works even when automatic summarization is turned off
+#[tauri::command]
+pub async fn summarize_context(
+    state: State<'_, AppState>
+) -> Result<String, String> {
+    println!("[Command] summarize_context");
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+    
+    {
+        let tx = state.agent_tx.lock().await;
+        tx.send(AgentCommand::SummarizeContext { reply: reply_tx })
+            .await
+            .map_err(|e| format!("Failed to send command: {}", e))?;
+    }
+    
+    match tokio::time::timeout(std::time::Duration::from_secs(60), reply_rx).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(_)) => Err("Agent dropped the summarize request".to_string()),
+        Err(_) => Err("Agent is busy - try again when the current task finishes".to_string()),
+    }
+}
+
+/// Drop the accumulated context to save tokens while staying in the same conversation
+/// Unlike starting a new conversation, the thread and its saved messages are kept
+#[tauri::command]
+pub async fn clear_context(
+    state: State<'_, AppState>
+) -> Result<(), String> {
+    println!("[Command] clear_context");
+    
+    let tx = state.agent_tx.lock().await;
+    tx.send(AgentCommand::ClearContext)
+        .await
+        .map_err(|e| format!("Failed to send command: {}", e))
+}
+
+/// List the agent's tools with their parameter schemas and whether calls need approval
+#[tauri::command]
+pub fn list_tools() -> Vec<ToolDefinition> {
+    get_all_tools()
+}
+
+/// Current agent mode and the features the active provider supports
+#[derive(Debug, Clone, Serialize)]
+pub struct AgentModeInfo {
+    /// "chat" or "turbo" - the mode of the last started task
+    pub mode: String,
+    pub capabilities: ProviderCapabilities,
+}
+
+/// Query the agent mode so the UI can gate the turbo toggle and tool indicators
+/// Answered by the actor between tasks, so this fails if a task is running
+#[tauri::command]
+pub async fn get_agent_mode(
+    state: State<'_, AppState>
+) -> Result<AgentModeInfo, String> {
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+    
+    {
+        let tx = state.agent_tx.lock().await;
+        tx.send(AgentCommand::GetMode { reply: reply_tx })
+            .await
+            .map_err(|e| format!("Failed to send command: {}", e))?;
+    }
+    
+    match tokio::time::timeout(std::time::Duration::from_secs(5), reply_rx).await {
+        Ok(Ok((mode, capabilities))) => Ok(AgentModeInfo { mode, capabilities }),
+        Ok(Err(_)) => Err("Agent dropped the mode request".to_string()),
+        Err(_) => Err("Agent is busy - try again when the current task finishes".to_string()),
+    }
+}
+
+/// Report how many chunks the semantic index holds and roughly how much memory it uses
+/// Answered by the actor between tasks, so this fails if a task is running
+#[tauri::command]
+pub async fn get_rag_stats(
+    state: State<'_, AppState>
+) -> Result<RagStats, String> {
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+    
+    {
+        let tx = state.agent_tx.lock().await;
+        tx.send(AgentCommand::GetRagStats { reply: reply_tx })
+            .await
+            .map_err(|e| format!("Failed to send command: {}", e))?;
+    }
+    
+    match tokio::time::timeout(std::time::Duration::from_secs(5), reply_rx).await {
+        Ok(Ok(stats)) => Ok(stats),
+        Ok(Err(_)) => Err("Agent dropped the stats request".to_string()),
+        Err(_) => Err("Agent is busy - try again when the current task finishes".to_string()),
+    }
+}
+
+/// Report whether the semantic index's embedder is initializing, ready, failed or cancelled
+/// Read from shared state, so it answers even while a task is running
+#[tauri::command]
+pub fn get_rag_status() -> RagInitStatus {
+    rag::init_status()
+}
+
+/// Stop waiting for a RAG initialization that hangs (e.g. a slow model download)
+/// The agent continues without RAG for the rest of the session; false if nothing was initializing
+#[tauri::command]
+pub fn cancel_rag_init() -> bool {
+    println!("[Command] cancel_rag_init");
+    rag::cancel_init()
+}
+
+/// Time a fixed prompt on the local model so CPU, CUDA and Metal can be compared
+/// Loads the default model if needed; fails when the cloud provider is active
+#[tauri::command]
+pub async fn benchmark_local_model(
+    state: State<'_, AppState>
+) -> Result<LocalInferenceStats, String> {
+    println!("[Command] benchmark_local_model");
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+    
+    {
+        let tx = state.agent_tx.lock().await;
+        tx.send(AgentCommand::BenchmarkLocal { reply: reply_tx })
+            .await
+            .map_err(|e| format!("Failed to send command: {}", e))?;
+    }
+    
+    // Loading the model and generating can take minutes on CPU
+    match tokio::time::timeout(std::time::Duration::from_secs(600), reply_rx).await {
+        Ok(Ok(result)) => result,
+        Ok(Err(_)) => Err("Agent dropped the benchmark request".to_string()),
+        Err(_) => Err("Benchmark timed out".to_string()),
+    }
+}
+
+/// Re-walk the workspace and rebuild its file chunks in the semantic index
+/// Progress arrives as `rag-reindex-progress`, then `rag-reindex-complete` or `rag-reindex-failed`
+#[tauri::command]
+pub async fn reindex_workspace(
+    state: State<'_, AppState>
+) -> Result<(), String> {
+    println!("[Command] reindex_workspace");
+    
+    let tx = state.agent_tx.lock().await;
+    tx.send(AgentCommand::ReindexWorkspace)
+        .await
+        .map_err(|e| format!("Failed to send command: {}", e))?;
+    Ok(())
+}
+
+/// Attach an image file to the next prompt
+/// Returns the number of images currently attached
+#[tauri::command]
+pub async fn attach_image(
+    path: String,
+    state: State<'_, AppState>
+) -> Result<usize, String> {
+    let path = std::path::PathBuf::from(path);
+    let mime_type = match path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()).as_deref() {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("webp") => "image/webp",
+        Some("gif") => "image/gif",
+        _ => return Err(format!("Unsupported image type: {}", path.display())),
+    };
+    
+    let bytes = std::fs::read(&path)
+        .map_err(|e| format!("Failed to read image: {}", e))?;
+    
+    attach_image_bytes(&bytes, mime_type, &state)
+}
+
+/// Attach raw image data (e.g. a pasted screenshot) to the next prompt
+/// `data` is base64-encoded
+#[tauri::command]
+pub async fn attach_image_data(
+    data: String,
+    mime_type: String,
+    state: State<'_, AppState>
+) -> Result<usize, String> {
+    if !mime_type.starts_with("image/") {
+        return Err(format!("Unsupported mime type: {}", mime_type));
+    }
+    
+    let bytes = base64::engine::general_purpose::STANDARD
+        .decode(data.trim())
+        .map_err(|e| format!("Invalid base64 image data: {}", e))?;
+    
+    attach_image_bytes(&bytes, &mime_type, &state)
+}
+
+/// Clear any images attached to the next prompt
+#[tauri::command]
+pub async fn clear_attached_images(
+    state: State<'_, AppState>
+) -> Result<(), String> {
+    state.take_pending_images();
+    Ok(())
+}
+
+fn attach_image_bytes(bytes: &[u8], mime_type: &str, state: &AppState) -> Result<usize, String> {
+    if bytes.is_empty() {
+        return Err("Image is empty".to_string());
+    }
+    if bytes.len() > MAX_IMAGE_BYTES {
+        return Err(format!("Image too large ({} bytes, max {})", bytes.len(), MAX_IMAGE_BYTES));
+    }
+    
+    let count = state.attach_image(ImageAttachment {
+        mime_type: mime_type.to_string(),
+        data: base64::engine::general_purpose::STANDARD.encode(bytes),
+    });
+    println!("[Command] Attached {} image ({} bytes), {} pending", mime_type, bytes.len(), count);
+    Ok(count)
+}
+
+/// Check a candidate workspace directory before using it
+/// Returns warnings the UI must ask the user to confirm; dangerous roots are marked rejected
+#[tauri::command]
+pub fn validate_workspace_path(path: String) -> Result<WorkspacePathCheck, String> {
+    println!("[Command] validate_workspace_path: {}", path);
+    validate_workspace_root(std::path::Path::new(&path))
+}
+
+/// Replace the directories the agent may work in - the first path becomes the primary root
+/// Paths prefixed with a root's folder name (e.g. `backend/main.rs`) resolve inside that root
+#[tauri::command]
+pub fn set_workspace_roots(
+    paths: Vec<String>,
+    state: State<'_, AppState>
+) -> Result<(), String> {
+    println!("[Command] set_workspace_roots: {:?}", paths);
+    let workspace = state.get_workspace()
+        .ok_or_else(|| "Workspace not initialized".to_string())?;
+    
+    workspace.set_roots(paths.iter().map(std::path::PathBuf::from).collect())
+}
+
+/// Get the directories the agent may work in, primary first
+#[tauri::command]
+pub fn get_workspace_roots(state: State<'_, AppState>) -> Result<Vec<String>, String> {
+    let workspace = state.get_workspace()
+        .ok_or_else(|| "Workspace not initialized".to_string())?;
+    
+    Ok(workspace.get_roots().iter().map(|root| root.display().to_string()).collect())
+}
+
+/// Where the agent and app data live on disk
+#[derive(Debug, Clone, Serialize)]
+pub struct WorkspaceInfo {
+    /// Root of all Zox data
+    pub root: String,
+    /// Primary workspace the agent edits in
+    pub workspace: String,
+    /// RAG/LanceDB storage
+    pub data: String,
+    /// Saved conversations
+    pub history: String,
+}
+
+/// Get the workspace and data directory paths, e.g. for a header or "open in file manager"
+#[tauri::command]
+pub fn get_workspace_info(state: State<'_, AppState>) -> Result<WorkspaceInfo, String> {
+    let workspace = state.get_workspace()
+        .ok_or_else(|| "Workspace not initialized".to_string())?;
+    
+    Ok(WorkspaceInfo {
+        root: workspace.get_root_dir().display().to_string(),
+        workspace: workspace.get_workspace_dir().display().to_string(),
+        data: workspace.get_data_dir().display().to_string(),
+        history: workspace.get_history_dir().display().to_string(),
+    })
+}
+
 /// Read a file from the workspace
 #[tauri::command]
 pub async fn read_workspace_file(
@@ -96,16 +543,93 @@ pub async fn save_workspace_file(
         .map_err(|e| format!("Failed to write file: {}", e))
 }
 
-/// List all chat conversations
+/// Undo the agent's most recent file edit
+#[tauri::command]
+pub async fn undo_last_edit(
+    state: State<'_, AppState>,
+    app: AppHandle
+) -> Result<String, String> {
+    let workspace = state.get_workspace()
+        .ok_or_else(|| "Workspace not initialized".to_string())?;
+    
+    let path = workspace.undo_last_edit()?;
+    let path_str = path.to_string_lossy().to_string();
+    
+    app.emit("agent-file-access", serde_json::json!({
+        "action": "undo",
+        "path": path_str
+    })).ok();
+    
+    Ok(path_str)
+}
+
+/// List chat conversations, optionally only those with a tag or marked favorite
 #[tauri::command]
 pub async fn list_conversations(
+    tag: Option<String>,
+    favorites_only: Option<bool>,
     state: State<'_, AppState>
 ) -> Result<Vec<ConversationMeta>, String> {
     let workspace = state.get_workspace()
         .ok_or_else(|| "Workspace not initialized".to_string())?;
     
     let history_manager = HistoryManager::new(workspace.get_history_dir());
-    Ok(history_manager.list_conversations())
+    Ok(history_manager.list_conversations_filtered(tag.as_deref(), favorites_only.unwrap_or(false)))
+}
+
+/// Replace the tags on a conversation
+#[tauri::command]
+pub async fn tag_conversation(
+    id: String,
+    tags: Vec<String>,
+    state: State<'_, AppState>
+) -> Result<ConversationMeta, String> {
+    let workspace = state.get_workspace()
+        .ok_or_else(|| "Workspace not initialized".to_string())?;
+    
+    let history_manager = HistoryManager::new(workspace.get_history_dir());
+    history_manager.tag_conversation(&id, tags)
+}
+
+/// Toggle a conversation's favorite flag, returning the new value
+#[tauri::command]
+pub async fn toggle_favorite(
+    id: String,
+    state: State<'_, AppState>
+) -> Result<bool, String> {
+    let workspace = state.get_workspace()
+        .ok_or_else(|| "Workspace not initialized".to_string())?;
+    
+    let history_manager = HistoryManager::new(workspace.get_history_dir());
+    history_manager.toggle_favorite(&id)
+}
+
+/// Save a retention policy for conversation history and apply it right away
+/// Favorited and tagged conversations are kept; returns (and emits as `history-pruned`)
+/// how many conversations were deleted
+#[tauri::command]
+pub async fn set_retention_policy(
+    max_conversations: Option<usize>,
+    max_age_days: Option<u64>,
+    state: State<'_, AppState>,
+    app: AppHandle,
+) -> Result<usize, String> {
+    println!("[Command] set_retention_policy: max_conversations={:?}, max_age_days={:?}", max_conversations, max_age_days);
+    
+    if max_conversations == Some(0) || max_age_days == Some(0) {
+        return Err("Retention limits must be at least 1 (leave empty for no limit)".to_string());
+    }
+    
+    let policy = RetentionPolicy { max_conversations, max_age_days };
+    let mut settings = crate::state::settings::get();
+    settings.history_retention = policy.clone();
+    crate::state::settings::update(settings)?;
+    
+    let workspace = state.get_workspace()
+        .ok_or_else(|| "Workspace not initialized".to_string())?;
+    let pruned = HistoryManager::new(workspace.get_history_dir()).apply_retention(&policy, None);
+    app.emit("history-pruned", pruned).ok();
+    Ok(pruned)
 }
 
 /// Delete a conversation
@@ -121,18 +645,82 @@ pub async fn delete_conversation(
     history_manager.delete_conversation(&id)
 }
 
+/// Recover the readable part of a conversation listed as corrupt and save it as a valid file
+#[tauri::command]
+pub async fn repair_conversation(
+    id: String,
+    state: State<'_, AppState>
+) -> Result<ConversationMeta, String> {
+    println!("[Command] repair_conversation: {}", id);
+    let workspace = state.get_workspace()
+        .ok_or_else(|| "Workspace not initialized".to_string())?;
+    
+    let history_manager = HistoryManager::new(workspace.get_history_dir());
+    history_manager.repair_conversation(&id)
+}
+
 /// Load a specific conversation by ID
+/// If it prefers another provider than the active one, see `apply_provider_preference`
 #[tauri::command]
 pub async fn load_conversation(
     id: String,
+    state: State<'_, AppState>,
+    app: AppHandle
+) -> Result<Conversation, String> {
+    let workspace = state.get_workspace()
+        .ok_or_else(|| "Workspace not initialized".to_string())?;
+    
+    let history_manager = HistoryManager::new(workspace.get_history_dir());
+    let conversation = history_manager.load_conversation(&id)
+        .ok_or_else(|| format!("Conversation {} not found", id))?;
+    
+    apply_provider_preference(&conversation, &state, &app).await;
+    Ok(conversation)
+}
+
+/// Compare a loaded conversation's provider preference with the active provider
+/// Switches automatically when `auto_switch_conversation_provider` is set, otherwise
+/// emits `provider-preference-mismatch` so the UI can offer to switch
+async fn apply_provider_preference(conversation: &Conversation, state: &AppState, app: &AppHandle) {
+    let Some(preferred) = conversation.provider_preference.as_deref() else {
+        return;
+    };
+    let active = if state.is_offline() { "offline" } else { "cloud" };
+    if preferred == active {
+        return;
+    }
+    
+    if crate::state::settings::get().auto_switch_conversation_provider {
+        println!("[Command] Conversation {} prefers {} mode, switching", conversation.id, preferred);
+        match crate::commands::setup_cmds::switch_connection_mode(preferred.to_string(), state, app).await {
+            Ok(()) => return,
+            Err(e) => println!("[Command] Switching to {} mode failed: {}", preferred, e),
+        }
+    }
+    
+    app.emit("provider-preference-mismatch", serde_json::json!({
+        "conversation_id": conversation.id,
+        "preferred": preferred,
+        "active": active,
+    })).ok();
+}
+
+/// Import a conversation exported as json or markdown, returning its new id
+#[tauri::command]
+pub async fn import_conversation(
+    data: String,
+    format: String,
     state: State<'_, AppState>
-) -> Result<crate::agent::history::Conversation, String> {
+) -> Result<String, String> {
     let workspace = state.get_workspace()
         .ok_or_else(|| "Workspace not initialized".to_string())?;
     
+    let conversation = crate::agent::history::import_conversation(&data, &format)?;
     let history_manager = HistoryManager::new(workspace.get_history_dir());
-    history_manager.load_conversation(&id)
-        .ok_or_else(|| format!("Conversation {} not found", id))
+    history_manager.save_conversation(&conversation)?;
+    
+    println!("[Command] Imported conversation {} ({} messages)", conversation.id, conversation.messages.len());
+    Ok(conversation.id)
 }
 
 /// Export a conversation in specified format (json or markdown)
@@ -161,7 +749,11 @@ pub async fn export_conversation(
             md.push_str("---\n\n");
             
             for msg in &conversation.messages {
-                let role = if msg.role == "user" { "**User**" } else { "**Assistant**" };
+                let role = match (msg.role.as_str(), &msg.model_tier) {
+                    ("user", _) => "**User**".to_string(),
+                    (_, Some(tier)) => format!("**Assistant** ({})", tier),
+                    (_, None) => "**Assistant**".to_string(),
+                };
                 md.push_str(&format!("{}:\n\n{}\n\n---\n\n", role, msg.content));
             }
             
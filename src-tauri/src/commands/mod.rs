@@ -1,3 +1,4 @@
 pub mod agent_cmds;
 pub mod setup_cmds;
+pub mod settings_cmds;
 pub mod update_cmds;
@@ -0,0 +1,305 @@
+//! Settings Commands
+//!
+//! Tauri commands for reading and updating persisted user settings, and for the
+//! recent-log view used in bug reports.
+
+use tauri::State;
+
+use crate::agent::actor::AgentCommand;
+use crate::agent::memory::ContextStrategy;
+use serde::Serialize;
+
+use crate::providers::cloud::{key_hint, normalize_gemini_endpoint, KeyRanking};
+use crate::state::app_state::AppState;
+use crate::state::logs;
+use crate::state::settings::{self, ApiKeyProfile, AppSettings};
+
+/// Get the current settings
+/// Key profiles come back by name only - their keys are never serialized
+#[tauri::command]
+pub fn get_settings() -> AppSettings {
+    settings::get()
+}
+
+/// Replace and persist the settings, rejecting the whole payload if any field is invalid
+/// Key profiles are kept as they are; they change through the key profile commands.
+/// Changed fields take effect as through their own setters (see `apply_changed_settings`)
+#[tauri::command]
+pub async fn update_settings(
+    mut new_settings: AppSettings,
+    state: State<'_, AppState>,
+) -> Result<AppSettings, String> {
+    let previous = settings::get();
+    new_settings.key_profiles = previous.key_profiles.clone();
+    {
+        let _downloads = if new_settings.storage_dir != previous.storage_dir {
+            Some(crate::commands::setup_cmds::lock_storage_for_move()?)
+        } else {
+            None
+        };
+        settings::update(new_settings)?;
+    }
+    
+    apply_changed_settings(&previous, &state).await?;
+    Ok(settings::get())
+}
+
+/// Apply what a settings change needs beyond saving it, comparing with `previous`:
+/// a moved storage directory, the log capture level, and a cloud provider rebuild for a
+/// new proxy or Gemini endpoint
+pub(crate) async fn apply_changed_settings(previous: &AppSettings, state: &AppState) -> Result<(), String> {
+    let current = settings::get();
+    if current.storage_dir != previous.storage_dir {
+        crate::commands::setup_cmds::storage_dir_changed();
+    }
+    if current.log_capture_level != previous.log_capture_level {
+        logs::set_capture_level(logs::parse_level(&current.log_capture_level)?);
+    }
+    
+    let provider_changed = current.proxy_url != previous.proxy_url
+        || current.gemini_endpoint != previous.gemini_endpoint
+        || current.native_tool_calling != previous.native_tool_calling
+        || current.native_tool_model != previous.native_tool_model;
+    if provider_changed && !state.is_offline() {
+        let tx = state.agent_tx.lock().await;
+        tx.send(AgentCommand::SetConnectionMode { is_offline: false })
+            .await
+            .map_err(|e| format!("Failed to reload cloud provider: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Point Gemini API calls at another base URL (None or empty restores the default)
+/// Recreates the cloud provider so its clients pick up the change
+#[tauri::command]
+pub async fn set_gemini_endpoint(
+    url: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Option<String>, String> {
+    println!("[Command] set_gemini_endpoint: {:?}", url);
+    let endpoint = match url.filter(|u| !u.trim().is_empty()) {
+        Some(url) => Some(normalize_gemini_endpoint(&url)?),
+        None => None,
+    };
+    
+    let mut new_settings = settings::get();
+    new_settings.gemini_endpoint = endpoint.clone();
+    settings::update(new_settings)?;
+    
+    if !state.is_offline() {
+        let tx = state.agent_tx.lock().await;
+        tx.send(AgentCommand::SetConnectionMode { is_offline: false })
+            .await
+            .map_err(|e| format!("Failed to reload cloud provider: {}", e))?;
+    }
+    
+    Ok(endpoint)
+}
+
+/// Ping every API key and make the cloud clients prefer the fastest working ones
+/// Returns the keys best first with their latency, for showing in settings
+#[tauri::command]
+pub async fn rank_api_keys(state: State<'_, AppState>) -> Result<Vec<KeyRanking>, String> {
+    println!("[Command] rank_api_keys");
+    let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+    
+    {
+        let tx = state.agent_tx.lock().await;
+        tx.send(AgentCommand::RankApiKeys { reply: reply_tx })
+            .await
+            .map_err(|e| format!("Failed to send command: {}", e))?;
+    }
+    
+    // Each ping is bounded at 10s; the rest covers a task finishing first
+    match tokio::time::timeout(std::time::Duration::from_secs(30), reply_rx).await {
+        Ok(Ok(rankings)) if rankings.is_empty() => Err("No API keys configured".to_string()),
+        Ok(Ok(rankings)) => Ok(rankings),
+        Ok(Err(_)) => Err("Agent dropped the ranking request".to_string()),
+        Err(_) => Err("Agent is busy - try again when the current task finishes".to_string()),
+    }
+}
+
+/// A saved key profile as shown in settings - the keys themselves never leave the backend
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyProfileInfo {
+    pub name: String,
+    /// Last four characters of each key
+    pub key_hints: Vec<String>,
+    pub active: bool,
+}
+
+/// Save a named set of API keys, replacing any profile with the same name
+/// Replacing the active profile switches the agent to the new keys
+#[tauri::command]
+pub async fn add_key_profile(
+    name: String,
+    keys: Vec<String>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let name = name.trim().to_string();
+    let keys: Vec<String> = keys.into_iter()
+        .map(|k| k.trim().to_string())
+        .filter(|k| !k.is_empty())
+        .collect();
+    println!("[Command] add_key_profile: {} ({} keys)", name, keys.len());
+    if name.is_empty() {
+        return Err("Profile name cannot be empty".to_string());
+    }
+    if keys.is_empty() {
+        return Err("A profile needs at least one API key".to_string());
+    }
+    
+    let mut new_settings = settings::get();
+    new_settings.key_profiles.retain(|p| p.name != name);
+    new_settings.key_profiles.push(ApiKeyProfile { name: name.clone(), keys: keys.clone() });
+    let is_active = new_settings.active_key_profile.as_deref() == Some(name.as_str());
+    settings::update(new_settings)?;
+    
+    if is_active {
+        let tx = state.agent_tx.lock().await;
+        tx.send(AgentCommand::SetApiKeys { keys })
+            .await
+            .map_err(|e| format!("Failed to send command: {}", e))?;
+    }
+    Ok(())
+}
+
+/// List the saved key profiles
+#[tauri::command]
+pub fn list_key_profiles() -> Vec<KeyProfileInfo> {
+    let settings = settings::get();
+    settings.key_profiles.iter()
+        .map(|p| KeyProfileInfo {
+            name: p.name.clone(),
+            key_hints: p.keys.iter().map(|k| key_hint(k)).collect(),
+            active: settings.active_key_profile.as_deref() == Some(p.name.as_str()),
+        })
+        .collect()
+}
+
+/// Switch the agent to a saved profile's keys; they are also used on the next launch
+#[tauri::command]
+pub async fn activate_key_profile(name: String, state: State<'_, AppState>) -> Result<(), String> {
+    println!("[Command] activate_key_profile: {}", name);
+    let mut new_settings = settings::get();
+    let keys = new_settings.key_profiles.iter()
+        .find(|p| p.name == name)
+        .map(|p| p.keys.clone())
+        .ok_or_else(|| format!("No key profile named '{}'", name))?;
+    new_settings.active_key_profile = Some(name);
+    settings::update(new_settings)?;
+    
+    let tx = state.agent_tx.lock().await;
+    tx.send(AgentCommand::SetApiKeys { keys })
+        .await
+        .map_err(|e| format!("Failed to send command: {}", e))
+}
+
+/// Turn automatic context summarization on or off
+/// When off, the summarizer only runs through `summarize_context`
+#[tauri::command]
+pub fn set_auto_summarize(enabled: bool) -> Result<(), String> {
+    println!("[Command] set_auto_summarize: {}", enabled);
+    let mut new_settings = settings::get();
+    new_settings.auto_summarize = enabled;
+    settings::update(new_settings)
+}
+
+/// Replace the patterns for files hidden from the agent (gitignore syntax)
+/// An empty list lets the agent see every file gitignore doesn't exclude
+#[tauri::command]
+pub fn set_sensitive_patterns(patterns: Vec<String>) -> Result<Vec<String>, String> {
+    println!("[Command] set_sensitive_patterns: {:?}", patterns);
+    let patterns: Vec<String> = patterns.into_iter()
+        .map(|p| p.trim().to_string())
+        .filter(|p| !p.is_empty())
+        .collect();
+    
+    let mut new_settings = settings::get();
+    new_settings.sensitive_patterns = patterns.clone();
+    settings::update(new_settings)?;
+    Ok(patterns)
+}
+
+/// The last `n` captured log lines, oldest first, for attaching to bug reports
+#[tauri::command]
+pub fn get_recent_logs(n: usize) -> Vec<String> {
+    logs::recent(n)
+}
+
+/// Set the most verbose level kept for `get_recent_logs`
+#[tauri::command]
+pub fn set_log_capture_level(level: String) -> Result<(), String> {
+    println!("[Command] set_log_capture_level: {}", level);
+    let filter = logs::parse_level(&level)?;
+    let mut new_settings = settings::get();
+    new_settings.log_capture_level = level.trim().to_lowercase();
+    settings::update(new_settings)?;
+    logs::set_capture_level(filter);
+    Ok(())
+}
+
+/// Turn the `agent-raw-chunk` debug stream of unmodified model output on or off
+/// Off by default since it roughly doubles event traffic while streaming
+#[tauri::command]
+pub fn set_raw_stream_debug(enabled: bool) -> Result<(), String> {
+    println!("[Command] set_raw_stream_debug: {}", enabled);
+    let mut new_settings = settings::get();
+    new_settings.debug_raw_stream = enabled;
+    settings::update(new_settings)
+}
+
+/// Show or hide the model's live <thinking> stream
+/// Thinking is stripped from the final message either way
+#[tauri::command]
+pub fn set_show_thinking(enabled: bool) -> Result<(), String> {
+    println!("[Command] set_show_thinking: {}", enabled);
+    let mut new_settings = settings::get();
+    new_settings.show_thinking = enabled;
+    settings::update(new_settings)
+}
+
+/// Choose which messages are sent to the model once a conversation grows
+/// Applies from the next request
+#[tauri::command]
+pub fn set_context_strategy(strategy: ContextStrategy) -> Result<(), String> {
+    println!("[Command] set_context_strategy: {:?}", strategy);
+    let mut new_settings = settings::get();
+    new_settings.context_strategy = strategy;
+    settings::update(new_settings)
+}
+
+/// Set standing rules put ahead of the chat and turbo system prompts (None or empty clears them)
+/// Applies from the next request
+#[tauri::command]
+pub fn set_system_prompt_prefix(prefix: Option<String>) -> Result<(), String> {
+    let prefix = prefix.map(|p| p.trim().to_string()).filter(|p| !p.is_empty());
+    println!("[Command] set_system_prompt_prefix: {} chars", prefix.as_ref().map_or(0, |p| p.chars().count()));
+    let mut new_settings = settings::get();
+    new_settings.system_prompt_prefix = prefix;
+    settings::update(new_settings)
+}
+
+/// Set the proxy for all outbound HTTP (None or empty clears it)
+/// Recreates the cloud provider so its HTTP clients pick up the change
+#[tauri::command]
+pub async fn set_proxy(
+    url: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    let url = url.map(|u| u.trim().to_string()).filter(|u| !u.is_empty());
+    println!("[Command] set_proxy: {:?}", url);
+    
+    let mut new_settings = settings::get();
+    new_settings.proxy_url = url;
+    settings::update(new_settings)?;
+    
+    if !state.is_offline() {
+        let tx = state.agent_tx.lock().await;
+        tx.send(AgentCommand::SetConnectionMode { is_offline: false })
+            .await
+            .map_err(|e| format!("Failed to reload cloud provider: {}", e))?;
+    }
+    
+    Ok(())
+}
@@ -2,20 +2,27 @@
 //!
 //! Tauri commands for GPU detection, binary/model download, and mode switching.
 
+use std::path::PathBuf;
+
 use once_cell::sync::Lazy;
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
 use tauri::{AppHandle, Emitter, State};
 
 use crate::hardware::{detect_gpu, GpuInfo};
+use crate::setup::backup::{export_backup, import_backup, BackupEntry, ImportSummary};
 use crate::setup::downloader::{
-    download_file_with_resume, get_binaries_url, get_model_url, get_tokenizer_url,
-    load_download_state, DownloadController,
+    cleanup_download_dir, download_file_with_resume, get_binaries_url, get_model_url, get_tokenizer_url,
+    get_total_download_size, load_download_state, speed_history, DownloadController, DownloadFailure, DownloadOutcome,
+    DownloadSizeEstimate, SpeedSample,
 };
 use crate::setup::extractor::{cleanup_temp_file, extract_zip};
-use crate::setup::paths::{ensure_directories, get_binaries_dir, get_model_path, get_tokenizer_path, get_temp_download_path, SetupStatus};
+use crate::setup::paths::{ensure_directories, get_settings_path, get_binaries_dir, get_model_path, get_models_dir, get_storage_dir as storage_dir, get_tokenizer_path, get_temp_dir, get_temp_download_path, list_model_files, SetupStatus};
 use crate::state::app_state::AppState;
 
+/// Temp downloads untouched for this long are treated as abandoned
+const DEFAULT_DOWNLOAD_MAX_AGE_HOURS: u64 = 24;
+
 /// Global download controller for pause/resume
 static DOWNLOAD_CONTROLLER: Lazy<RwLock<Option<DownloadController>>> = Lazy::new(|| RwLock::new(None));
 
@@ -54,6 +61,14 @@ impl From<SetupStatus> for SetupStatusResult {
     }
 }
 
+/// Local GGUF model entry for frontend
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LocalModelInfo {
+    pub filename: String,
+    pub size_bytes: u64,
+    pub active: bool,
+}
+
 /// Detect GPU hardware
 #[tauri::command]
 pub fn detect_gpu_cmd() -> Result<GpuDetectionResult, String> {
@@ -71,9 +86,27 @@ pub fn check_setup_status() -> Result<SetupStatusResult, String> {
     Ok(status.into())
 }
 
+/// Get the combined size of the binaries, model and tokenizer before downloading
+#[tauri::command]
+pub async fn get_setup_download_size(gpu_type: String) -> Result<DownloadSizeEstimate, String> {
+    println!("[Command] get_setup_download_size: gpu_type={}", gpu_type);
+    let estimate = get_total_download_size(&gpu_type)
+        .await
+        .map_err(|e| format!("Failed to get download size: {}", e))?;
+    println!("[Command] Setup download size: {} bytes", estimate.total_bytes);
+    Ok(estimate)
+}
+
+/// Emit `download-error` for a real failure and return the message for the command result
+fn download_failed(app: &AppHandle, step: &str, error: String) -> String {
+    println!("[Command] {} download failed: {}", step, error);
+    app.emit("download-error", &DownloadFailure { step: step.to_string(), error: error.clone() }).ok();
+    error
+}
+
 /// Download GPU-specific binaries with pause/resume support
 #[tauri::command]
-pub async fn download_binaries(gpu_type: String, app: AppHandle) -> Result<(), String> {
+pub async fn download_binaries(gpu_type: String, app: AppHandle) -> Result<DownloadOutcome, String> {
     println!("[Command] download_binaries: gpu_type={}", gpu_type);
 
     // Ensure directories exist
@@ -105,27 +138,28 @@ pub async fn download_binaries(gpu_type: String, app: AppHandle) -> Result<(), S
     match download_file_with_resume(url, &temp_zip, "binaries", &app, &controller, resume_bytes).await {
         Ok(()) => {
             // Extract to binaries directory
-            extract_zip(&temp_zip, &dest_dir).map_err(|e| format!("Extraction failed: {}", e))?;
+            extract_zip(&temp_zip, &dest_dir)
+                .map_err(|e| download_failed(&app, "binaries", format!("Extraction failed: {}", e)))?;
             cleanup_temp_file(&temp_zip).ok();
             println!("[Command] Binaries download complete");
-            Ok(())
+            Ok(DownloadOutcome::Completed)
         }
         Err(crate::setup::downloader::DownloadError::Paused) => {
             println!("[Command] Binaries download paused");
-            Err("Download paused".to_string())
+            Ok(DownloadOutcome::Paused)
         }
         Err(crate::setup::downloader::DownloadError::Cancelled) => {
             cleanup_temp_file(&temp_zip).ok();
             println!("[Command] Binaries download cancelled");
             Err("Download cancelled".to_string())
         }
-        Err(e) => Err(format!("Download failed: {}", e)),
+        Err(e) => Err(download_failed(&app, "binaries", format!("Download failed: {}", e))),
     }
 }
 
 /// Download the AI model with pause/resume support
 #[tauri::command]
-pub async fn download_model(app: AppHandle) -> Result<(), String> {
+pub async fn download_model(app: AppHandle) -> Result<DownloadOutcome, String> {
     println!("[Command] download_model");
 
     // Ensure directories exist
@@ -161,27 +195,45 @@ pub async fn download_model(app: AppHandle) -> Result<(), String> {
             let tok_url = get_tokenizer_url();
             let tok_dest = get_tokenizer_path();
             
-            println!("[Command] Starting tokenizer download");
-            // Simple download for tokenizer (it's small, no resume needed really, but consistent)
-            match download_file_with_resume(tok_url, &tok_dest, "tokenizer", &app, &controller, 0).await {
+            // Resume a partial tokenizer the same way as the model
+            let tok_resume_bytes = if let Some(state) = load_download_state("tokenizer").await {
+                if !state.is_complete && tok_dest.exists() {
+                    state.downloaded_bytes
+                } else {
+                    0
+                }
+            } else {
+                0
+            };
+            
+            println!("[Command] Starting tokenizer download (resume from {} bytes)", tok_resume_bytes);
+            match download_file_with_resume(tok_url, &tok_dest, "tokenizer", &app, &controller, tok_resume_bytes).await {
                 Ok(()) => {
                     println!("[Command] Tokenizer download complete");
                     app.emit("setup-complete", ()).ok();
-                    Ok(())
+                    Ok(DownloadOutcome::Completed)
                 }
-                Err(e) => Err(format!("Tokenizer download failed: {}", e)),
+                Err(crate::setup::downloader::DownloadError::Paused) => {
+                    println!("[Command] Tokenizer download paused");
+                    Ok(DownloadOutcome::Paused)
+                }
+                Err(crate::setup::downloader::DownloadError::Cancelled) => {
+                    println!("[Command] Tokenizer download cancelled");
+                    Err("Download cancelled".to_string())
+                }
+                Err(e) => Err(download_failed(&app, "tokenizer", format!("Tokenizer download failed: {}", e))),
             }
         }
         Err(crate::setup::downloader::DownloadError::Paused) => {
             println!("[Command] Model download paused");
-            Err("Download paused".to_string())
+            Ok(DownloadOutcome::Paused)
         }
         Err(crate::setup::downloader::DownloadError::Cancelled) => {
             // Don't delete partial model - allow resume
             println!("[Command] Model download cancelled");
             Err("Download cancelled".to_string())
         }
-        Err(e) => Err(format!("Download failed: {}", e)),
+        Err(e) => Err(download_failed(&app, "model", format!("Download failed: {}", e))),
     }
 }
 
@@ -224,8 +276,227 @@ pub fn cancel_download() -> Result<(), String> {
     }
 }
 
+/// Recent download speed samples (about one per second) for a speed-over-time chart
+/// New samples arrive as `download-speed-sample` events while a download runs
+#[tauri::command]
+pub fn get_download_speed_history() -> Vec<SpeedSample> {
+    speed_history()
+}
+
+/// Abort the local model load in progress, e.g. when the user changes their mind
+/// The agent reports `model-load-complete` "cancelled" once the load has stopped
+#[tauri::command]
+pub fn cancel_model_load() {
+    println!("[Command] cancel_model_load");
+    crate::providers::local::cancel_model_load();
+}
+
+/// Remove abandoned partial downloads and orphaned resume state from the temp directory
+/// Refuses while a download is running; returns the bytes reclaimed
+#[tauri::command]
+pub fn cleanup_downloads(max_age_hours: Option<u64>) -> Result<u64, String> {
+    println!("[Command] cleanup_downloads: max_age_hours={:?}", max_age_hours);
+    
+    // Hold the lock so no download can start while files are being removed
+    let ctrl = DOWNLOAD_CONTROLLER.read();
+    if ctrl.as_ref().map_or(false, |c| c.is_running()) {
+        return Err("A download is in progress - pause or cancel it first".to_string());
+    }
+    
+    let max_age = std::time::Duration::from_secs(max_age_hours.unwrap_or(DEFAULT_DOWNLOAD_MAX_AGE_HOURS) * 3600);
+    let reclaimed = cleanup_download_dir(&get_temp_dir(), max_age);
+    println!("[Command] Reclaimed {} bytes of download leftovers", reclaimed);
+    Ok(reclaimed)
+}
+
+/// Locations of the app data included in a backup
+/// Models and binaries are left out, and so are API keys (api_keys.json)
+fn backup_entries(state: &AppState) -> Vec<BackupEntry> {
+    let mut entries = vec![BackupEntry { name: "settings.json", path: get_settings_path() }];
+    if let Some(workspace) = state.get_workspace() {
+        entries.push(BackupEntry { name: "history", path: workspace.get_history_dir().to_path_buf() });
+        entries.push(BackupEntry { name: "rag", path: workspace.get_root_dir().join("rag") });
+    }
+    entries
+}
+
+/// Run a blocking backup step while the agent has its RAG store closed and history idle
+/// Refused while a task is running. With `replaces_data` the agent afterwards drops its
+/// in-memory conversation rather than saving it over the restored files
+async fn with_storage_suspended<T: Send + 'static>(
+    state: &AppState,
+    replaces_data: bool,
+    step: impl FnOnce() -> Result<T, String> + Send + 'static,
+) -> Result<T, String> {
+    use crate::agent::actor::AgentCommand;
+    
+    if !state.try_begin_task() {
+        return Err("The agent is running a task - wait for it to finish or cancel it first".to_string());
+    }
+    
+    let result = async {
+        let (held_tx, held_rx) = tokio::sync::oneshot::channel();
+        let (release_tx, release_rx) = tokio::sync::oneshot::channel();
+        {
+            let tx = state.agent_tx.lock().await;
+            tx.send(AgentCommand::SuspendStorage { held: held_tx, release: release_rx })
+                .await
+                .map_err(|e| format!("Failed to pause the agent: {}", e))?;
+        }
+        held_rx.await.map_err(|_| "The agent stopped before its storage was paused".to_string())?;
+        
+        let result = tokio::task::spawn_blocking(step)
+            .await
+            .map_err(|e| format!("Backup task failed: {}", e))
+            .and_then(|result| result);
+        // Even a failed import may have replaced some files
+        let _ = release_tx.send(replaces_data);
+        result
+    }.await;
+    
+    state.finish_task();
+    result
+}
+
+/// Back up conversations, settings and the RAG store into one zip at `dest`
+/// Returns the number of files written
+#[tauri::command]
+pub async fn export_app_data(
+    dest: PathBuf,
+    state: State<'_, AppState>,
+) -> Result<usize, String> {
+    println!("[Command] export_app_data: {}", dest.display());
+    let entries = backup_entries(&state);
+    with_storage_suspended(&state, false, move || export_backup(&dest, &entries)).await
+}
+
+/// Restore a backup made by `export_app_data`
+/// Files that already exist are kept unless `overwrite` is set
+#[tauri::command]
+pub async fn import_app_data(
+    src: PathBuf,
+    overwrite: Option<bool>,
+    state: State<'_, AppState>,
+) -> Result<ImportSummary, String> {
+    let overwrite = overwrite.unwrap_or(false);
+    println!("[Command] import_app_data: {} overwrite={}", src.display(), overwrite);
+    let entries = backup_entries(&state);
+    let previous = crate::state::settings::get();
+    let summary = with_storage_suspended(&state, true, move || import_backup(&src, &entries, overwrite)).await?;
+    
+    crate::state::settings::reload()?;
+    crate::commands::settings_cmds::apply_changed_settings(&previous, &state).await?;
+    Ok(summary)
+}
+
+/// List downloaded GGUF models in the models directory
+#[tauri::command]
+pub fn list_local_models() -> Result<Vec<LocalModelInfo>, String> {
+    println!("[Command] list_local_models");
+    let active_path = crate::providers::local::get_default_model_path();
+
+    let models = list_model_files()
+        .into_iter()
+        .filter_map(|path| {
+            let filename = path.file_name()?.to_string_lossy().to_string();
+            let size_bytes = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            Some(LocalModelInfo {
+                filename,
+                size_bytes,
+                active: path == active_path,
+            })
+        })
+        .collect();
+
+    Ok(models)
+}
+
+/// Select which local model to use
+/// If offline mode is active, the current model is unloaded and the new one loaded
+#[tauri::command]
+pub async fn select_local_model(
+    filename: String,
+    state: State<'_, AppState>,
+) -> Result<(), String> {
+    use crate::agent::actor::AgentCommand;
+
+    println!("[Command] select_local_model: {}", filename);
+
+    // Only plain file names inside the models directory are allowed
+    if filename.contains('/') || filename.contains('\\') || filename.contains("..") {
+        return Err(format!("Invalid model file name: {}", filename));
+    }
+
+    let path = get_models_dir().join(&filename);
+    let is_gguf = path.extension().map_or(false, |e| e.eq_ignore_ascii_case("gguf"));
+    if !is_gguf || !path.is_file() {
+        return Err(format!("Model not found: {}", filename));
+    }
+
+    crate::providers::local::set_selected_model_path(path);
+
+    if state.is_offline() {
+        // Re-enter offline mode so the actor unloads the old model and loads the new one
+        let tx = state.agent_tx.lock().await;
+        tx.send(AgentCommand::SetConnectionMode { is_offline: true })
+            .await
+            .map_err(|e| format!("Failed to send model switch: {}", e))?;
+    }
+
+    Ok(())
+}
+
+/// Get the directory models and binaries are downloaded to
+#[tauri::command]
+pub fn get_storage_dir() -> Result<String, String> {
+    Ok(storage_dir().display().to_string())
+}
+
+/// Move where models and binaries are downloaded (None or empty restores the default)
+/// Existing files are not moved; returns the setup status at the new location so
+/// downloads already present there are picked up
+#[tauri::command]
+pub fn set_storage_dir(path: Option<String>) -> Result<SetupStatusResult, String> {
+    println!("[Command] set_storage_dir: {:?}", path);
+    
+    let _downloads = lock_storage_for_move()?;
+    let path = path.map(|p| p.trim().to_string()).filter(|p| !p.is_empty());
+    let mut settings = crate::state::settings::get();
+    settings.storage_dir = path;
+    crate::state::settings::update(settings)?;
+    
+    storage_dir_changed();
+    
+    let status = SetupStatus::check();
+    println!("[Command] Storage now at {} (binaries={}, model={})", storage_dir().display(), status.binaries_ok, status.model_ok);
+    Ok(status.into())
+}
+
+/// Refuse to move storage while a download is running
+/// The returned guard keeps a new download from starting in the old location until it is dropped
+pub(crate) fn lock_storage_for_move() -> Result<parking_lot::RwLockReadGuard<'static, Option<DownloadController>>, String> {
+    let ctrl = DOWNLOAD_CONTROLLER.read();
+    if ctrl.as_ref().map_or(false, |c| c.is_running()) {
+        return Err("A download is in progress - pause or cancel it before moving storage".to_string());
+    }
+    Ok(ctrl)
+}
+
+/// Follow a storage directory change: forget a model picked from the old location
+/// and put the new binaries directory on PATH
+pub(crate) fn storage_dir_changed() {
+    // A model picked from the old location would no longer be found
+    if !crate::providers::local::get_default_model_path().starts_with(get_models_dir()) {
+        crate::providers::local::clear_selected_model_path();
+    }
+    if let Err(e) = crate::setup::paths::add_binaries_to_path() {
+        eprintln!("[Command] Failed to add binaries to PATH: {}", e);
+    }
+}
+
 /// Set connection mode (cloud or offline)
 /// This triggers model loading/unloading and switches the agent provider
+/// The choice is also remembered as the open conversation's provider preference
 #[tauri::command]
 pub async fn set_connection_mode(
     mode: String,
@@ -235,6 +506,21 @@ pub async fn set_connection_mode(
     use crate::agent::actor::AgentCommand;
     
     println!("[Command] set_connection_mode: mode={}", mode);
+    switch_connection_mode(mode.clone(), &state, &app).await?;
+    
+    let tx = state.agent_tx.lock().await;
+    tx.send(AgentCommand::SetProviderPreference { mode })
+        .await
+        .map_err(|e| format!("Failed to save provider preference: {}", e))
+}
+
+/// Switch the agent provider to "cloud" or "offline" and remember it for the next launch
+pub(crate) async fn switch_connection_mode(
+    mode: String,
+    state: &AppState,
+    app: &AppHandle,
+) -> Result<(), String> {
+    use crate::agent::actor::AgentCommand;
 
     match mode.as_str() {
         "offline" => {
@@ -264,6 +550,9 @@ pub async fn set_connection_mode(
             app.emit("connection-mode-changed", "offline").ok();
         }
         "cloud" => {
+            // A model still loading would hold up the switch, and its memory, until it finished
+            crate::providers::local::cancel_model_load();
+            
             // Send command to agent to switch provider
             let tx = state.agent_tx.lock().await;
             tx.send(AgentCommand::SetConnectionMode { is_offline: false })
@@ -286,5 +575,12 @@ pub async fn set_connection_mode(
         }
     }
 
+    // Remember the choice so the next launch starts in the same mode
+    let mut new_settings = crate::state::settings::get();
+    new_settings.last_connection_mode = Some(mode);
+    if let Err(e) = crate::state::settings::update(new_settings) {
+        eprintln!("[Command] Failed to save connection mode: {}", e);
+    }
+
     Ok(())
 }
@@ -8,6 +8,16 @@ use tauri::{AppHandle, Emitter};
 use tauri_plugin_updater::UpdaterExt;
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use tokio::task::AbortHandle;
+
+/// Id and abort handle of the in-progress update download, if any
+/// Only the download is abortable - installing starts once the handle is released
+static UPDATE_DOWNLOAD: Lazy<Mutex<Option<(u64, AbortHandle)>>> = Lazy::new(|| Mutex::new(None));
+
+/// Source of download ids, so a finished download only releases its own handle
+static NEXT_DOWNLOAD_ID: AtomicU64 = AtomicU64::new(0);
 
 /// Update information returned to frontend
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -97,31 +107,69 @@ pub async fn download_update(app: AppHandle) -> Result<(), String> {
     let start_time = std::time::Instant::now();
     
     // Download with progress tracking - callbacks return ()
-    update.download_and_install(
-        move |chunk_len, content_len| {
-            let prev = downloaded_clone.fetch_add(chunk_len as u64, Ordering::SeqCst);
-            let current = prev + chunk_len as u64;
-            let total = content_len.unwrap_or(0) as u64;
-            let elapsed = start_time.elapsed().as_secs_f64();
-            let speed = if elapsed > 0.0 { current as f64 / elapsed } else { 0.0 };
-            let percent = if total > 0 { (current as f64 / total as f64) * 100.0 } else { 0.0 };
-            
-            let progress = UpdateProgress {
-                downloaded: current,
-                total,
-                percent,
-                speed,
+    // Runs in its own task so cancel_update_download can abort it; install runs after, unabortable
+    let downloader = update.clone();
+    let task = tokio::spawn(async move {
+        downloader.download(
+            move |chunk_len, content_len| {
+                let prev = downloaded_clone.fetch_add(chunk_len as u64, Ordering::SeqCst);
+                let current = prev + chunk_len as u64;
+                let total = content_len.unwrap_or(0) as u64;
+                let elapsed = start_time.elapsed().as_secs_f64();
+                let speed = if elapsed > 0.0 { current as f64 / elapsed } else { 0.0 };
+                let percent = if total > 0 { (current as f64 / total as f64) * 100.0 } else { 0.0 };
+                
+                let progress = UpdateProgress {
+                    downloaded: current,
+                    total,
+                    percent,
+                    speed,
+                };
+                
+                app_clone.emit("update-download-progress", progress).ok();
+            },
+            || {
+                // We handle restart separately via install_update
+            }
+        ).await
+    });
+    
+    let download_id = NEXT_DOWNLOAD_ID.fetch_add(1, Ordering::SeqCst);
+    {
+        let mut current = UPDATE_DOWNLOAD.lock();
+        if let Some((_, previous)) = current.replace((download_id, task.abort_handle())) {
+            previous.abort();
+        }
+    }
+    
+    let result = task.await;
+    // Release our handle only - a newer download may have replaced it. If it is already gone,
+    // this download was cancelled or superseded, even if it finished before the abort landed
+    let still_current = {
+        let mut current = UPDATE_DOWNLOAD.lock();
+        let ours = current.as_ref().map_or(false, |(id, _)| *id == download_id);
+        if ours {
+            current.take();
+        }
+        ours
+    };
+    
+    let result = match result {
+        Ok(result) if still_current => result,
+        Err(e) if !e.is_cancelled() => return Err(format!("Download task failed: {}", e)),
+        _ => {
+            println!("[Updater] Download cancelled");
+            let error = UpdateError {
+                code: "cancelled".to_string(),
+                message: "Update download was cancelled".to_string(),
+                retryable: true,
             };
-            
-            app_clone.emit("update-download-progress", progress).ok();
-        },
-        || {
-            // This callback is called before restart - return () not bool
-            // We handle restart separately via install_update
+            app.emit("update-error", error).ok();
+            return Err("Download cancelled".to_string());
         }
-    )
-    .await
-    .map_err(|e| {
+    };
+    
+    let bytes = result.map_err(|e| {
         let error = UpdateError {
             code: "download".to_string(),
             message: e.to_string(),
@@ -131,10 +179,35 @@ pub async fn download_update(app: AppHandle) -> Result<(), String> {
         format!("Download failed: {}", e)
     })?;
     
-    println!("[Updater] Download complete, ready to install");
+    println!("[Updater] Download complete, installing...");
+    update.install(bytes).map_err(|e| {
+        let error = UpdateError {
+            code: "install".to_string(),
+            message: e.to_string(),
+            retryable: false,
+        };
+        app.emit("update-error", error).ok();
+        format!("Install failed: {}", e)
+    })?;
+    
+    println!("[Updater] Update installed, ready to restart");
     Ok(())
 }
 
+/// Cancel the in-progress update download
+/// Returns false if no download was running
+#[tauri::command]
+pub fn cancel_update_download() -> bool {
+    match UPDATE_DOWNLOAD.lock().take() {
+        Some((_, handle)) => {
+            println!("[Updater] Cancelling download...");
+            handle.abort();
+            true
+        }
+        None => false,
+    }
+}
+
 /// Install the downloaded update and restart the app
 #[tauri::command]
 pub async fn install_update(app: AppHandle) -> Result<(), String> {
@@ -36,6 +36,37 @@ impl fmt::Display for ZoxError {
 
 impl std::error::Error for ZoxError {}
 
+impl ZoxError {
+    /// Whether trying the same request again could succeed
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ZoxError::Api(e) => e.retryable,
+            _ => false,
+        }
+    }
+
+    /// Whether offline mode was used with no local model downloaded
+    pub fn is_model_missing(&self) -> bool {
+        matches!(self, ZoxError::Model(e) if e.kind == ModelErrorKind::NotInstalled)
+    }
+
+    /// Short explanation for the UI, telling the user what to do about the error
+    pub fn user_message(&self) -> String {
+        match self {
+            ZoxError::Api(e) => match e.code {
+                ApiErrorCode::RateLimit => "Rate limited on every API key - wait a moment and try again".to_string(),
+                ApiErrorCode::InvalidKey => "API key rejected - check your Gemini API keys in Settings".to_string(),
+                ApiErrorCode::Network => "Network error - check your connection".to_string(),
+                ApiErrorCode::Timeout => "The model took too long to respond".to_string(),
+                ApiErrorCode::ServerError => "The model service is having problems - try again shortly".to_string(),
+                ApiErrorCode::Unknown => format!("API error: {}", e.message),
+            },
+            ZoxError::Model(e) => e.message.clone(),
+            other => other.to_string(),
+        }
+    }
+}
+
 /// API-related errors
 #[derive(Debug)]
 pub struct ApiError {
@@ -100,6 +131,34 @@ impl ApiError {
             retryable: true,
         }
     }
+
+    pub fn unknown(message: impl Into<String>) -> Self {
+        Self {
+            code: ApiErrorCode::Unknown,
+            message: message.into(),
+            retryable: false,
+        }
+    }
+
+    /// Classify a failed HTTP response from the Gemini API
+    pub fn from_status(status: u16, body: &str) -> Self {
+        let message = format!("HTTP {}: {}", status, body);
+        match status {
+            429 => Self::rate_limit(message),
+            401 | 403 => Self::invalid_key(message),
+            // Gemini reports a bad key as a 400 rather than a 401
+            400 if body.contains("API_KEY_INVALID") || body.contains("API key not valid") => Self::invalid_key(message),
+            408 | 504 => Self::timeout(message),
+            500..=599 => Self::server(message),
+            _ => Self::unknown(message),
+        }
+    }
+}
+
+impl From<ApiError> for ZoxError {
+    fn from(err: ApiError) -> Self {
+        ZoxError::Api(err)
+    }
 }
 
 /// Tool execution errors
@@ -141,8 +200,13 @@ pub struct ModelError {
     pub message: String,
 }
 
+/// Shown when offline mode is used before any local model has been downloaded
+pub const NO_LOCAL_MODEL_MESSAGE: &str = "No local model installed — download one in Setup";
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ModelErrorKind {
+    /// No model file on disk, so there is nothing to load
+    NotInstalled,
     NotLoaded,
     LoadFailed,
     InferenceFailed,
@@ -158,6 +222,13 @@ impl fmt::Display for ModelError {
 }
 
 impl ModelError {
+    pub fn not_installed() -> Self {
+        Self {
+            kind: ModelErrorKind::NotInstalled,
+            message: NO_LOCAL_MODEL_MESSAGE.into(),
+        }
+    }
+
     pub fn not_loaded() -> Self {
         Self {
             kind: ModelErrorKind::NotLoaded,
@@ -214,3 +285,30 @@ impl From<std::io::Error> for ZoxError {
 
 /// Result type alias for ZOX operations
 pub type ZoxResult<T> = Result<T, ZoxError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_codes_map_to_api_errors() {
+        assert_eq!(ApiError::from_status(429, "").code, ApiErrorCode::RateLimit);
+        assert_eq!(ApiError::from_status(403, "").code, ApiErrorCode::InvalidKey);
+        assert_eq!(ApiError::from_status(400, "API key not valid. Please pass a valid API key.").code, ApiErrorCode::InvalidKey);
+        assert_eq!(ApiError::from_status(400, "Invalid JSON payload").code, ApiErrorCode::Unknown);
+        assert_eq!(ApiError::from_status(504, "").code, ApiErrorCode::Timeout);
+        assert_eq!(ApiError::from_status(503, "").code, ApiErrorCode::ServerError);
+        
+        assert!(ZoxError::from(ApiError::from_status(503, "")).is_retryable());
+        assert!(!ZoxError::from(ApiError::from_status(403, "")).is_retryable());
+    }
+
+    #[test]
+    fn test_missing_model_is_actionable() {
+        let error = ZoxError::Model(ModelError::not_installed());
+        assert!(error.is_model_missing());
+        assert!(!error.is_retryable());
+        assert_eq!(error.user_message(), NO_LOCAL_MODEL_MESSAGE);
+        assert!(!ZoxError::Model(ModelError::not_loaded()).is_model_missing());
+    }
+}
@@ -15,15 +15,18 @@ pub use error::{ZoxError, ZoxResult};
 /// Desktop uses main.rs directly
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    use tauri::Manager;
+    use tauri::{Emitter, Listener, Manager};
     use state::app_state::{AppState, ApprovalState, ConnectionMode};
     use std::sync::Arc;
     use parking_lot::RwLock;
     use crate::agent::actor::spawn_agent;
 
+    state::logs::init();
+
     tauri::Builder::default()
         .plugin(tauri_plugin_fs::init())
         .plugin(tauri_plugin_updater::Builder::new().build())
+        .plugin(tauri_plugin_notification::init())
         .setup(|app| {
             // Load API keys from environment
             let keys = load_api_keys_for_mobile();
@@ -35,53 +38,131 @@ pub fn run() {
             // Create shared approval state
             let approval_state = Arc::new(RwLock::new(ApprovalState::new()));
 
-            // Spawn Agent Actor
+            // Spawn Agent Actor in the last-used connection mode
+            let (connection_mode, mode_warning) = ConnectionMode::from_settings();
             let (agent_tx, workspace) = spawn_agent(
                 app.handle().clone(), 
                 keys,
                 approval_state.clone(),
-                ConnectionMode::Cloud,  // Default to cloud mode
+                connection_mode,
             );
 
             // Create and manage state with shared approval_state
             let state = AppState::new(agent_tx, approval_state);
             state.set_workspace(workspace);
+            *state.connection_mode.write() = connection_mode;
             app.manage(state);
+            
+            // Tell the UI why offline mode wasn't restored once it is listening
+            if let Some(warning) = mode_warning {
+                eprintln!("[WARNING] {}", warning);
+                let handle = app.handle().clone();
+                app.once("frontend_loaded", move |_| {
+                    handle.emit("connection-mode-warning", &warning).ok();
+                });
+            }
+            
+            // Watch connectivity and fall back to the local model when offline
+            crate::state::connectivity::spawn_connectivity_monitor(app.handle().clone());
 
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             // Agent commands
             commands::agent_cmds::start_agent_task,
+            commands::agent_cmds::start_agent_task_with_files,
             commands::agent_cmds::cancel_agent_task,
+            commands::agent_cmds::regenerate_last,
+            commands::agent_cmds::resume_interrupted_run,
+            commands::agent_cmds::is_agent_busy,
             commands::agent_cmds::send_user_feedback,
+            commands::agent_cmds::get_context_messages,
+            commands::agent_cmds::summarize_context,
+            commands::agent_cmds::clear_context,
+            commands::agent_cmds::get_agent_mode,
+            commands::agent_cmds::list_tools,
+            commands::agent_cmds::get_rag_stats,
+            commands::agent_cmds::get_rag_status,
+            commands::agent_cmds::cancel_rag_init,
+            commands::agent_cmds::reindex_workspace,
+            commands::agent_cmds::benchmark_local_model,
+            commands::agent_cmds::set_system_prompt,
+            commands::agent_cmds::reset_system_prompt,
+            commands::agent_cmds::attach_image,
+            commands::agent_cmds::attach_image_data,
+            commands::agent_cmds::clear_attached_images,
+            commands::agent_cmds::validate_workspace_path,
+            commands::agent_cmds::set_workspace_roots,
+            commands::agent_cmds::get_workspace_roots,
+            commands::agent_cmds::get_workspace_info,
             commands::agent_cmds::read_workspace_file,
             commands::agent_cmds::save_workspace_file,
+            commands::agent_cmds::undo_last_edit,
             commands::agent_cmds::list_conversations,
+            commands::agent_cmds::tag_conversation,
+            commands::agent_cmds::toggle_favorite,
             commands::agent_cmds::delete_conversation,
+            commands::agent_cmds::repair_conversation,
+            commands::agent_cmds::set_retention_policy,
             commands::agent_cmds::load_conversation,
             commands::agent_cmds::export_conversation,
+            commands::agent_cmds::import_conversation,
             // Setup commands
             commands::setup_cmds::detect_gpu_cmd,
             commands::setup_cmds::check_setup_status,
+            commands::setup_cmds::get_setup_download_size,
             commands::setup_cmds::download_binaries,
             commands::setup_cmds::download_model,
             commands::setup_cmds::set_connection_mode,
             commands::setup_cmds::pause_download,
             commands::setup_cmds::resume_download,
             commands::setup_cmds::cancel_download,
+            commands::setup_cmds::get_download_speed_history,
+            commands::setup_cmds::cancel_model_load,
+            commands::setup_cmds::cleanup_downloads,
+            commands::setup_cmds::export_app_data,
+            commands::setup_cmds::import_app_data,
+            commands::setup_cmds::list_local_models,
+            commands::setup_cmds::select_local_model,
+            commands::setup_cmds::get_storage_dir,
+            commands::setup_cmds::set_storage_dir,
+            commands::settings_cmds::get_settings,
+            commands::settings_cmds::update_settings,
+            commands::settings_cmds::set_proxy,
+            commands::settings_cmds::set_gemini_endpoint,
+            commands::settings_cmds::rank_api_keys,
+            commands::settings_cmds::add_key_profile,
+            commands::settings_cmds::list_key_profiles,
+            commands::settings_cmds::activate_key_profile,
+            commands::settings_cmds::set_raw_stream_debug,
+            commands::settings_cmds::set_show_thinking,
+            commands::settings_cmds::set_system_prompt_prefix,
+            commands::settings_cmds::set_context_strategy,
+            commands::settings_cmds::set_auto_summarize,
+            commands::settings_cmds::set_sensitive_patterns,
+            commands::settings_cmds::get_recent_logs,
+            commands::settings_cmds::set_log_capture_level,
             // Update commands
             commands::update_cmds::check_for_updates,
             commands::update_cmds::download_update,
+            commands::update_cmds::cancel_update_download,
             commands::update_cmds::install_update,
             commands::update_cmds::get_app_version,
         ])
+        .on_window_event(|_window, event| {
+            if let tauri::WindowEvent::Destroyed = event {
+                agent::workspace::stop_all_watchers();
+            }
+        })
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
 }
 
 /// Load API keys for mobile (simplified - typically from secure storage)
 fn load_api_keys_for_mobile() -> Vec<String> {
+    if let Some(keys) = state::settings::get().active_profile_keys() {
+        return keys;
+    }
     if let Ok(keys_str) = std::env::var("GEMINI_API_KEYS") {
         keys_str
             .split(',')
@@ -4,10 +4,86 @@
 //! - Chat: 4B → 12B fallback
 //! - Agent: 27B → 12B fallback
 //! - Summarizer: 2B (background, no fallback)
+//!
+//! A tier is failed over when it is rate limited, or when its reply ends without any
+//! text (e.g. a SAFETY block) - the stream is held until its first text to tell.
 
-use super::client::GemmaClient;
+use std::fmt;
+use std::pin::Pin;
+
+use futures::{Stream, StreamExt};
+
+use super::client::{native_tools_enabled, GemmaClient};
 use crate::agent::memory::Message;
-use crate::providers::{ModelTier, CompletionResult};
+use crate::error::{ApiError, ApiErrorCode, ZoxError};
+use crate::providers::{ModelTier, CompletionResult, CompletionMetadataHandle};
+
+type ChunkStream = Pin<Box<dyn Stream<Item = Result<String, String>> + Send>>;
+
+/// Why a tier was given up on and the next one tried
+#[derive(Debug)]
+enum FailoverReason {
+    RateLimited(ApiError),
+    /// The reply finished without text - blocked if the finish reason says why
+    Empty { finish_reason: Option<String> },
+}
+
+impl fmt::Display for FailoverReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::RateLimited(_) => write!(f, "rate limited"),
+            Self::Empty { finish_reason: Some(reason) } if reason != "stop" => write!(f, "blocked ({})", reason),
+            Self::Empty { .. } => write!(f, "returned an empty response"),
+        }
+    }
+}
+
+/// Outcome of trying one tier
+enum AttemptError {
+    Failover(FailoverReason),
+    Failed(ApiError),
+}
+
+impl From<AttemptError> for ApiError {
+    fn from(err: AttemptError) -> Self {
+        match err {
+            AttemptError::Failover(FailoverReason::RateLimited(e)) | AttemptError::Failed(e) => e,
+            AttemptError::Failover(reason) => ApiError::unknown(format!("Model {}", reason)),
+        }
+    }
+}
+
+/// Wait for the stream's first text, giving back a stream that still yields everything
+/// An error chunk counts as content - it is reported, not failed over
+async fn await_first_text(mut stream: ChunkStream, metadata: &CompletionMetadataHandle) -> Result<ChunkStream, FailoverReason> {
+    let mut held = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        let has_content = chunk.as_ref().map_or(true, |text| !text.is_empty());
+        held.push(chunk);
+        if has_content {
+            return Ok(Box::pin(futures::stream::iter(held).chain(stream)));
+        }
+    }
+    Err(FailoverReason::Empty { finish_reason: metadata.lock().finish_reason.clone() })
+}
+
+/// Run a completion on one tier, up to its first text
+async fn attempt(
+    client: &GemmaClient,
+    system_prompt: &str,
+    messages: &[Message],
+    is_turbo: bool,
+    native_tools: bool,
+) -> Result<CompletionResult, AttemptError> {
+    let metadata = CompletionMetadataHandle::default();
+    let stream = match client.stream_completion(system_prompt, messages, is_turbo, native_tools, metadata.clone()).await {
+        Ok(stream) => stream,
+        Err(e) if e.code == ApiErrorCode::RateLimit => return Err(AttemptError::Failover(FailoverReason::RateLimited(e))),
+        Err(e) => return Err(AttemptError::Failed(e)),
+    };
+    let stream = await_first_text(Box::pin(stream), &metadata).await.map_err(AttemptError::Failover)?;
+    Ok(CompletionResult::Stream(stream, metadata))
+}
 
 /// Cascade executor with all model clients
 pub struct CascadeExecutor {
@@ -19,25 +95,32 @@ pub struct CascadeExecutor {
     client_27b: GemmaClient,
     /// 2B - Summarizer
     client_2b: GemmaClient,
+    /// Gemini model for agent runs with native tool calling (the `native_tool_model` setting)
+    client_tools: Option<GemmaClient>,
 }
 
 impl CascadeExecutor {
     pub fn new(keys: Vec<String>) -> Self {
+        let settings = crate::state::settings::get();
+        let client_tools = native_tools_enabled(&settings)
+            .then(|| GemmaClient::new(keys.clone(), ModelTier::Agent).with_model(settings.native_tool_model));
         Self {
             client_4b: GemmaClient::new(keys.clone(), ModelTier::BasicChat),
             client_12b: GemmaClient::new(keys.clone(), ModelTier::AdvancedChat),
             client_27b: GemmaClient::new(keys.clone(), ModelTier::Agent),
             client_2b: GemmaClient::new(keys, ModelTier::Summarizer),
+            client_tools,
         }
     }
     
     /// Execute chat request with failover
+    /// Returns the tier that actually served the request
     pub async fn execute_chat(
         &self,
         tier: ModelTier,
         system_prompt: &str,
         messages: &[Message],
-    ) -> Result<CompletionResult, String> {
+    ) -> Result<(CompletionResult, ModelTier), ZoxError> {
         let primary = match tier {
             ModelTier::BasicChat => &self.client_4b,
             ModelTier::AdvancedChat => &self.client_12b,
@@ -47,54 +130,79 @@ impl CascadeExecutor {
         println!("[Cascade] Trying {} for chat...", primary.tier().display_name());
         
         // Try primary model
-        match primary.stream_completion(system_prompt, messages, false).await {
-            Ok(stream) => {
+        match attempt(primary, system_prompt, messages, false, false).await {
+            Ok(result) => {
                 println!("[Cascade] {} succeeded", primary.tier().display_name());
-                Ok(CompletionResult::Stream(Box::pin(stream)))
+                Ok((result, primary.tier()))
             }
-            Err(e) if e == "RATE_LIMIT" => {
+            Err(AttemptError::Failover(reason)) => {
                 // Failover to 12B
-                println!("[Cascade] {} rate limited, failing over to 12B", primary.tier().display_name());
+                println!("[Cascade] {} {}, failing over to 12B", primary.tier().display_name(), reason);
                 
-                match self.client_12b.stream_completion(system_prompt, messages, false).await {
-                    Ok(stream) => {
+                match attempt(&self.client_12b, system_prompt, messages, false, false).await {
+                    Ok(result) => {
                         println!("[Cascade] 12B fallback succeeded");
-                        Ok(CompletionResult::Stream(Box::pin(stream)))
+                        Ok((result, ModelTier::AdvancedChat))
+                    }
+                    Err(e) => {
+                        let mut e = ApiError::from(e);
+                        e.message = format!("All models failed: {}", e.message);
+                        Err(e.into())
                     }
-                    Err(e) => Err(format!("All models failed: {}", e))
                 }
             }
-            Err(e) => Err(e)
+            Err(AttemptError::Failed(e)) => Err(e.into())
         }
     }
     
-    /// Execute agent request with failover
+    /// Execute agent request with failover, returning the tier that served it
+    /// With `native_tools`, the native tool model is tried first with the tools sent as
+    /// function declarations; the Gemma tiers remain the XML-tool fallback
     pub async fn execute_agent(
         &self,
         system_prompt: &str,
         messages: &[Message],
-    ) -> Result<CompletionResult, String> {
+        native_tools: bool,
+    ) -> Result<(CompletionResult, ModelTier), ZoxError> {
+        if let Some(client) = self.client_tools.as_ref().filter(|_| native_tools) {
+            println!("[Cascade] Trying {} with native tools for agent...", client.model());
+            match attempt(client, system_prompt, messages, true, true).await {
+                Ok(result) => {
+                    println!("[Cascade] {} succeeded", client.model());
+                    return Ok((result, ModelTier::Agent));
+                }
+                Err(AttemptError::Failover(reason)) => {
+                    println!("[Cascade] {} {}, falling back to 27B with XML tools", client.model(), reason);
+                }
+                Err(AttemptError::Failed(e)) => return Err(e.into()),
+            }
+        }
+        
         println!("[Cascade] Trying 27B for agent...");
         
         // Try 27B first
-        match self.client_27b.stream_completion(system_prompt, messages, true).await {
-            Ok(stream) => {
+        match attempt(&self.client_27b, system_prompt, messages, true, false).await {
+            Ok(result) => {
                 println!("[Cascade] 27B succeeded");
-                Ok(CompletionResult::Stream(Box::pin(stream)))
+                Ok((result, ModelTier::Agent))
             }
-            Err(e) if e == "RATE_LIMIT" => {
+            Err(AttemptError::Failover(reason)) => {
                 // Failover to 12B with agent prompt
-                println!("[Cascade] 27B rate limited, failing over to 12B");
+                println!("[Cascade] 27B {}, failing over to 12B", reason);
                 
-                match self.client_12b.stream_completion(system_prompt, messages, true).await {
-                    Ok(stream) => {
+                match attempt(&self.client_12b, system_prompt, messages, true, false).await {
+                    Ok(result) => {
                         println!("[Cascade] 12B fallback succeeded");
-                        Ok(CompletionResult::Stream(Box::pin(stream)))
+                        Ok((result, ModelTier::AdvancedChat))
+                    }
+                    Err(e) => {
+                        let mut e = ApiError::from(e);
+                        e.message = format!("All agent models failed: {}", e.message);
+                        Err(e.into())
                     }
-                    Err(e) => Err(format!("All agent models failed: {}", e))
                 }
             }
-            Err(e) => Err(e)
+            Err(AttemptError::Failed(e)) => Err(e.into())
         }
     }
     
@@ -104,3 +212,22 @@ impl CascadeExecutor {
         self.client_2b.summarize(messages).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_empty_reply_fails_over_but_text_is_kept() {
+        let metadata = CompletionMetadataHandle::default();
+        metadata.lock().finish_reason = Some("SAFETY".to_string());
+        let empty: ChunkStream = Box::pin(futures::stream::iter(vec![Ok(String::new())]));
+        let reason = await_first_text(empty, &metadata).await.err().unwrap();
+        assert_eq!(reason.to_string(), "blocked (SAFETY)");
+
+        let chunks = vec![Ok(String::new()), Ok("Hel".to_string()), Ok("lo".to_string())];
+        let stream = await_first_text(Box::pin(futures::stream::iter(chunks)), &metadata).await.ok().unwrap();
+        let text: Vec<String> = stream.map(|c| c.unwrap()).collect().await;
+        assert_eq!(text.concat(), "Hello");
+    }
+}
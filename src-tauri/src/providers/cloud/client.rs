@@ -3,21 +3,72 @@
 //! HTTP client for Gemini API that can work with any model tier.
 //! Preserves the exact prompt structure and streaming behavior from the original implementation.
 
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
 use reqwest::Client;
 use futures::StreamExt;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use crate::agent::memory::Message;
-use crate::providers::ModelTier;
+use crate::error::{ApiError as ProviderError, ApiErrorCode};
+use crate::providers::{CompletionMetadataHandle, ModelTier};
+
+/// Default request rate per API key (Gemma free tier allows ~30 RPM)
+const DEFAULT_REQUESTS_PER_MINUTE: u32 = 30;
+
+/// Requests allowed back-to-back on a key before throttling kicks in
+const KEY_BURST: f64 = 4.0;
+
+/// Configured requests per minute per key (0 = unlimited)
+/// Starts from the `requests_per_minute` setting and follows it as settings change
+static REQUESTS_PER_MINUTE: Lazy<AtomicU32> = Lazy::new(|| {
+    AtomicU32::new(resolve_requests_per_minute(crate::state::settings::get().requests_per_minute))
+});
+
+/// Per-key rate for a setting value, falling back to GEMINI_RPM and then the default
+fn resolve_requests_per_minute(setting: Option<u32>) -> u32 {
+    setting
+        .or_else(|| std::env::var("GEMINI_RPM").ok().and_then(|v| v.trim().parse().ok()))
+        .unwrap_or(DEFAULT_REQUESTS_PER_MINUTE)
+}
+
+/// Token buckets keyed by API key - shared by every tier (router, chat, agent, summarizer)
+static KEY_LIMITERS: Lazy<DashMap<String, Arc<Mutex<TokenBucket>>>> = Lazy::new(DashMap::new);
+
+/// Preferred key order from the last `rank_api_keys`, fastest first - shared by every KeyManager
+static KEY_RANKING: Lazy<RwLock<Vec<String>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+/// Bumped whenever the ranking changes, so each KeyManager restarts from its best key
+static RANKING_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// How long a key may take to answer the ranking ping
+const KEY_PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Simple token bucket for smoothing requests on a single key
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+/// Apply the `requests_per_minute` setting (Some(0) disables throttling)
+pub fn set_requests_per_minute(setting: Option<u32>) {
+    let rpm = resolve_requests_per_minute(setting);
+    REQUESTS_PER_MINUTE.store(rpm, Ordering::SeqCst);
+    println!("[KeyManager] Rate limit set to {} requests/min per key", rpm);
+}
 
 /// API Key Manager with rotation support
 #[derive(Clone)]
 pub struct KeyManager {
     keys: Vec<String>,
     current_index: Arc<Mutex<usize>>,
+    /// Ranking generation the index was last reset for
+    ranking_generation: Arc<AtomicU64>,
 }
 
 impl KeyManager {
@@ -25,7 +76,17 @@ impl KeyManager {
         Self {
             keys,
             current_index: Arc::new(Mutex::new(0)),
+            ranking_generation: Arc::new(AtomicU64::new(RANKING_GENERATION.load(Ordering::SeqCst))),
+        }
+    }
+
+    /// Keys in the ranked order, with the index moved back to the best key after a new ranking
+    fn ranked_keys(&self, idx: &mut usize) -> Vec<String> {
+        let generation = RANKING_GENERATION.load(Ordering::SeqCst);
+        if self.ranking_generation.swap(generation, Ordering::SeqCst) != generation {
+            *idx = 0;
         }
+        order_by_ranking(&self.keys, &KEY_RANKING.read())
     }
 
     pub fn get_current_key(&self) -> Option<String> {
@@ -33,8 +94,9 @@ impl KeyManager {
             eprintln!("[KeyManager] ERROR: No API keys available!");
             return None;
         }
-        let idx = *self.current_index.lock();
-        self.keys.get(idx).cloned()
+        let mut idx = self.current_index.lock();
+        let keys = self.ranked_keys(&mut idx);
+        keys.get(*idx).cloned()
     }
     
     pub fn has_keys(&self) -> bool {
@@ -44,9 +106,135 @@ impl KeyManager {
     pub fn rotate(&self) {
         if self.keys.is_empty() { return; }
         let mut idx = self.current_index.lock();
+        self.ranked_keys(&mut idx);
         *idx = (*idx + 1) % self.keys.len();
         println!("[KeyManager] Rotated to API Key Index: {}", *idx);
     }
+    
+    /// Wait until a request slot is available on this key
+    /// 
+    /// All tiers share one bucket per key, so the router, main stream and
+    /// summarizer can't burst the same key into 429s together.
+    pub async fn acquire(&self, key: &str) {
+        let rpm = REQUESTS_PER_MINUTE.load(Ordering::SeqCst);
+        if rpm == 0 {
+            return;
+        }
+        let rate_per_sec = rpm as f64 / 60.0;
+        
+        let limiter = KEY_LIMITERS
+            .entry(key.to_string())
+            .or_insert_with(|| Arc::new(Mutex::new(TokenBucket {
+                tokens: KEY_BURST,
+                last_refill: Instant::now(),
+            })))
+            .clone();
+        
+        // Reserve a token (may go negative) and compute how long to wait for it
+        let wait = {
+            let mut bucket = limiter.lock();
+            let now = Instant::now();
+            let elapsed = now.duration_since(bucket.last_refill).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * rate_per_sec).min(KEY_BURST);
+            bucket.last_refill = now;
+            bucket.tokens -= 1.0;
+            
+            if bucket.tokens < 0.0 {
+                Duration::from_secs_f64(-bucket.tokens / rate_per_sec)
+            } else {
+                Duration::ZERO
+            }
+        };
+        
+        if !wait.is_zero() {
+            println!("[KeyManager] Throttling request for {}ms", wait.as_millis());
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Sort keys by their position in `ranking`; unranked keys keep their order after the ranked ones
+fn order_by_ranking(keys: &[String], ranking: &[String]) -> Vec<String> {
+    let mut ordered = keys.to_vec();
+    ordered.sort_by_key(|key| ranking.iter().position(|r| r == key).unwrap_or(usize::MAX));
+    ordered
+}
+
+/// Result of pinging one API key
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyRanking {
+    /// Position of the key in the configured list
+    pub index: usize,
+    /// Last four characters, enough to tell keys apart without showing them
+    pub key_hint: String,
+    /// Round trip of the ping, None if it failed
+    pub latency_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Last four characters of a key, enough to tell keys apart without showing them
+pub fn key_hint(key: &str) -> String {
+    key.chars().skip(key.chars().count().saturating_sub(4)).collect()
+}
+
+/// Ping one key with the cheapest authenticated call (a one-model listing)
+async fn ping_key(http: &Client, base_url: &str, key: &str) -> Result<u64, String> {
+    let started = Instant::now();
+    let response = http
+        .get(format!("{}/models?pageSize=1&key={}", base_url, key))
+        .send()
+        .await
+        .map_err(|e| if e.is_timeout() { "timed out".to_string() } else { e.to_string() })?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(match status.as_u16() {
+            400 | 401 | 403 => "rejected (invalid key)".to_string(),
+            429 => "quota exhausted".to_string(),
+            code => format!("HTTP {}", code),
+        });
+    }
+    Ok(started.elapsed().as_millis() as u64)
+}
+
+/// Ping every key concurrently and make every KeyManager prefer the fastest working ones
+/// Returns the keys best first; failed keys go last but stay usable
+pub async fn rank_api_keys(keys: &[String]) -> Vec<KeyRanking> {
+    let base_url = gemini_base_url();
+    let http = crate::state::settings::apply_proxy(Client::builder())
+        .timeout(KEY_PING_TIMEOUT)
+        .build()
+        .unwrap_or_default();
+
+    let pings = keys.iter().map(|key| ping_key(&http, &base_url, key));
+    let results = futures::future::join_all(pings).await;
+
+    let mut rankings: Vec<(String, KeyRanking)> = keys.iter().zip(results).enumerate()
+        .map(|(index, (key, result))| {
+            let key_hint = key_hint(key);
+            let (latency_ms, error) = match result {
+                Ok(ms) => (Some(ms), None),
+                Err(e) => (None, Some(e)),
+            };
+            (key.clone(), KeyRanking { index, key_hint, latency_ms, error })
+        })
+        .collect();
+    rankings.sort_by_key(|(_, r)| r.latency_ms.unwrap_or(u64::MAX));
+
+    *KEY_RANKING.write() = rankings.iter().map(|(key, _)| key.clone()).collect();
+    RANKING_GENERATION.fetch_add(1, Ordering::SeqCst);
+    for (_, r) in &rankings {
+        println!("[KeyManager] Key #{} (...{}): {:?} {:?}", r.index, r.key_hint, r.latency_ms, r.error);
+    }
+    rankings.into_iter().map(|(_, r)| r).collect()
+}
+
+/// Map Gemini finish reasons onto the shared vocabulary ("stop", "length"), keeping others as-is
+fn normalize_finish_reason(reason: &str) -> String {
+    match reason {
+        "STOP" => "stop".to_string(),
+        "MAX_TOKENS" => "length".to_string(),
+        other => other.to_string(),
+    }
 }
 
 /// Response structures for JSON parsing
@@ -54,16 +242,34 @@ impl KeyManager {
 struct StreamResponse {
     candidates: Option<Vec<Candidate>>,
     error: Option<ApiError>,
+    /// Set instead of candidates when the prompt itself was blocked
+    #[serde(rename = "promptFeedback")]
+    prompt_feedback: Option<PromptFeedback>,
+    #[serde(rename = "usageMetadata")]
+    usage_metadata: Option<UsageMetadata>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PromptFeedback {
+    #[serde(rename = "blockReason")]
+    block_reason: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct Candidate {
     content: Option<Content>,
     #[serde(rename = "finishReason")]
-    #[allow(dead_code)]
     finish_reason: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+struct UsageMetadata {
+    #[serde(rename = "promptTokenCount")]
+    prompt_token_count: Option<usize>,
+    #[serde(rename = "candidatesTokenCount")]
+    candidates_token_count: Option<usize>,
+}
+
 #[derive(Debug, Deserialize)]
 struct Content {
     parts: Option<Vec<Part>>,
@@ -74,6 +280,65 @@ struct Content {
 #[derive(Debug, Deserialize)]
 struct Part {
     text: Option<String>,
+    #[serde(rename = "functionCall")]
+    function_call: Option<FunctionCall>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FunctionCall {
+    name: String,
+    #[serde(default)]
+    args: Value,
+}
+
+/// Render a native function call in the XML tool convention so the agent loop parses it like any other call
+/// `<` and backticks are escaped inside the JSON arguments so file contents can't close the
+/// tags early or be mangled by the parser's code fence stripping
+fn function_call_to_xml(call: &FunctionCall) -> String {
+    let args = if call.args.is_object() { call.args.clone() } else { Value::Object(Default::default()) };
+    let json = serde_json::to_string(&args)
+        .unwrap_or_else(|_| "{}".to_string())
+        .replace('<', "\\u003c")
+        .replace('`', "\\u0060");
+    format!("\n<tool>{}</tool>\n<params>{}</params>\n", call.name, json)
+}
+
+/// Gemini `tools` entry declaring every agent tool as a function
+fn function_declarations() -> Value {
+    let declarations: Vec<Value> = crate::agent::tools::get_all_tools()
+        .iter()
+        .map(|tool| {
+            let schema = serde_json::from_str(&tool.input_schema)
+                .unwrap_or_else(|_| serde_json::json!({ "type": "object" }));
+            serde_json::json!({
+                "name": tool.name,
+                "description": tool.description,
+                "parameters": gemini_schema(schema),
+            })
+        })
+        .collect();
+    serde_json::json!([{ "functionDeclarations": declarations }])
+}
+
+/// Gemini expects upper-case OpenAPI type names ("OBJECT", "STRING") in declarations
+fn gemini_schema(mut schema: Value) -> Value {
+    match &mut schema {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                match value.as_str() {
+                    Some(name) if key == "type" => *value = Value::String(name.to_uppercase()),
+                    _ => *value = gemini_schema(value.take()),
+                }
+            }
+        }
+        Value::Array(items) => {
+            for item in items.iter_mut() {
+                *item = gemini_schema(item.take());
+            }
+        }
+        _ => {}
+    }
+    schema
 }
 
 #[derive(Debug, Deserialize)]
@@ -82,26 +347,92 @@ struct ApiError {
     message: Option<String>,
 }
 
+/// Gemini API base URL used when no endpoint is configured
+const DEFAULT_GEMINI_ENDPOINT: &str = "https://generativelanguage.googleapis.com/v1beta";
+
+/// Base URL for Gemini calls: the `gemini_endpoint` setting or Google's public API
+pub fn gemini_base_url() -> String {
+    crate::state::settings::get().gemini_endpoint.unwrap_or_else(|| DEFAULT_GEMINI_ENDPOINT.to_string())
+}
+
+/// Validate a custom Gemini base URL and strip trailing slashes
+/// Model paths (`/models/<name>:<method>`) are appended to the result
+pub fn normalize_gemini_endpoint(url: &str) -> Result<String, String> {
+    let trimmed = url.trim().trim_end_matches('/');
+    let parsed = reqwest::Url::parse(trimmed).map_err(|e| format!("Invalid endpoint URL: {}", e))?;
+    if parsed.scheme() != "https" && parsed.scheme() != "http" {
+        return Err(format!("Endpoint must use http or https, not '{}'", parsed.scheme()));
+    }
+    if parsed.host_str().is_none() {
+        return Err("Endpoint URL has no host".to_string());
+    }
+    if parsed.query().is_some() || parsed.fragment().is_some() {
+        return Err("Endpoint URL must not contain a query or fragment".to_string());
+    }
+    Ok(trimmed.to_string())
+}
+
+/// Whether a model accepts function declarations (`tools`)
+/// Only Gemini models do - the Gemma endpoints reject the request outright
+pub fn supports_function_calling(model: &str) -> bool {
+    model.starts_with("gemini-")
+}
+
+/// Whether agent runs get native tool calling under these settings
+pub(crate) fn native_tools_enabled(settings: &crate::state::settings::AppSettings) -> bool {
+    settings.native_tool_calling && supports_function_calling(&settings.native_tool_model)
+}
+
 /// Gemma Client for a specific model tier
 #[derive(Clone)]
 pub struct GemmaClient {
     http: Client,
     key_manager: KeyManager,
     model_tier: ModelTier,
+    /// Model the requests go to (the tier's model unless overridden with `with_model`)
+    model: String,
+    /// Base URL the model paths are appended to (the `gemini_endpoint` setting or the default)
+    base_url: String,
+    /// Per-request timeout for small-model calls so they never stall the main completion
+    small_model_timeout: Duration,
 }
 
 impl GemmaClient {
+    /// No overall client timeout: long streams may legitimately run for minutes.
+    /// Streams are bounded by an idle read timeout instead, short calls by `small_model_timeout`.
     pub fn new(keys: Vec<String>, tier: ModelTier) -> Self {
+        let settings = crate::state::settings::get();
+        let mut builder = crate::state::settings::apply_proxy(Client::builder())
+            .connect_timeout(Duration::from_secs(settings.http_connect_timeout_secs.max(1)));
+        if settings.stream_read_timeout_secs > 0 {
+            builder = builder.read_timeout(Duration::from_secs(settings.stream_read_timeout_secs));
+        }
+        
         Self {
-            http: Client::builder()
-                .timeout(std::time::Duration::from_secs(120))
-                .build()
-                .unwrap_or_default(),
+            http: builder.build().unwrap_or_default(),
             key_manager: KeyManager::new(keys),
             model_tier: tier,
+            model: tier.model_name().to_string(),
+            base_url: gemini_base_url(),
+            small_model_timeout: Duration::from_secs(settings.http_request_timeout_secs.max(1)),
         }
     }
     
+    /// Send requests to `model` instead of the tier's model, keeping the tier for reporting
+    pub fn with_model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+    
+    /// URL of an API method on this client's model, e.g. `generateContent`
+    fn model_url(&self, method: &str) -> String {
+        format!("{}/models/{}:{}", self.base_url, self.model, method)
+    }
+    
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+    
     pub fn tier(&self) -> ModelTier {
         self.model_tier
     }
@@ -112,47 +443,40 @@ impl GemmaClient {
     /// * `system_prompt` - The system instruction
     /// * `messages` - Conversation history
     /// * `is_turbo` - Whether this is turbo/agent mode (affects temperature)
+    /// * `native_tools` - Send the agent tools as function declarations
     pub async fn stream_completion(
         &self,
         system_prompt: &str,
         messages: &[Message],
         is_turbo: bool,
-    ) -> Result<impl futures::Stream<Item = Result<String, String>>, String> {
+        native_tools: bool,
+        metadata: CompletionMetadataHandle,
+    ) -> Result<impl futures::Stream<Item = Result<String, String>>, ProviderError> {
         const MAX_RETRIES: u32 = 3;
         const BASE_DELAY_MS: u64 = 500;
         
-        let mut last_error = String::new();
-        
-        for attempt in 0..MAX_RETRIES {
-            match self.stream_completion_inner(system_prompt, messages, is_turbo).await {
+        let mut attempt = 0;
+        loop {
+            match self.stream_completion_inner(system_prompt, messages, is_turbo, native_tools, metadata.clone()).await {
                 Ok(stream) => return Ok(stream),
                 Err(e) => {
-                    last_error = e.clone();
-                    
-                    // Check if retryable
-                    let retryable = e == "RATE_LIMIT" || 
-                                   e.contains("timeout") || 
-                                   e.contains("connection") ||
-                                   e.contains("network");
-                    
-                    if !retryable || attempt == MAX_RETRIES - 1 {
-                        break;
+                    attempt += 1;
+                    if !e.retryable || attempt == MAX_RETRIES {
+                        return Err(e);
                     }
                     
                     // Exponential backoff: 500ms, 1000ms, 2000ms
-                    let delay = BASE_DELAY_MS * (2_u64.pow(attempt));
-                    println!("[GemmaClient] Retry {} after {}ms due to: {}", attempt + 1, delay, e);
+                    let delay = BASE_DELAY_MS * (2_u64.pow(attempt - 1));
+                    println!("[GemmaClient] Retry {} after {}ms due to: {}", attempt, delay, e);
                     tokio::time::sleep(std::time::Duration::from_millis(delay)).await;
                     
                     // Rotate key on rate limit
-                    if e == "RATE_LIMIT" {
+                    if e.code == ApiErrorCode::RateLimit {
                         self.key_manager.rotate();
                     }
                 }
             }
         }
-        
-        Err(last_error)
     }
     
     /// Internal stream implementation without retry
@@ -161,16 +485,15 @@ impl GemmaClient {
         system_prompt: &str,
         messages: &[Message],
         is_turbo: bool,
-    ) -> Result<impl futures::Stream<Item = Result<String, String>>, String> {
+        native_tools: bool,
+        metadata: CompletionMetadataHandle,
+    ) -> Result<impl futures::Stream<Item = Result<String, String>>, ProviderError> {
         // Check if we have API keys first
         let key = self.key_manager.get_current_key()
-            .ok_or_else(|| "No API keys configured. Please set GEMINI_API_KEYS environment variable.".to_string())?;
+            .ok_or_else(|| ProviderError::invalid_key("No API keys configured. Please set GEMINI_API_KEYS environment variable."))?;
+        self.key_manager.acquire(&key).await;
         
-        let model_name = self.model_tier.model_name();
-        let url = format!(
-            "https://generativelanguage.googleapis.com/v1beta/models/{}:streamGenerateContent?alt=sse",
-            model_name
-        );
+        let url = self.model_url("streamGenerateContent?alt=sse");
         let url_with_key = format!("{}&key={}", url, key);
 
         // Build contents array - EXACT same structure as original
@@ -196,9 +519,19 @@ impl GemmaClient {
                 _ => "user",
             };
             
+            let mut parts = vec![serde_json::json!({ "text": &msg.content })];
+            for image in &msg.images {
+                parts.push(serde_json::json!({
+                    "inlineData": {
+                        "mimeType": &image.mime_type,
+                        "data": &image.data
+                    }
+                }));
+            }
+            
             contents.push(serde_json::json!({
                 "role": gemma_role,
-                "parts": [{ "text": &msg.content }]
+                "parts": parts
             }));
         }
 
@@ -209,7 +542,7 @@ impl GemmaClient {
             "maxOutputTokens": 8192
         });
         
-        let request_body = serde_json::json!({
+        let mut request_body = serde_json::json!({
             "contents": contents,
             "generationConfig": generation_config,
             "safetySettings": [
@@ -219,6 +552,10 @@ impl GemmaClient {
                 { "category": "HARM_CATEGORY_DANGEROUS_CONTENT", "threshold": "BLOCK_NONE" }
             ]
         });
+        // Models without function calling keep to the XML tool format in the prompt
+        if native_tools && supports_function_calling(&self.model) {
+            request_body["tools"] = function_declarations();
+        }
 
         println!("[GemmaClient] {} sending request with {} messages", 
             self.model_tier.display_name(), messages.len());
@@ -230,27 +567,22 @@ impl GemmaClient {
             .await
             .map_err(|e| {
                 if e.is_timeout() {
-                    "Request timeout".to_string()
+                    ProviderError::timeout("Request timeout")
                 } else if e.is_connect() {
-                    "connection error".to_string()
+                    ProviderError::network(format!("Connection error: {}", e))
                 } else {
-                    format!("Request failed: {}", e)
+                    ProviderError::unknown(format!("Request failed: {}", e))
                 }
             })?;
 
         if !resp.status().is_success() {
             let status = resp.status();
             let body = resp.text().await.unwrap_or_default();
-            
-            if status.as_u16() == 429 {
-                return Err("RATE_LIMIT".to_string());
-            }
-            
-            return Err(format!("API Error {}: {}", status, body));
+            return Err(ProviderError::from_status(status.as_u16(), &body));
         }
 
-        // Process SSE stream
-        let stream = resp.bytes_stream().map(|chunk_result| {
+        // Process SSE stream - usage and finish reason are recorded into metadata as they arrive
+        let stream = resp.bytes_stream().map(move |chunk_result| {
             match chunk_result {
                 Ok(bytes) => {
                     let s = String::from_utf8_lossy(&bytes).to_string();
@@ -260,19 +592,36 @@ impl GemmaClient {
                         if let Some(json_str) = line.strip_prefix("data: ") {
                             if let Ok(response) = serde_json::from_str::<StreamResponse>(json_str) {
                                 if let Some(error) = response.error {
+                                    metadata.lock().finish_reason = Some("error".to_string());
                                     return Err(format!("API Error: {} - {}", 
                                         error.code.unwrap_or(0), 
                                         error.message.unwrap_or_default()));
                                 }
                                 
+                                if let Some(reason) = response.prompt_feedback.and_then(|f| f.block_reason) {
+                                    metadata.lock().finish_reason = Some(reason);
+                                }
+                                
+                                if let Some(usage) = response.usage_metadata {
+                                    let mut meta = metadata.lock();
+                                    meta.prompt_tokens = usage.prompt_token_count.or(meta.prompt_tokens);
+                                    meta.completion_tokens = usage.candidates_token_count.or(meta.completion_tokens);
+                                }
+                                
                                 if let Some(candidates) = response.candidates {
                                     for candidate in candidates {
+                                        if let Some(reason) = candidate.finish_reason {
+                                            metadata.lock().finish_reason = Some(normalize_finish_reason(&reason));
+                                        }
                                         if let Some(content) = candidate.content {
                                             if let Some(parts) = content.parts {
                                                 for part in parts {
                                                     if let Some(text) = part.text {
                                                         text_parts.push(text);
                                                     }
+                                                    if let Some(call) = part.function_call {
+                                                        text_parts.push(function_call_to_xml(&call));
+                                                    }
                                                 }
                                             }
                                         }
@@ -284,7 +633,10 @@ impl GemmaClient {
                     
                     Ok(text_parts.join(""))
                 },
-                Err(e) => Err(e.to_string())
+                Err(e) => {
+                    metadata.lock().finish_reason = Some("error".to_string());
+                    Err(e.to_string())
+                }
             }
         });
 
@@ -297,13 +649,10 @@ impl GemmaClient {
         let short_input: String = input.chars().take(100).collect();
         let prompt = format!("Classify as SIMPLE or COMPLEX: \"{}\"", short_input);
         
-        let model_name = self.model_tier.model_name();
-        let url = format!(
-            "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent",
-            model_name
-        );
+        let url = self.model_url("generateContent");
         let key = self.key_manager.get_current_key()
             .ok_or_else(|| "No API keys configured".to_string())?;
+        self.key_manager.acquire(&key).await;
         let url_with_key = format!("{}?key={}", url, key);
         
         // Minimal request - no system prompt, low tokens
@@ -320,10 +669,17 @@ impl GemmaClient {
         
         let resp = self.http.post(&url_with_key)
             .header("Content-Type", "application/json")
+            .timeout(self.small_model_timeout)
             .json(&request_body)
             .send()
             .await
-            .map_err(|e| format!("Router request failed: {}", e))?;
+            .map_err(|e| {
+                if e.is_timeout() {
+                    "Router request timeout".to_string()
+                } else {
+                    format!("Router request failed: {}", e)
+                }
+            })?;
         
         if !resp.status().is_success() {
             return Err("Router API error".to_string());
@@ -342,6 +698,11 @@ impl GemmaClient {
     
     /// Summarize messages (for 2B summarizer) - MINIMAL prompt for small model
     pub async fn summarize(&self, messages: &[Message]) -> Result<String, String> {
+        self.summarize_streaming(messages, |_| {}).await
+    }
+    
+    /// Summarize messages, calling `on_text` with the summary so far as each piece streams in
+    pub async fn summarize_streaming(&self, messages: &[Message], mut on_text: impl FnMut(&str)) -> Result<String, String> {
         // Take only last 5 messages, truncate each heavily
         let history: String = messages.iter()
             .rev()
@@ -357,14 +718,11 @@ impl GemmaClient {
         // Ultra-simple prompt for 2B
         let prompt = format!("Summarize in 2 sentences: {}", history);
         
-        let model_name = self.model_tier.model_name();
-        let url = format!(
-            "https://generativelanguage.googleapis.com/v1beta/models/{}:generateContent",
-            model_name
-        );
+        let url = self.model_url("streamGenerateContent?alt=sse");
         let key = self.key_manager.get_current_key()
             .ok_or_else(|| "No API keys configured".to_string())?;
-        let url_with_key = format!("{}?key={}", url, key);
+        self.key_manager.acquire(&key).await;
+        let url_with_key = format!("{}&key={}", url, key);
         
         // Minimal config for small model
         let request_body = serde_json::json!({
@@ -378,12 +736,20 @@ impl GemmaClient {
             }
         });
         
+        let timeout_err = |e: reqwest::Error| {
+            if e.is_timeout() {
+                "Summarizer request timeout".to_string()
+            } else {
+                format!("Summarizer request failed: {}", e)
+            }
+        };
         let resp = self.http.post(&url_with_key)
             .header("Content-Type", "application/json")
+            .timeout(self.small_model_timeout)
             .json(&request_body)
             .send()
             .await
-            .map_err(|e| format!("Summarizer request failed: {}", e))?;
+            .map_err(timeout_err)?;
         
         if !resp.status().is_success() {
             let status = resp.status();
@@ -392,14 +758,176 @@ impl GemmaClient {
             return Err(format!("Summarizer API error: {}", status));
         }
         
+        // SSE events can be split across network chunks, so only complete lines are parsed
+        let mut stream = resp.bytes_stream();
+        let mut pending = String::new();
+        let mut summary = String::new();
+        while let Some(chunk) = stream.next().await {
+            pending.push_str(&String::from_utf8_lossy(&chunk.map_err(timeout_err)?));
+            while let Some(newline) = pending.find('\n') {
+                let line: String = pending.drain(..=newline).collect();
+                let Some(json_str) = line.trim_end().strip_prefix("data: ") else {
+                    continue;
+                };
+                let Ok(response) = serde_json::from_str::<StreamResponse>(json_str) else {
+                    continue;
+                };
+                if let Some(error) = response.error {
+                    return Err(format!("Summarizer API error: {}", error.message.unwrap_or_default()));
+                }
+                let text: String = response.candidates.into_iter().flatten()
+                    .filter_map(|candidate| candidate.content?.parts)
+                    .flatten()
+                    .filter_map(|part| part.text)
+                    .collect();
+                if !text.is_empty() {
+                    summary.push_str(&text);
+                    on_text(summary.trim_start());
+                }
+            }
+        }
+        
+        Ok(summary.trim().to_string())
+    }
+    
+    /// Generate a short conversation title (for 2B summarizer) from the first exchange
+    pub async fn generate_title(&self, messages: &[Message]) -> Result<String, String> {
+        let exchange: String = messages.iter()
+            .take(2)
+            .map(|m| {
+                let short_content: String = m.content.chars().take(200).collect();
+                format!("{}: {}", m.role, short_content)
+            })
+            .collect::<Vec<_>>()
+            .join(" | ");
+        
+        let prompt = format!("Write a 3-5 word title for this chat. Reply with the title only: {}", exchange);
+        
+        let url = self.model_url("generateContent");
+        let key = self.key_manager.get_current_key()
+            .ok_or_else(|| "No API keys configured".to_string())?;
+        self.key_manager.acquire(&key).await;
+        let url_with_key = format!("{}?key={}", url, key);
+        
+        let request_body = serde_json::json!({
+            "contents": [{
+                "role": "user",
+                "parts": [{ "text": prompt }]
+            }],
+            "generationConfig": {
+                "temperature": 0.2,
+                "maxOutputTokens": 20,
+            }
+        });
+        
+        let resp = self.http.post(&url_with_key)
+            .header("Content-Type", "application/json")
+            .timeout(self.small_model_timeout)
+            .json(&request_body)
+            .send()
+            .await
+            .map_err(|e| {
+                if e.is_timeout() {
+                    "Title request timeout".to_string()
+                } else {
+                    format!("Title request failed: {}", e)
+                }
+            })?;
+        
+        if !resp.status().is_success() {
+            return Err(format!("Title API error: {}", resp.status()));
+        }
+        
         let body: Value = resp.json().await.map_err(|e| e.to_string())?;
         
-        let text = body["candidates"][0]["content"]["parts"][0]["text"]
+        // Small models like to wrap titles in quotes or markdown
+        let title = body["candidates"][0]["content"]["parts"][0]["text"]
             .as_str()
             .unwrap_or("")
-            .trim()
-            .to_string();
+            .lines()
+            .next()
+            .unwrap_or("")
+            .trim_matches(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '*' | '#' | '.'))
+            .chars()
+            .take(60)
+            .collect::<String>();
         
-        Ok(text)
+        if title.is_empty() {
+            return Err("Empty title".to_string());
+        }
+        Ok(title)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::parser::{ParsedResponse, ResponseParser};
+
+    #[test]
+    fn test_rate_setting_overrides_the_default() {
+        assert_eq!(resolve_requests_per_minute(Some(12)), 12);
+        assert_eq!(resolve_requests_per_minute(Some(0)), 0);
+    }
+
+    #[test]
+    fn test_only_gemini_models_get_function_declarations() {
+        for tier in [ModelTier::Router, ModelTier::BasicChat, ModelTier::AdvancedChat, ModelTier::Agent, ModelTier::Summarizer] {
+            assert!(!supports_function_calling(tier.model_name()), "{} must fall back to XML tools", tier.model_name());
+        }
+        
+        let client = GemmaClient::new(Vec::new(), ModelTier::Agent).with_model("gemini-2.0-flash");
+        assert!(supports_function_calling(client.model()));
+        assert!(client.model_url("generateContent").ends_with("/models/gemini-2.0-flash:generateContent"));
+        assert_eq!(client.tier(), ModelTier::Agent);
+    }
+
+    #[test]
+    fn test_normalize_gemini_endpoint() {
+        assert_eq!(
+            normalize_gemini_endpoint(" https://gateway.example.com/gemini/v1beta/ ").unwrap(),
+            "https://gateway.example.com/gemini/v1beta"
+        );
+        assert!(normalize_gemini_endpoint("ftp://example.com/v1beta").is_err());
+        assert!(normalize_gemini_endpoint("generativelanguage.googleapis.com").is_err());
+        assert!(normalize_gemini_endpoint("https://example.com/v1beta?key=abc").is_err());
+    }
+    
+    #[test]
+    fn test_order_by_ranking_keeps_unranked_keys_last() {
+        let keys: Vec<String> = ["a", "b", "c", "d"].iter().map(|k| k.to_string()).collect();
+        let ranking: Vec<String> = ["c", "a"].iter().map(|k| k.to_string()).collect();
+        assert_eq!(order_by_ranking(&keys, &ranking), vec!["c", "a", "b", "d"]);
+        assert_eq!(order_by_ranking(&keys, &[]), keys);
+    }
+
+    #[test]
+    fn test_function_call_parses_as_tool_call() {
+        let call = FunctionCall {
+            name: "write_file".to_string(),
+            args: serde_json::json!({ "path": "index.html", "content": "<p>hi</p></params></tool>\n```" }),
+        };
+        
+        match ResponseParser::parse(&function_call_to_xml(&call)) {
+            ParsedResponse::ToolCalls { calls, .. } => {
+                assert_eq!(calls.len(), 1);
+                assert_eq!(calls[0].tool, "write_file");
+                assert_eq!(calls[0].parameters["content"], "<p>hi</p></params></tool>\n```");
+            }
+            other => panic!("Expected ToolCalls, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_declaration_types_are_upper_case() {
+        let schema = gemini_schema(serde_json::json!({
+            "type": "object",
+            "properties": { "path": { "type": "string" }, "all": { "type": "boolean" } },
+            "required": ["path"]
+        }));
+        assert_eq!(schema["type"], "OBJECT");
+        assert_eq!(schema["properties"]["path"]["type"], "STRING");
+        assert_eq!(schema["properties"]["all"]["type"], "BOOLEAN");
+        assert_eq!(schema["required"][0], "path");
     }
 }
@@ -11,15 +11,17 @@ mod client;
 mod router;
 mod cascade;
 
-pub use client::GemmaClient;
+pub use client::{GemmaClient, KeyRanking, gemini_base_url, key_hint, normalize_gemini_endpoint, rank_api_keys, set_requests_per_minute, supports_function_calling};
 pub use router::ModelRouter;
 pub use cascade::CascadeExecutor;
+use client::native_tools_enabled;
 
 use async_trait::async_trait;
 use std::sync::Arc;
 use parking_lot::RwLock;
 
 use crate::agent::memory::Message;
+use crate::error::ZoxError;
 use crate::providers::{
     ModelProvider, ProviderCapabilities, CompletionResult, 
     ModelTier, Complexity
@@ -31,7 +33,8 @@ pub struct CloudProvider {
     router: ModelRouter,
     /// Cascade executor with all model clients
     cascade: CascadeExecutor,
-    /// Current active model for UI display
+    /// Tier that served the last successful request (for UI display)
+    /// Only written once the cascade succeeds, so a failover shows the fallback tier
     active_model: Arc<RwLock<ModelTier>>,
 }
 
@@ -53,9 +56,11 @@ impl ModelProvider for CloudProvider {
     fn capabilities(&self) -> ProviderCapabilities {
         ProviderCapabilities {
             supports_tools: true,
+            supports_native_tools: native_tools_enabled(&crate::state::settings::get()),
             supports_streaming: true,
             supports_cascade: true,
             supports_summarization: true,
+            supports_vision: true, // Gemma 3 is multimodal
             max_context_tokens: 128000, // Gemma 3 27B supports 128K context
         }
     }
@@ -68,7 +73,7 @@ impl ModelProvider for CloudProvider {
         &self,
         system_prompt: &str,
         messages: &[Message],
-    ) -> Result<CompletionResult, String> {
+    ) -> Result<CompletionResult, ZoxError> {
         // Step 1: Route with 1B to determine complexity
         let input = messages.last()
             .map(|m| m.content.as_str())
@@ -83,21 +88,22 @@ impl ModelProvider for CloudProvider {
             Complexity::Complex => ModelTier::AdvancedChat, // 12B
         };
         
-        *self.active_model.write() = tier;
-        
         // Step 3: Execute with failover
-        self.cascade.execute_chat(tier, system_prompt, messages).await
+        let (result, served_by) = self.cascade.execute_chat(tier, system_prompt, messages).await?;
+        *self.active_model.write() = served_by;
+        Ok(result)
     }
     
     async fn agent(
         &self,
         system_prompt: &str,
         messages: &[Message],
-    ) -> Result<CompletionResult, String> {
+    ) -> Result<CompletionResult, ZoxError> {
         // Agent mode: Always use 27B with 12B fallback
-        *self.active_model.write() = ModelTier::Agent;
-        
-        self.cascade.execute_agent(system_prompt, messages).await
+        let native_tools = self.capabilities().supports_native_tools;
+        let (result, served_by) = self.cascade.execute_agent(system_prompt, messages, native_tools).await?;
+        *self.active_model.write() = served_by;
+        Ok(result)
     }
     
     async fn classify(&self, input: &str) -> Option<Complexity> {
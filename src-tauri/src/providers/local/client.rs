@@ -4,33 +4,35 @@
 //! Supports CUDA (NVIDIA), Metal (Apple), and CPU fallback.
 
 use std::fs::File;
-use std::io::BufReader;
+use std::io::Cursor;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 use async_trait::async_trait;
 use futures::stream;
+use serde::Serialize;
 
 use crate::providers::{
-    ModelProvider, CompletionResult, ProviderCapabilities, ModelTier, Message
+    ModelProvider, CompletionResult, CompletionMetadata, CompletionMetadataHandle, ProviderCapabilities, ModelTier, Message
 };
+use crate::error::{ModelError, ZoxError, NO_LOCAL_MODEL_MESSAGE};
 use tauri::{AppHandle, Emitter};
+use once_cell::sync::Lazy;
 use crate::setup::paths::get_model_path;
+use super::template::ChatTemplate;
 
 use candle_core::Device;
 use candle_core::quantized::gguf_file::Content;
-use candle_transformers::models::quantized_llama::ModelWeights;
+use candle_transformers::models::quantized_llama::{ModelWeights, MAX_SEQ_LEN};
 use tokenizers::Tokenizer;
 
 /// Chat mode system prompt
-const LOCAL_CHAT_TEMPLATE: &str = r#"<|im_start|>system
-You are ZOX, a helpful AI coding assistant. Be concise and accurate.
-<|im_end|>
-"#;
+const LOCAL_CHAT_SYSTEM: &str = r#"You are ZOX, a helpful AI coding assistant. Be concise and accurate."#;
 
-/// Turbo/Agent prompt template with strict XML tool output
-const LOCAL_TURBO_TEMPLATE: &str = r#"<|im_start|>system
-You are ZOX, an autonomous coding agent. You execute tools via strict XML format.
+/// Turbo/Agent system prompt with strict XML tool output
+const LOCAL_TURBO_SYSTEM: &str = r#"You are ZOX, an autonomous coding agent. You execute tools via strict XML format.
 
 AVAILABLE TOOLS:
 | Tool | Parameters |
@@ -40,6 +42,7 @@ AVAILABLE TOOLS:
 | list_files | <path>directory</path> |
 | search_project | <query>search_term</query> |
 | replace_lines | <path>file_path</path><start>line_num</start><end>line_num</end><content>new_content</content> |
+| replace_in_file | <path>file_path</path><find>exact_text</find><replace>new_text</replace> |
 
 RESPONSE FORMAT (STRICT XML):
 <thinking>brief reasoning</thinking>
@@ -50,9 +53,95 @@ RESPONSE FORMAT (STRICT XML):
 
 For text responses WITHOUT tools:
 <message>Your response here</message>
-<|im_end|>
 "#;
 
+/// Largest context window in tokens (prompt + generated output) the local engine runs
+/// `ModelWeights` precomputes rotary embeddings for `MAX_SEQ_LEN` (4096) positions, so no
+/// position past it can be evaluated. Models trained on a shorter window are clamped further
+/// when loaded (see `model_context_size`)
+const LOCAL_CTX_SIZE: usize = MAX_SEQ_LEN;
+
+/// Maximum tokens generated per completion - reserved out of the context window
+/// Capped at half the model's window so a short-context model still has room for a prompt
+const LOCAL_MAX_TOKENS: usize = 2048;
+
+/// Fixed prompt for `benchmark` so runs are comparable across devices
+const BENCHMARK_PROMPT: &str = "Write a Rust function that returns the n-th Fibonacci number iteratively, then explain how it works.";
+
+/// Tokens generated per benchmark run
+const BENCHMARK_MAX_TOKENS: usize = 256;
+
+/// Error shown when generation runs out of GPU memory
+const OUT_OF_MEMORY_MESSAGE: &str = "GPU out of memory - try a smaller model or CPU mode";
+
+/// Check whether a Candle/CUDA error message means the device ran out of memory
+fn is_out_of_memory(error: &str) -> bool {
+    let lower = error.to_lowercase();
+    ["out of memory", "out_of_memory", "outofmemory", "failed to allocate", "alloc failed"]
+        .iter()
+        .any(|pattern| lower.contains(pattern))
+}
+
+/// Error shown when a GGUF file is cut short or unreadable
+const CORRUPT_MODEL_MESSAGE: &str = "Model file is incomplete or corrupted - delete it and download it again";
+
+/// Context window to run a model with: its trained `<arch>.context_length`, at most `LOCAL_CTX_SIZE`
+/// GGUF files without the key get the full engine window
+fn model_context_size(content: &Content) -> usize {
+    let key = content.metadata.get("general.architecture")
+        .and_then(|v| v.to_string().ok())
+        .map(|architecture| format!("{}.context_length", architecture));
+    let trained = key
+        .and_then(|key| content.metadata.get(&key))
+        .and_then(|v| v.to_u32().map(|n| n as usize).or_else(|_| v.to_u64().map(|n| n as usize)).ok());
+    trained.map_or(LOCAL_CTX_SIZE, |trained| trained.min(LOCAL_CTX_SIZE))
+}
+
+/// Size in bytes a GGUF file must have to hold all the tensors listed in its header
+fn expected_gguf_len(content: &Content) -> u64 {
+    let data_len = content.tensor_infos.values()
+        .map(|info| {
+            let dtype = info.ggml_dtype;
+            let bytes = info.shape.elem_count() / dtype.block_size() * dtype.type_size();
+            info.offset + bytes as u64
+        })
+        .max()
+        .unwrap_or(0);
+    content.tensor_data_offset + data_len
+}
+
+/// Set by `cancel_model_load` - the load in progress stops at its next milestone
+static LOAD_CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Error a cancelled load returns
+const LOAD_CANCELLED_MESSAGE: &str = "Model load cancelled";
+
+/// Model file selected by the user - `None` means the default `model.gguf`
+static SELECTED_MODEL_PATH: Lazy<parking_lot::RwLock<Option<PathBuf>>> = Lazy::new(|| parking_lot::RwLock::new(None));
+
+/// Throughput of one local generation, emitted as `local-inference-stats`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LocalInferenceStats {
+    pub prompt_tokens: usize,
+    pub gen_tokens: usize,
+    pub tokens_per_sec: f64,
+    pub device: String,
+}
+
+impl LocalInferenceStats {
+    /// Build stats from a finished generation's metadata and wall time
+    fn from_metadata(metadata: &CompletionMetadata, elapsed: Duration, device: &str) -> Self {
+        let gen_tokens = metadata.completion_tokens.unwrap_or(0);
+        let secs = elapsed.as_secs_f64();
+        Self {
+            prompt_tokens: metadata.prompt_tokens.unwrap_or(0),
+            gen_tokens,
+            tokens_per_sec: if secs > 0.0 { gen_tokens as f64 / secs } else { 0.0 },
+            device: device.to_string(),
+        }
+    }
+}
+
 /// Provider state
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ProviderState {
@@ -67,6 +156,17 @@ struct LoadedModel {
     weights: ModelWeights,
     tokenizer: Tokenizer,
     device: Device,
+    /// Prompt format of the model family
+    template: ChatTemplate,
+    /// Context window in tokens (prompt + output) this model runs with
+    context_size: usize,
+}
+
+impl LoadedModel {
+    /// Tokens reserved for generated output out of the context window
+    fn max_output_tokens(&self) -> usize {
+        LOCAL_MAX_TOKENS.min(self.context_size / 2)
+    }
 }
 
 /// Local model provider using Candle GGUF
@@ -187,7 +287,8 @@ impl LocalLlamaProvider {
             return Err(format!("Model file not found: {:?}", model_path));
         }
         
-        // Set state to loading
+        // Set state to loading - a cancel sent before this point was for an earlier load
+        LOAD_CANCELLED.store(false, Ordering::SeqCst);
         *self.state.write().await = ProviderState::Loading;
         
         if let Some(app) = &self.app_handle {
@@ -220,8 +321,23 @@ impl LocalLlamaProvider {
                 println!("[LocalLlamaProvider] Model loaded successfully");
                 Ok(())
             }
+            Err(e) if is_load_cancelled(&e) => {
+                // Whatever was loaded so far was dropped with the blocking task
+                *self.model_path.write().await = None;
+                *self.state.write().await = ProviderState::Unloaded;
+                if let Some(app) = &self.app_handle {
+                    app.emit("model-load-complete", "cancelled").ok();
+                }
+                Err(e)
+            }
             Err(e) => {
                 *self.state.write().await = ProviderState::Error;
+                let e = if is_out_of_memory(&e) {
+                    println!("[LocalLlamaProvider] Out of memory while loading: {}", e);
+                    OUT_OF_MEMORY_MESSAGE.to_string()
+                } else {
+                    e
+                };
                 if let Some(app) = &self.app_handle {
                     app.emit("model-load-error", e.clone()).ok();
                 }
@@ -230,6 +346,18 @@ impl LocalLlamaProvider {
         }
     }
     
+    /// Report load progress, or stop the load if it was cancelled
+    fn load_checkpoint(app_handle: &Option<AppHandle>, progress: u32) -> Result<(), String> {
+        if LOAD_CANCELLED.load(Ordering::SeqCst) {
+            println!("[LocalLlamaProvider] Load cancelled before {}%", progress);
+            return Err(LOAD_CANCELLED_MESSAGE.to_string());
+        }
+        if let Some(app) = app_handle {
+            app.emit("model-load-progress", progress).ok();
+        }
+        Ok(())
+    }
+    
     /// Synchronous model loading
     fn load_model_sync(model_path: PathBuf, app_handle: Option<AppHandle>) -> Result<LoadedModel, String> {
         // Get device
@@ -238,28 +366,41 @@ impl LocalLlamaProvider {
         
         println!("[LocalLlamaProvider] Using device: {:?}", device);
         
-        if let Some(app) = &app_handle {
-            app.emit("model-load-progress", 20).ok();
-        }
+        Self::load_checkpoint(&app_handle, 20)?;
 
-        // Open and read GGUF file
+        // Map the GGUF file instead of reading it, so weights are paged in as they're copied
         let file = File::open(&model_path)
             .map_err(|e| format!("Failed to open model file: {}", e))?;
-        let mut reader = BufReader::new(file);
+        // SAFETY: the model file is only replaced by the downloader, which never runs while a
+        // model is loading, and the mapping is dropped once the weights are copied out
+        let mmap = unsafe { memmap2::Mmap::map(&file) }
+            .map_err(|e| format!("Failed to map model file: {}", e))?;
+        let mut reader = Cursor::new(&mmap[..]);
         
-        if let Some(app) = &app_handle {
-            app.emit("model-load-progress", 30).ok();
-        }
+        Self::load_checkpoint(&app_handle, 30)?;
         
         // Read GGUF content
         let content = Content::read(&mut reader)
-            .map_err(|e| format!("Failed to read GGUF content: {}", e))?;
+            .map_err(|e| format!("{} ({})", CORRUPT_MODEL_MESSAGE, e))?;
+        
+        // A truncated download still maps fine, so check the header against the file size
+        let expected_len = expected_gguf_len(&content);
+        if (mmap.len() as u64) < expected_len {
+            println!("[LocalLlamaProvider] Model file truncated: {} of {} bytes", mmap.len(), expected_len);
+            return Err(format!("{} ({} of {} bytes present)", CORRUPT_MODEL_MESSAGE, mmap.len(), expected_len));
+        }
         
         println!("[LocalLlamaProvider] GGUF content read successfully");
         
-        if let Some(app) = &app_handle {
-            app.emit("model-load-progress", 50).ok();
-        }
+        // The setting wins over detection; unknown families get ChatML
+        let template = crate::state::settings::get().local_chat_template
+            .or_else(|| ChatTemplate::detect(&content))
+            .unwrap_or(ChatTemplate::ChatML);
+        println!("[LocalLlamaProvider] Using {:?} chat template", template);
+        let context_size = model_context_size(&content);
+        println!("[LocalLlamaProvider] Context window: {} tokens", context_size);
+        
+        Self::load_checkpoint(&app_handle, 50)?;
         
         // Load model weights
         let weights = ModelWeights::from_gguf(content, &mut reader, &device)
@@ -267,18 +408,14 @@ impl LocalLlamaProvider {
         
         println!("[LocalLlamaProvider] Model weights loaded");
         
-        if let Some(app) = &app_handle {
-            app.emit("model-load-progress", 70).ok();
-        }
+        Self::load_checkpoint(&app_handle, 70)?;
         
         // Load tokenizer - try to find tokenizer.json next to model
         let tokenizer_path = model_path.parent()
             .map(|p| p.join("tokenizer.json"))
             .filter(|p| p.exists());
         
-        if let Some(app) = &app_handle {
-            app.emit("model-load-progress", 80).ok();
-        }
+        Self::load_checkpoint(&app_handle, 80)?;
         
         let tokenizer = if let Some(tok_path) = tokenizer_path {
             println!("[LocalLlamaProvider] Loading tokenizer from: {:?}", tok_path);
@@ -288,14 +425,14 @@ impl LocalLlamaProvider {
             return Err("No tokenizer.json found next to model file. Please provide a tokenizer.".to_string());
         };
         
-        if let Some(app) = &app_handle {
-            app.emit("model-load-progress", 95).ok();
-        }
+        Self::load_checkpoint(&app_handle, 95)?;
         
         Ok(LoadedModel {
             weights,
             tokenizer,
             device,
+            template,
+            context_size,
         })
     }
     
@@ -307,27 +444,59 @@ impl LocalLlamaProvider {
         *self.state.write().await = ProviderState::Unloaded;
     }
     
-    /// Format messages for the model
-    fn format_messages(system_prompt: &str, messages: &[Message], is_turbo: bool) -> String {
-        let base_system = if is_turbo { LOCAL_TURBO_TEMPLATE } else { LOCAL_CHAT_TEMPLATE };
-        let mut formatted = format!("<|im_start|>system\n{}\n{}\n<|im_end|>\n", base_system.trim(), system_prompt);
-        
-        for msg in messages {
-            let role = match msg.role.as_str() {
-                "user" => "user",
-                "assistant" => "assistant",
-                "system" => continue,
-                _ => "user",
-            };
-            
-            formatted.push_str(&format!(
-                "<|im_start|>{}\n{}\n<|im_end|>\n",
-                role, msg.content
+    /// Format messages for the model in its family's chat template
+    fn format_messages(template: ChatTemplate, system_prompt: &str, messages: &[Message], is_turbo: bool) -> String {
+        let base_system = if is_turbo { LOCAL_TURBO_SYSTEM } else { LOCAL_CHAT_SYSTEM };
+        let system = format!("{}\n{}", base_system.trim(), system_prompt);
+        template.format_messages(&system, messages)
+    }
+    
+    /// Build the prompt, dropping the oldest messages until it fits the context window
+    /// 
+    /// Leaves room for the model's maximum output. Errors if the system prompt alone
+    /// (or the system prompt plus the latest message) cannot fit.
+    async fn build_prompt(&self, system_prompt: &str, messages: &[Message], is_turbo: bool) -> Result<String, String> {
+        let model_guard = self.model.read().await;
+        let loaded = model_guard.as_ref()
+            .ok_or_else(|| "Model not loaded".to_string())?;
+        
+        let budget = loaded.context_size.saturating_sub(loaded.max_output_tokens());
+        let count_tokens = |prompt: &str| -> Result<usize, String> {
+            loaded.tokenizer.encode(prompt, true)
+                .map(|encoding| encoding.len())
+                .map_err(|e| format!("Tokenization failed: {}", e))
+        };
+        
+        let system_tokens = count_tokens(&Self::format_messages(loaded.template, system_prompt, &[], is_turbo))?;
+        if system_tokens > budget {
+            return Err(format!(
+                "System prompt is too long for the local model ({} tokens, limit {})",
+                system_tokens, budget
             ));
         }
         
-        formatted.push_str("<|im_start|>assistant\n");
-        formatted
+        if messages.is_empty() {
+            return Ok(Self::format_messages(loaded.template, system_prompt, messages, is_turbo));
+        }
+        
+        // Drop oldest messages first, always keeping the latest one
+        for start in 0..messages.len() {
+            let prompt = Self::format_messages(loaded.template, system_prompt, &messages[start..], is_turbo);
+            let prompt_tokens = count_tokens(&prompt)?;
+            
+            if prompt_tokens <= budget {
+                if start > 0 {
+                    println!("[LocalLlamaProvider] Dropped {} oldest messages to fit context ({} tokens)", 
+                        start, prompt_tokens);
+                }
+                return Ok(prompt);
+            }
+        }
+        
+        Err(format!(
+            "Message is too long for the local model's context window ({} tokens available). Please shorten it.",
+            budget - system_tokens
+        ))
     }
     
     /// Generate text (blocking) - returns full string
@@ -340,6 +509,8 @@ impl LocalLlamaProvider {
         use candle_core::Tensor;
         use rand::SeedableRng;
         
+        let max_tokens = max_tokens.min(model.max_output_tokens());
+        
         // Tokenize input
         let encoding = model.tokenizer.encode(prompt, true)
             .map_err(|e| format!("Tokenization failed: {}", e))?;
@@ -350,15 +521,13 @@ impl LocalLlamaProvider {
         let mut generated_text = String::new();
         
         // Get special tokens
-        let eos_token_id = model.tokenizer.token_to_id("<|im_end|>")
-            .or_else(|| model.tokenizer.token_to_id("</s>"))
-            .unwrap_or(2);
+        let stop_token_ids = model.template.stop_token_ids(&model.tokenizer);
         
         let mut rng = rand::rngs::StdRng::seed_from_u64(42);
         
         for i in 0..max_tokens {
             // Create tensor from tokens
-            let context_size = tokens.len().min(2048);
+            let context_size = tokens.len().min(model.context_size);
             let start = tokens.len().saturating_sub(context_size);
             let context = &tokens[start..];
             
@@ -402,7 +571,7 @@ impl LocalLlamaProvider {
             };
             
             // Check for EOS
-            if next_token == eos_token_id {
+            if stop_token_ids.contains(&next_token) {
                 break;
             }
             
@@ -425,10 +594,12 @@ impl LocalLlamaProvider {
         max_tokens: usize,
         temperature: f32,
         tx: std::sync::mpsc::Sender<Result<String, String>>,
-    ) -> Result<(), String> {
+    ) -> Result<CompletionMetadata, String> {
         use candle_core::Tensor;
         use rand::SeedableRng;
         
+        let max_tokens = max_tokens.min(model.max_output_tokens());
+        
         // Tokenize input
         let encoding = model.tokenizer.encode(prompt, true)
             .map_err(|e| format!("Tokenization failed: {}", e))?;
@@ -437,9 +608,7 @@ impl LocalLlamaProvider {
         let mut tokens: Vec<u32> = input_ids.to_vec();
         let mut index_pos = 0;
         
-        let eos_token_id = model.tokenizer.token_to_id("<|im_end|>")
-            .or_else(|| model.tokenizer.token_to_id("</s>"))
-            .unwrap_or(2);
+        let stop_token_ids = model.template.stop_token_ids(&model.tokenizer);
         
         let mut rng = rand::rngs::StdRng::seed_from_u64(42);
         
@@ -482,13 +651,21 @@ impl LocalLlamaProvider {
         
         tokens.push(next_token);
         
+        let metadata = |tokens: &[u32], finish_reason: &str| CompletionMetadata {
+            finish_reason: Some(finish_reason.to_string()),
+            prompt_tokens: Some(context_size),
+            completion_tokens: Some(tokens.len() - context_size),
+        };
+        
         if let Some(text) = model.tokenizer.decode(&[next_token], false).ok() {
-            if tx.send(Ok(text)).is_err() { return Ok(()); }
+            if tx.send(Ok(text)).is_err() { return Ok(metadata(&tokens, "cancelled")); }
         }
 
         // DECODE PHASE: Token-by-token
+        let mut finish_reason = "length";
         for _ in 0..max_tokens {
-            if next_token == eos_token_id {
+            if stop_token_ids.contains(&next_token) {
+                finish_reason = "stop";
                 break;
             }
             
@@ -533,20 +710,77 @@ impl LocalLlamaProvider {
             // Decode and send token
             if let Some(text) = model.tokenizer.decode(&[next_token], false).ok() {
                 if tx.send(Ok(text)).is_err() {
+                    finish_reason = "cancelled";
                     break;
                 }
             }
         }
         
-        Ok(())
+        Ok(metadata(&tokens, finish_reason))
+    }
+    
+    /// Drop a model whose device ran out of memory so VRAM is freed
+    /// The Error state makes the next call reload the model from scratch
+    fn release_after_oom(model: &mut Option<LoadedModel>, state: &RwLock<ProviderState>, error: &str) -> String {
+        println!("[LocalLlamaProvider] Out of memory during generation, unloading model: {}", error);
+        *model = None;
+        *futures::executor::block_on(state.write()) = ProviderState::Error;
+        OUT_OF_MEMORY_MESSAGE.to_string()
+    }
+    
+    /// Log a finished generation's throughput and emit it to the frontend
+    fn report_stats(app_handle: Option<&AppHandle>, stats: &LocalInferenceStats) {
+        println!("[LocalLlamaProvider] {} prompt + {} generated tokens at {:.1} tok/s on {}",
+            stats.prompt_tokens, stats.gen_tokens, stats.tokens_per_sec, stats.device);
+        if let Some(app) = app_handle {
+            app.emit("local-inference-stats", stats).ok();
+        }
+    }
+    
+    /// Run a fixed prompt greedily and measure throughput
+    /// Loads the default model first if nothing is loaded yet
+    pub async fn run_benchmark(&self) -> Result<LocalInferenceStats, String> {
+        self.ensure_loaded().await?;
+        
+        let messages = vec![Message::new("user", BENCHMARK_PROMPT)];
+        let prompt = self.build_prompt("", &messages, false).await?;
+        let model_arc = self.model.clone();
+        let state = self.state.clone();
+        let app_handle = self.app_handle.clone();
+        let device_name = self.device_name.clone();
+        
+        tokio::task::spawn_blocking(move || {
+            let mut model_guard = futures::executor::block_on(model_arc.write());
+            let Some(ref mut loaded) = *model_guard else {
+                return Err("Model not loaded".to_string());
+            };
+            // Tokens are only counted, so keep the receiver alive and never read it
+            let (tx, _rx) = std::sync::mpsc::channel();
+            let started = Instant::now();
+            match Self::generate_streaming(loaded, &prompt, BENCHMARK_MAX_TOKENS, 0.0, tx) {
+                Ok(result) => {
+                    let stats = LocalInferenceStats::from_metadata(&result, started.elapsed(), &device_name);
+                    Self::report_stats(app_handle.as_ref(), &stats);
+                    Ok(stats)
+                }
+                Err(e) if is_out_of_memory(&e) => Err(Self::release_after_oom(&mut model_guard, &state, &e)),
+                Err(e) => Err(e),
+            }
+        })
+        .await
+        .map_err(|e| format!("Task join error: {}", e))?
     }
     
     /// Generate with streaming support
     fn generate_stream_impl(
         model_arc: Arc<RwLock<Option<LoadedModel>>>,
+        state: Arc<RwLock<ProviderState>>,
         prompt: String,
         max_tokens: usize,
         temperature: f32,
+        metadata: CompletionMetadataHandle,
+        app_handle: Option<AppHandle>,
+        device_name: String,
     ) -> impl futures::Stream<Item = Result<String, String>> + Send {
         let (tx, rx) = std::sync::mpsc::channel::<Result<String, String>>();
         
@@ -554,10 +788,25 @@ impl LocalLlamaProvider {
         std::thread::spawn(move || {
             let mut model_guard = futures::executor::block_on(model_arc.write());
             if let Some(ref mut loaded) = *model_guard {
-                if let Err(e) = Self::generate_streaming(loaded, &prompt, max_tokens, temperature, tx.clone()) {
-                    let _ = tx.send(Err(e));
+                let started = Instant::now();
+                match Self::generate_streaming(loaded, &prompt, max_tokens, temperature, tx.clone()) {
+                    Ok(result) => {
+                        let stats = LocalInferenceStats::from_metadata(&result, started.elapsed(), &device_name);
+                        Self::report_stats(app_handle.as_ref(), &stats);
+                        *metadata.lock() = result;
+                    }
+                    Err(e) => {
+                        metadata.lock().finish_reason = Some("error".to_string());
+                        let e = if is_out_of_memory(&e) {
+                            Self::release_after_oom(&mut model_guard, &state, &e)
+                        } else {
+                            e
+                        };
+                        let _ = tx.send(Err(e));
+                    }
                 }
             } else {
+                metadata.lock().finish_reason = Some("error".to_string());
                 let _ = tx.send(Err("Model not loaded".to_string()));
             }
         });
@@ -571,7 +820,45 @@ impl LocalLlamaProvider {
         })
     }
     
-    /// Internal generation method
+    /// Make sure a model is ready, auto-loading the default model if it exists
+    async fn ensure_loaded(&self) -> Result<(), String> {
+        if self.is_loaded().await {
+            return Ok(());
+        }
+        // Check if model is actually loaded in memory despite state
+        if self.model.read().await.is_some() {
+            println!("[LocalLlamaProvider] Model is in memory but state is not Ready. Setting state to Ready.");
+            *self.state.write().await = ProviderState::Ready;
+            return Ok(());
+        }
+        // Auto-load if default model exists
+        let default_path = get_default_model_path();
+        if default_path.exists() {
+            println!("[LocalLlamaProvider] Auto-loading default model...");
+            self.load_model(default_path).await
+        } else {
+            Err(NO_LOCAL_MODEL_MESSAGE.to_string())
+        }
+    }
+    
+    /// `ensure_loaded` for completions, telling a missing model apart from a failed load
+    async fn ensure_ready(&self) -> Result<(), ZoxError> {
+        if self.is_loaded().await {
+            return Ok(());
+        }
+        if self.model.read().await.is_some() {
+            *self.state.write().await = ProviderState::Ready;
+            return Ok(());
+        }
+        let default_path = get_default_model_path();
+        if !default_path.exists() {
+            println!("[LocalLlamaProvider] No model at {:?}", default_path);
+            return Err(ZoxError::Model(ModelError::not_installed()));
+        }
+        self.load_model(default_path).await
+            .map_err(|e| ZoxError::Model(ModelError::load_failed(e)))
+    }
+    
     /// Internal generation method
     async fn generate(&self, system_prompt: &str, messages: &[Message], is_turbo: bool) -> Result<String, String> {
         // Log current state
@@ -581,30 +868,22 @@ impl LocalLlamaProvider {
             self.model.read().await.is_some()
         );
 
-        if !self.is_loaded().await {
-            // Check if model is actually loaded in memory despite state
-            if self.model.read().await.is_some() {
-                 println!("[LocalLlamaProvider] State is {:?} but model is in memory. Setting state to Ready.", current_state);
-                 *self.state.write().await = ProviderState::Ready;
-            } else {
-                // Auto-load if default model exists
-                let default_path = get_default_model_path();
-                if default_path.exists() {
-                    println!("[LocalLlamaProvider] Auto-loading default model...");
-                    self.load_model(default_path).await?;
-                } else {
-                    return Err("Model not loaded. Please download the model first.".to_string());
-                }
-            }
-        }
+        self.ensure_loaded().await?;
         
-        let prompt = Self::format_messages(system_prompt, messages, is_turbo);
+        let prompt = self.build_prompt(system_prompt, messages, is_turbo).await?;
         let model_arc = self.model.clone();
+        let state = self.state.clone();
         
         tokio::task::spawn_blocking(move || {
             let mut model_guard = futures::executor::block_on(model_arc.write());
             if let Some(ref mut loaded) = *model_guard {
-                Self::generate_sync(loaded, &prompt, 2048, 0.7)
+                Self::generate_sync(loaded, &prompt, LOCAL_MAX_TOKENS, 0.7).map_err(|e| {
+                    if is_out_of_memory(&e) {
+                        Self::release_after_oom(&mut model_guard, &state, &e)
+                    } else {
+                        e
+                    }
+                })
             } else {
                 Err("Model not loaded".to_string())
             }
@@ -625,10 +904,12 @@ impl ModelProvider for LocalLlamaProvider {
     fn capabilities(&self) -> ProviderCapabilities {
         ProviderCapabilities {
             supports_tools: true,
+            supports_native_tools: false,
             supports_streaming: true,  // Now supports streaming!
             supports_cascade: false,
             supports_summarization: false,
-            max_context_tokens: 4096,
+            supports_vision: false,
+            max_context_tokens: LOCAL_CTX_SIZE,
         }
     }
     
@@ -640,61 +921,130 @@ impl ModelProvider for LocalLlamaProvider {
         &self,
         system_prompt: &str,
         messages: &[Message],
-    ) -> Result<CompletionResult, String> {
-        // Ensure model is loaded
-        if !self.is_loaded().await {
-            if self.model.read().await.is_some() {
-                *self.state.write().await = ProviderState::Ready;
-            } else {
-                let default_path = get_default_model_path();
-                if default_path.exists() {
-                    self.load_model(default_path).await?;
-                } else {
-                    return Err("Model not loaded. Please download the model first.".to_string());
-                }
-            }
-        }
+    ) -> Result<CompletionResult, ZoxError> {
+        self.ensure_ready().await?;
         
-        let prompt = Self::format_messages(system_prompt, messages, false);
-        let stream = Self::generate_stream_impl(self.model.clone(), prompt, 2048, 0.7);
-        Ok(CompletionResult::Stream(Box::pin(stream)))
+        let prompt = self.build_prompt(system_prompt, messages, false).await
+            .map_err(|e| ZoxError::Model(ModelError::inference_failed(e)))?;
+        let metadata = CompletionMetadataHandle::default();
+        let stream = Self::generate_stream_impl(self.model.clone(), self.state.clone(), prompt, LOCAL_MAX_TOKENS, 0.7, metadata.clone(),
+            self.app_handle.clone(), self.device_name.clone());
+        Ok(CompletionResult::Stream(Box::pin(stream), metadata))
     }
     
     async fn agent(
         &self,
         system_prompt: &str,
         messages: &[Message],
-    ) -> Result<CompletionResult, String> {
-        // Ensure model is loaded
-        if !self.is_loaded().await {
-            if self.model.read().await.is_some() {
-                *self.state.write().await = ProviderState::Ready;
-            } else {
-                let default_path = get_default_model_path();
-                if default_path.exists() {
-                    self.load_model(default_path).await?;
-                } else {
-                    return Err("Model not loaded. Please download the model first.".to_string());
-                }
-            }
-        }
+    ) -> Result<CompletionResult, ZoxError> {
+        self.ensure_ready().await?;
         
-        let prompt = Self::format_messages(system_prompt, messages, true);
-        let stream = Self::generate_stream_impl(self.model.clone(), prompt, 2048, 0.7);
-        Ok(CompletionResult::Stream(Box::pin(stream)))
+        let prompt = self.build_prompt(system_prompt, messages, true).await
+            .map_err(|e| ZoxError::Model(ModelError::inference_failed(e)))?;
+        let metadata = CompletionMetadataHandle::default();
+        let stream = Self::generate_stream_impl(self.model.clone(), self.state.clone(), prompt, LOCAL_MAX_TOKENS, 0.7, metadata.clone(),
+            self.app_handle.clone(), self.device_name.clone());
+        Ok(CompletionResult::Stream(Box::pin(stream), metadata))
     }
     
     fn active_model(&self) -> Option<ModelTier> {
         Some(ModelTier::Local)
     }
+    
+    async fn unload(&self) {
+        self.unload_model().await;
+    }
+    
+    async fn benchmark(&self) -> Result<LocalInferenceStats, String> {
+        self.run_benchmark().await
+    }
 }
 
-/// Get default model path for offline mode
+/// Get default model path for offline mode (the user-selected model if any)
 pub fn get_default_model_path() -> PathBuf {
-    get_model_path()
+    SELECTED_MODEL_PATH.read().clone().unwrap_or_else(get_model_path)
+}
+
+/// Select which GGUF file the local provider loads next
+pub fn set_selected_model_path(path: PathBuf) {
+    println!("[LocalLlamaProvider] Selected model: {:?}", path);
+    *SELECTED_MODEL_PATH.write() = Some(path);
+}
+
+/// Stop the model load in progress at its next milestone, freeing what it loaded so far
+/// Does nothing if no load is running
+pub fn cancel_model_load() {
+    println!("[LocalLlamaProvider] Cancelling model load");
+    LOAD_CANCELLED.store(true, Ordering::SeqCst);
+}
+
+/// Whether a `load_model` error means the load was cancelled
+pub fn is_load_cancelled(error: &str) -> bool {
+    error == LOAD_CANCELLED_MESSAGE
+}
+
+/// Forget the selected model so the default model path is used again
+pub fn clear_selected_model_path() {
+    *SELECTED_MODEL_PATH.write() = None;
 }
 
 /// Check if a local model is available
 pub fn is_model_available() -> bool {
     get_default_model_path().exists()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use candle_core::quantized::gguf_file::{TensorInfo, VersionedMagic};
+    use candle_core::quantized::GgmlDType;
+
+    #[test]
+    fn test_expected_gguf_len_covers_last_tensor() {
+        let mut tensor_infos = std::collections::HashMap::new();
+        tensor_infos.insert("a".to_string(), TensorInfo { ggml_dtype: GgmlDType::F32, shape: (4, 4).into(), offset: 0 });
+        tensor_infos.insert("b".to_string(), TensorInfo { ggml_dtype: GgmlDType::F16, shape: (8,).into(), offset: 64 });
+        let content = Content {
+            magic: VersionedMagic::GgufV3,
+            metadata: std::collections::HashMap::new(),
+            tensor_infos,
+            tensor_data_offset: 100,
+        };
+        // 100 header bytes + 64 bytes of f32 + 16 bytes of f16
+        assert_eq!(expected_gguf_len(&content), 180);
+    }
+
+    #[test]
+    fn test_context_size_follows_trained_window() {
+        use candle_core::quantized::gguf_file::Value;
+        let content = |context_length: Option<u32>| {
+            let mut metadata = std::collections::HashMap::new();
+            metadata.insert("general.architecture".to_string(), Value::String("llama".to_string()));
+            if let Some(n) = context_length {
+                metadata.insert("llama.context_length".to_string(), Value::U32(n));
+            }
+            Content { magic: VersionedMagic::GgufV3, metadata, tensor_infos: std::collections::HashMap::new(), tensor_data_offset: 0 }
+        };
+        
+        assert_eq!(model_context_size(&content(Some(2048))), 2048);
+        assert_eq!(model_context_size(&content(Some(131_072))), LOCAL_CTX_SIZE);
+        assert_eq!(model_context_size(&content(None)), LOCAL_CTX_SIZE);
+    }
+
+    #[test]
+    fn test_inference_stats_rate() {
+        let metadata = CompletionMetadata {
+            finish_reason: Some("stop".to_string()),
+            prompt_tokens: Some(120),
+            completion_tokens: Some(50),
+        };
+        let stats = LocalInferenceStats::from_metadata(&metadata, Duration::from_secs(2), "CPU");
+        assert_eq!(stats.prompt_tokens, 120);
+        assert_eq!(stats.gen_tokens, 50);
+        assert_eq!(stats.tokens_per_sec, 25.0);
+        assert_eq!(stats.device, "CPU");
+
+        let instant = LocalInferenceStats::from_metadata(&metadata, Duration::ZERO, "CPU");
+        assert_eq!(instant.tokens_per_sec, 0.0);
+    }
+}
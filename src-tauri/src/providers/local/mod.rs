@@ -4,5 +4,7 @@
 //! Simple implementation: same model for chat and turbo, no cascade.
 
 mod client;
+mod template;
 
-pub use client::{LocalLlamaProvider, is_model_available, get_default_model_path};
+pub use template::ChatTemplate;
+pub use client::{LocalLlamaProvider, LocalInferenceStats, is_model_available, get_default_model_path, set_selected_model_path, clear_selected_model_path, cancel_model_load, is_load_cancelled};
@@ -0,0 +1,216 @@
+//! Chat templates for local model families
+//!
+//! Each family was trained on its own turn markers, and a prompt in the wrong format
+//! gives subtly degraded output. The template is detected from the GGUF metadata at
+//! load time, or forced with the `local_chat_template` setting.
+
+use candle_core::quantized::gguf_file::Content;
+use serde::{Deserialize, Serialize};
+use tokenizers::Tokenizer;
+
+use crate::providers::Message;
+
+/// Label opening tool output in templates without a tool role
+const TOOL_RESULT_LABEL: &str = "Tool result:";
+
+/// Who a message is from, as the templates distinguish it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Turn {
+    User,
+    Model,
+    /// Tool output - the agent loop stores it as a user message marked as an observation
+    Tool,
+}
+
+impl Turn {
+    /// None for system messages, which the template writes itself
+    fn of(msg: &Message) -> Option<Self> {
+        match msg.role.as_str() {
+            "system" => None,
+            "model" | "assistant" => Some(Self::Model),
+            "tool" => Some(Self::Tool),
+            _ if msg.observation => Some(Self::Tool),
+            _ => Some(Self::User),
+        }
+    }
+}
+
+/// Prompt format of a local model family
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatTemplate {
+    /// `<|im_start|>role ... <|im_end|>`
+    ChatML,
+    /// `<start_of_turn>user ... <end_of_turn>` - no system role
+    Gemma,
+    /// `<|start_header_id|>role<|end_header_id|> ... <|eot_id|>`
+    Llama3,
+    /// ChatML markers, with `<|endoftext|>` also ending a turn
+    Qwen,
+}
+
+impl ChatTemplate {
+    /// Guess the template from the GGUF's embedded Jinja chat template, then its architecture
+    pub fn detect(content: &Content) -> Option<Self> {
+        let metadata = |key: &str| content.metadata.get(key).and_then(|v| v.to_string().ok()).cloned();
+        let architecture = metadata("general.architecture").unwrap_or_default().to_lowercase();
+
+        if let Some(template) = metadata("tokenizer.chat_template") {
+            if template.contains("<start_of_turn>") {
+                return Some(Self::Gemma);
+            }
+            if template.contains("<|start_header_id|>") {
+                return Some(Self::Llama3);
+            }
+            if template.contains("<|im_start|>") {
+                return Some(if architecture.starts_with("qwen") { Self::Qwen } else { Self::ChatML });
+            }
+        }
+
+        if architecture.starts_with("gemma") {
+            Some(Self::Gemma)
+        } else if architecture.starts_with("qwen") {
+            Some(Self::Qwen)
+        } else {
+            // Plain "llama" covers Llama 2 and many fine-tunes, so it isn't enough for Llama 3
+            None
+        }
+    }
+
+    /// Format the system prompt and conversation, ending with an open assistant turn
+    /// The tokenizer adds the BOS token, so it isn't written here
+    pub fn format_messages(&self, system_prompt: &str, messages: &[Message]) -> String {
+        let turns = messages.iter().filter_map(|msg| Some((Turn::of(msg)?, msg.content.as_str())));
+
+        let mut formatted = String::new();
+        match self {
+            Self::ChatML | Self::Qwen => {
+                formatted.push_str(&format!("<|im_start|>system\n{}<|im_end|>\n", system_prompt));
+                for (turn, content) in turns {
+                    let (role, content) = match turn {
+                        Turn::Model => ("assistant", content.to_string()),
+                        Turn::User => ("user", content.to_string()),
+                        // Qwen was trained on tool output in a user turn wrapped like this
+                        Turn::Tool if *self == Self::Qwen => ("user", format!("<tool_response>\n{}\n</tool_response>", content)),
+                        Turn::Tool => ("user", format!("{}\n{}", TOOL_RESULT_LABEL, content)),
+                    };
+                    formatted.push_str(&format!("<|im_start|>{}\n{}<|im_end|>\n", role, content));
+                }
+                formatted.push_str("<|im_start|>assistant\n");
+            }
+            Self::Gemma => {
+                // No system role: the system prompt opens the first user turn
+                let mut pending_system = Some(system_prompt);
+                for (turn, content) in turns {
+                    if turn == Turn::Model {
+                        if let Some(system) = pending_system.take() {
+                            formatted.push_str(&format!("<start_of_turn>user\n{}<end_of_turn>\n", system));
+                        }
+                        formatted.push_str(&format!("<start_of_turn>model\n{}<end_of_turn>\n", content));
+                    } else {
+                        // No tool role either, so tool output is a labeled user turn
+                        let content = match turn {
+                            Turn::Tool => format!("{}\n{}", TOOL_RESULT_LABEL, content),
+                            _ => content.to_string(),
+                        };
+                        let content = match pending_system.take() {
+                            Some(system) => format!("{}\n\n{}", system, content),
+                            None => content,
+                        };
+                        formatted.push_str(&format!("<start_of_turn>user\n{}<end_of_turn>\n", content));
+                    }
+                }
+                if let Some(system) = pending_system {
+                    formatted.push_str(&format!("<start_of_turn>user\n{}<end_of_turn>\n", system));
+                }
+                formatted.push_str("<start_of_turn>model\n");
+            }
+            Self::Llama3 => {
+                let header = |role: &str| format!("<|start_header_id|>{}<|end_header_id|>\n\n", role);
+                formatted.push_str(&format!("{}{}<|eot_id|>", header("system"), system_prompt));
+                for (turn, content) in turns {
+                    // Llama 3.1+ reads tool output from the ipython role
+                    let role = match turn {
+                        Turn::Model => "assistant",
+                        Turn::User => "user",
+                        Turn::Tool => "ipython",
+                    };
+                    formatted.push_str(&format!("{}{}<|eot_id|>", header(role), content));
+                }
+                formatted.push_str(&header("assistant"));
+            }
+        }
+        formatted
+    }
+
+    /// Tokens that end the assistant's turn
+    fn stop_tokens(&self) -> &'static [&'static str] {
+        match self {
+            Self::ChatML => &["<|im_end|>"],
+            Self::Qwen => &["<|im_end|>", "<|endoftext|>"],
+            Self::Gemma => &["<end_of_turn>", "<eos>"],
+            Self::Llama3 => &["<|eot_id|>", "<|end_of_text|>"],
+        }
+    }
+
+    /// Ids of the stop tokens this tokenizer knows, falling back to `</s>` (or id 2)
+    pub fn stop_token_ids(&self, tokenizer: &Tokenizer) -> Vec<u32> {
+        let ids: Vec<u32> = self.stop_tokens().iter().filter_map(|token| tokenizer.token_to_id(token)).collect();
+        if !ids.is_empty() {
+            return ids;
+        }
+        vec![tokenizer.token_to_id("</s>").unwrap_or(2)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gemma_merges_system_into_first_user_turn() {
+        let messages = vec![Message::new("user", "hi"), Message::new("model", "hello")];
+        let prompt = ChatTemplate::Gemma.format_messages("Be brief.", &messages);
+        assert_eq!(
+            prompt,
+            "<start_of_turn>user\nBe brief.\n\nhi<end_of_turn>\n<start_of_turn>model\nhello<end_of_turn>\n<start_of_turn>model\n"
+        );
+    }
+
+    #[test]
+    fn test_observations_are_formatted_as_tool_output() {
+        let messages = vec![
+            Message::new("user", "read it"),
+            Message::new("model", "<tool>read_file</tool>"),
+            Message::observation("<observation>fn main() {}</observation>"),
+        ];
+        let prompt = ChatTemplate::ChatML.format_messages("sys", &messages);
+        assert!(prompt.contains("<|im_start|>user\nTool result:\n<observation>fn main() {}</observation><|im_end|>"));
+
+        let prompt = ChatTemplate::Qwen.format_messages("sys", &messages);
+        assert!(prompt.contains("<|im_start|>user\n<tool_response>\n<observation>fn main() {}</observation>\n</tool_response><|im_end|>"));
+
+        let prompt = ChatTemplate::Llama3.format_messages("sys", &messages);
+        assert!(prompt.contains("<|start_header_id|>ipython<|end_header_id|>\n\n<observation>"));
+        assert!(prompt.contains("<|start_header_id|>user<|end_header_id|>\n\nread it<|eot_id|>"));
+    }
+
+    #[test]
+    fn test_user_text_that_looks_like_an_observation_stays_a_user_turn() {
+        let messages = vec![Message::new("user", "<observation>not a tool</observation>")];
+        let prompt = ChatTemplate::ChatML.format_messages("sys", &messages);
+        assert!(prompt.contains("<|im_start|>user\n<observation>not a tool</observation><|im_end|>"));
+    }
+
+    #[test]
+    fn test_model_role_maps_to_assistant() {
+        let messages = vec![Message::new("user", "hi"), Message::new("model", "hello")];
+        let prompt = ChatTemplate::ChatML.format_messages("sys", &messages);
+        assert!(prompt.contains("<|im_start|>assistant\nhello<|im_end|>"));
+        assert!(prompt.ends_with("<|im_start|>assistant\n"));
+
+        let prompt = ChatTemplate::Llama3.format_messages("sys", &messages);
+        assert!(prompt.starts_with("<|start_header_id|>system<|end_header_id|>\n\nsys<|eot_id|>"));
+        assert!(prompt.ends_with("<|start_header_id|>assistant<|end_header_id|>\n\n"));
+    }
+}
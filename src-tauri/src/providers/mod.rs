@@ -5,29 +5,48 @@
 pub mod cloud;
 pub mod local;
 
+use std::sync::Arc;
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 use crate::agent::memory::Message;
+use crate::error::ZoxError;
 
 /// Provider capabilities - what features this provider supports
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct ProviderCapabilities {
     /// Whether the provider supports tool/function calling
     pub supports_tools: bool,
+    /// Whether agent requests send the tools as native function declarations
+    /// Calls still reach the agent loop in the XML tag convention either way
+    pub supports_native_tools: bool,
     /// Whether the provider supports streaming responses
     pub supports_streaming: bool,
     /// Whether the provider uses model cascade (routing + failover)
     pub supports_cascade: bool,
     /// Whether the provider supports background summarization
     pub supports_summarization: bool,
+    /// Whether the provider accepts inline image input
+    pub supports_vision: bool,
     /// Maximum context window in tokens
     pub max_context_tokens: usize,
 }
 
+/// Why a completion ended and how many tokens it used
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CompletionMetadata {
+    /// "stop", "length", "error", "cancelled", or the provider's own reason (e.g. "SAFETY")
+    pub finish_reason: Option<String>,
+    pub prompt_tokens: Option<usize>,
+    pub completion_tokens: Option<usize>,
+}
+
+/// Filled in by the provider while streaming - read it once the stream has ended
+pub type CompletionMetadataHandle = Arc<parking_lot::Mutex<CompletionMetadata>>;
+
 /// Result of a model completion
 pub enum CompletionResult {
-    /// Streaming response - yields chunks
-    Stream(std::pin::Pin<Box<dyn futures::Stream<Item = Result<String, String>> + Send>>),
+    /// Streaming response - yields chunks, with final metadata in the handle
+    Stream(std::pin::Pin<Box<dyn futures::Stream<Item = Result<String, String>> + Send>>, CompletionMetadataHandle),
 }
 
 /// Tool definition for providers
@@ -103,14 +122,14 @@ pub trait ModelProvider: Send + Sync {
         &self, 
         system_prompt: &str,
         messages: &[Message],
-    ) -> Result<CompletionResult, String>;
+    ) -> Result<CompletionResult, ZoxError>;
     
     /// Generate streaming completion for agent/turbo mode (with tools)
     async fn agent(
         &self,
         system_prompt: &str,
         messages: &[Message],
-    ) -> Result<CompletionResult, String>;
+    ) -> Result<CompletionResult, ZoxError>;
     
     /// Classify request complexity (for routing)
     /// Returns None if routing not supported
@@ -128,4 +147,15 @@ pub trait ModelProvider: Send + Sync {
     fn active_model(&self) -> Option<ModelTier> {
         None
     }
+    
+    /// Release loaded model resources (frees RAM/VRAM for local providers)
+    async fn unload(&self) {
+        // Default: nothing to release
+    }
+    
+    /// Measure generation throughput on a fixed prompt
+    /// Only local providers run on the user's hardware, so others return an error
+    async fn benchmark(&self) -> Result<local::LocalInferenceStats, String> {
+        Err("Benchmarking is only available for the local model".to_string())
+    }
 }
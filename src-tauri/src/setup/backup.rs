@@ -0,0 +1,190 @@
+//! App Data Backup
+//!
+//! Packs conversations, settings and the RAG store into a single zip for moving to
+//! another machine, and restores them. Models and binaries are never included - they
+//! are multi-GB and can be downloaded again.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// Archive entry that marks a zip as a Zox backup
+const MANIFEST_NAME: &str = "zox-backup.json";
+
+/// Backup format version, bumped if the layout changes
+const BACKUP_VERSION: u32 = 1;
+
+/// A file or directory included in a backup, stored under `name` in the archive
+pub struct BackupEntry {
+    pub name: &'static str,
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    version: u32,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Result of restoring a backup
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ImportSummary {
+    pub restored: usize,
+    /// Files left alone because they already existed (restore with overwrite to replace them)
+    pub skipped: usize,
+}
+
+/// Collect the files under a directory, relative to it
+fn collect_files(dir: &Path, relative: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(dir.join(relative))? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        let path = relative.join(entry.file_name());
+        if file_type.is_dir() {
+            collect_files(dir, &path, files)?;
+        } else if file_type.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Name of a file inside the archive - always `/`-separated
+fn archive_name(entry: &str, relative: &Path) -> String {
+    let parts: Vec<String> = relative.components().map(|c| c.as_os_str().to_string_lossy().to_string()).collect();
+    format!("{}/{}", entry, parts.join("/"))
+}
+
+/// Write the entries to a zip at `dest`, returning how many files were stored
+/// Missing entries are skipped, so a fresh install can still be backed up
+pub fn export_backup(dest: &Path, entries: &[BackupEntry]) -> Result<usize, String> {
+    let file = File::create(dest).map_err(|e| format!("Failed to create backup file: {}", e))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let zip_err = |e: zip::result::ZipError| format!("Failed to write backup: {}", e);
+
+    let manifest = Manifest { version: BACKUP_VERSION, created_at: chrono::Utc::now() };
+    zip.start_file(MANIFEST_NAME, options).map_err(zip_err)?;
+    zip.write_all(&serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?)
+        .map_err(|e| format!("Failed to write backup: {}", e))?;
+
+    let mut count = 0;
+    for entry in entries {
+        let files = if entry.path.is_dir() {
+            let mut files = Vec::new();
+            collect_files(&entry.path, Path::new(""), &mut files)
+                .map_err(|e| format!("Failed to read {}: {}", entry.path.display(), e))?;
+            files.into_iter().map(|rel| (archive_name(entry.name, &rel), entry.path.join(rel))).collect()
+        } else if entry.path.is_file() {
+            vec![(entry.name.to_string(), entry.path.clone())]
+        } else {
+            continue;
+        };
+
+        for (name, path) in files {
+            let mut source = File::open(&path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+            zip.start_file(name, options).map_err(zip_err)?;
+            io::copy(&mut source, &mut zip).map_err(|e| format!("Failed to write backup: {}", e))?;
+            count += 1;
+        }
+    }
+
+    zip.finish().map_err(zip_err)?;
+    println!("[Backup] Exported {} files to {}", count, dest.display());
+    Ok(count)
+}
+
+/// Restore a backup made by `export_backup` into the entries' locations
+/// Existing files are kept unless `overwrite` is set; unknown archive entries are ignored
+pub fn import_backup(src: &Path, entries: &[BackupEntry], overwrite: bool) -> Result<ImportSummary, String> {
+    let file = File::open(src).map_err(|e| format!("Failed to open backup: {}", e))?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|e| format!("Not a valid zip file: {}", e))?;
+
+    let manifest: Manifest = {
+        let manifest = archive.by_name(MANIFEST_NAME).map_err(|_| "Not a Zox backup (no manifest)".to_string())?;
+        serde_json::from_reader(manifest).map_err(|e| format!("Invalid backup manifest: {}", e))?
+    };
+    if manifest.version > BACKUP_VERSION {
+        return Err(format!("Backup was made by a newer version of Zox (format {})", manifest.version));
+    }
+
+    let mut summary = ImportSummary::default();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).map_err(|e| format!("Failed to read backup: {}", e))?;
+        // enclosed_name rejects absolute paths and `..`, so nothing lands outside the entries
+        let Some(name) = file.enclosed_name().map(Path::to_path_buf) else {
+            continue;
+        };
+        if file.is_dir() {
+            continue;
+        }
+
+        let mut components = name.components();
+        let Some(first) = components.next() else {
+            continue;
+        };
+        let Some(entry) = entries.iter().find(|e| first.as_os_str() == e.name) else {
+            continue;
+        };
+        let rest = components.as_path();
+        let dest = if rest.as_os_str().is_empty() { entry.path.clone() } else { entry.path.join(rest) };
+
+        if dest.exists() && !overwrite {
+            summary.skipped += 1;
+            continue;
+        }
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent).map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let mut out = File::create(&dest).map_err(|e| format!("Failed to write {}: {}", dest.display(), e))?;
+        io::copy(&mut file, &mut out).map_err(|e| format!("Failed to write {}: {}", dest.display(), e))?;
+        summary.restored += 1;
+    }
+
+    println!("[Backup] Restored {} files ({} skipped) from {}", summary.restored, summary.skipped, src.display());
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backup_round_trip_keeps_existing_files() {
+        let root = std::env::temp_dir().join(format!("zox-backup-{}", uuid::Uuid::new_v4()));
+        let history = root.join("source/history");
+        fs::create_dir_all(history.join("nested")).unwrap();
+        fs::write(history.join("a.jsonl"), "a").unwrap();
+        fs::write(history.join("nested/b.jsonl"), "b").unwrap();
+        fs::write(root.join("source/settings.json"), "{}").unwrap();
+
+        let archive = root.join("backup.zip");
+        let entries = vec![
+            BackupEntry { name: "history", path: history.clone() },
+            BackupEntry { name: "settings.json", path: root.join("source/settings.json") },
+            BackupEntry { name: "rag", path: root.join("source/missing") },
+        ];
+        assert_eq!(export_backup(&archive, &entries).unwrap(), 3);
+
+        let target = root.join("target");
+        fs::create_dir_all(target.join("history")).unwrap();
+        fs::write(target.join("history/a.jsonl"), "newer").unwrap();
+        let entries = vec![
+            BackupEntry { name: "history", path: target.join("history") },
+            BackupEntry { name: "settings.json", path: target.join("settings.json") },
+        ];
+
+        let summary = import_backup(&archive, &entries, false).unwrap();
+        assert_eq!((summary.restored, summary.skipped), (2, 1));
+        assert_eq!(fs::read_to_string(target.join("history/a.jsonl")).unwrap(), "newer");
+        assert_eq!(fs::read_to_string(target.join("history/nested/b.jsonl")).unwrap(), "b");
+
+        let summary = import_backup(&archive, &entries, true).unwrap();
+        assert_eq!(summary.restored, 3);
+        assert_eq!(fs::read_to_string(target.join("history/a.jsonl")).unwrap(), "a");
+
+        fs::remove_dir_all(&root).ok();
+    }
+}
@@ -6,8 +6,11 @@
 //! - Real-time progress events
 
 use futures::StreamExt;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
@@ -20,7 +23,10 @@ use tokio::io::{AsyncSeekExt, AsyncWriteExt};
 pub struct DownloadProgress {
     pub step: String,
     pub percent: f64,
+    /// Speed over the last progress interval
     pub speed_mbps: f64,
+    /// Smoothed speed used for the ETA
+    pub avg_speed_mbps: f64,
     pub eta_seconds: u64,
     pub downloaded_bytes: u64,
     pub total_bytes: u64,
@@ -38,6 +44,22 @@ pub enum DownloadState {
     Error,
 }
 
+/// How a download command finished
+/// A pause is a normal, resumable outcome rather than an error
+#[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum DownloadOutcome {
+    Completed,
+    Paused,
+}
+
+/// Payload of the `download-error` event, emitted only for real failures
+#[derive(Debug, Clone, Serialize)]
+pub struct DownloadFailure {
+    pub step: String,
+    pub error: String,
+}
+
 /// Persistent download state file
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DownloadStateFile {
@@ -71,12 +93,78 @@ impl std::fmt::Display for DownloadError {
     }
 }
 
+/// Weight of the newest sample in the smoothed download speed
+const SPEED_SMOOTHING: f64 = 0.2;
+
+/// Exponential moving average of download speed, so the ETA counts down steadily
+#[derive(Debug, Default)]
+struct SpeedEstimator {
+    avg_bps: Option<f64>,
+}
+
+impl SpeedEstimator {
+    /// Add an instantaneous speed sample and return the smoothed speed
+    fn update(&mut self, sample_bps: f64) -> f64 {
+        let avg = match self.avg_bps {
+            Some(avg) => avg + SPEED_SMOOTHING * (sample_bps - avg),
+            None => sample_bps,
+        };
+        self.avg_bps = Some(avg);
+        avg
+    }
+}
+
+/// Seconds between speed history samples
+const SPEED_SAMPLE_INTERVAL_SECS: f64 = 1.0;
+
+/// Speed history samples kept (5 minutes at one per second)
+const MAX_SPEED_SAMPLES: usize = 300;
+
+/// One point of the speed chart, emitted as `download-speed-sample`
+#[derive(Debug, Clone, Serialize)]
+pub struct SpeedSample {
+    /// Unix time in milliseconds
+    pub timestamp_ms: i64,
+    /// Bytes of the file downloaded so far
+    pub bytes: u64,
+    /// Speed since the previous sample
+    pub speed_mbps: f64,
+}
+
+/// Recent samples of the current download, oldest first
+static SPEED_HISTORY: Lazy<Mutex<VecDeque<SpeedSample>>> = Lazy::new(|| Mutex::new(VecDeque::with_capacity(MAX_SPEED_SAMPLES)));
+
+fn record_speed_sample(sample: SpeedSample) {
+    let mut history = SPEED_HISTORY.lock();
+    if history.len() >= MAX_SPEED_SAMPLES {
+        history.pop_front();
+    }
+    history.push_back(sample);
+}
+
+/// Speed samples of the current (or last) download, oldest first
+/// Kept across a pause and resume, cleared when a new file starts
+pub fn speed_history() -> Vec<SpeedSample> {
+    SPEED_HISTORY.lock().iter().cloned().collect()
+}
+
 /// Download controller for pause/resume/cancel
 #[derive(Clone)]
 pub struct DownloadController {
     pub is_paused: Arc<AtomicBool>,
     pub is_cancelled: Arc<AtomicBool>,
     pub downloaded_bytes: Arc<AtomicU64>,
+    /// Set while `download_file_with_resume` is writing a file
+    pub is_running: Arc<AtomicBool>,
+}
+
+/// Clears `is_running` when a download returns, whichever way it ends
+struct RunningGuard<'a>(&'a AtomicBool);
+
+impl Drop for RunningGuard<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::SeqCst);
+    }
 }
 
 impl DownloadController {
@@ -85,6 +173,7 @@ impl DownloadController {
             is_paused: Arc::new(AtomicBool::new(false)),
             is_cancelled: Arc::new(AtomicBool::new(false)),
             downloaded_bytes: Arc::new(AtomicU64::new(0)),
+            is_running: Arc::new(AtomicBool::new(false)),
         }
     }
 
@@ -107,6 +196,10 @@ impl DownloadController {
     pub fn is_cancelled(&self) -> bool {
         self.is_cancelled.load(Ordering::SeqCst)
     }
+
+    pub fn is_running(&self) -> bool {
+        self.is_running.load(Ordering::SeqCst)
+    }
 }
 
 impl Default for DownloadController {
@@ -118,7 +211,7 @@ impl Default for DownloadController {
 /// Get the download state file path
 fn get_state_file_path(step: &str) -> PathBuf {
     let temp_dir = super::paths::get_temp_dir();
-    temp_dir.join(format!(".download_state_{}.json", step))
+    temp_dir.join(format!("{}{}.json", STATE_FILE_PREFIX, step))
 }
 
 /// Save download state for resume
@@ -158,6 +251,66 @@ async fn clear_download_state(step: &str) -> Result<(), DownloadError> {
     Ok(())
 }
 
+/// Prefix of the resume state files kept in the temp directory
+const STATE_FILE_PREFIX: &str = ".download_state_";
+
+/// Whether a resume state file no longer points at a resumable partial download
+fn is_orphaned_state(path: &Path) -> bool {
+    let state: DownloadStateFile = match std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+    {
+        Some(state) => state,
+        None => return true,
+    };
+    state.is_complete || !state.dest.is_file()
+}
+
+/// Remove stale partial downloads and orphaned resume state from a temp directory
+/// Temp files are removed once older than `max_age`; state files whose target is gone
+/// (or finished) are removed regardless of age. Returns the bytes reclaimed.
+pub fn cleanup_download_dir(dir: &Path, max_age: std::time::Duration) -> u64 {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+    let (state_files, temp_files): (Vec<PathBuf>, Vec<PathBuf>) = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| p.is_file())
+        .partition(|p| p.file_name().map_or(false, |n| n.to_string_lossy().starts_with(STATE_FILE_PREFIX)));
+
+    let mut reclaimed = 0;
+    let mut remove = |path: &Path| {
+        let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+        match std::fs::remove_file(path) {
+            Ok(()) => {
+                println!("[Downloader] Removed {:?} ({} bytes)", path, size);
+                reclaimed += size;
+            }
+            Err(e) => eprintln!("[Downloader] Failed to remove {:?}: {}", path, e),
+        }
+    };
+
+    // Temp files first, so state for a removed partial download is seen as orphaned below
+    for path in &temp_files {
+        let age = std::fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|t| t.elapsed().ok())
+            .unwrap_or_default();
+        if age >= max_age {
+            remove(path);
+        }
+    }
+    for path in &state_files {
+        if is_orphaned_state(path) {
+            remove(path);
+        }
+    }
+    reclaimed
+}
+
 /// Download a file with progress reporting and pause/resume support
 pub async fn download_file_with_resume(
     url: &str,
@@ -168,6 +321,8 @@ pub async fn download_file_with_resume(
     resume_bytes: u64,
 ) -> Result<(), DownloadError> {
     println!("[Downloader] Starting download: {} -> {:?} (resume from {})", url, dest, resume_bytes);
+    controller.is_running.store(true, Ordering::SeqCst);
+    let _running = RunningGuard(&controller.is_running);
 
     // Ensure parent directory exists
     if let Some(parent) = dest.parent() {
@@ -176,7 +331,7 @@ pub async fn download_file_with_resume(
             .map_err(|e| DownloadError::Io(e.to_string()))?;
     }
 
-    let client = Client::builder()
+    let client = crate::state::settings::apply_proxy(Client::builder())
         .timeout(std::time::Duration::from_secs(3600))
         .build()
         .map_err(|e| DownloadError::Network(e.to_string()))?;
@@ -243,6 +398,13 @@ pub async fn download_file_with_resume(
     // Stream the response
     let mut stream = response.bytes_stream();
     let mut last_progress_time = std::time::Instant::now();
+    let mut last_progress_bytes = downloaded_bytes;
+    let mut speed = SpeedEstimator::default();
+    let mut last_sample_time = std::time::Instant::now();
+    let mut last_sample_bytes = downloaded_bytes;
+    if resume_bytes == 0 {
+        SPEED_HISTORY.lock().clear();
+    }
 
     while let Some(chunk_result) = stream.next().await {
         // Check for cancellation
@@ -269,12 +431,14 @@ pub async fn download_file_with_resume(
                 step: step.to_string(),
                 percent: if total_bytes > 0 { (downloaded_bytes as f64 / total_bytes as f64) * 100.0 } else { 0.0 },
                 speed_mbps: 0.0,
+                avg_speed_mbps: 0.0,
                 eta_seconds: 0,
                 downloaded_bytes,
                 total_bytes,
                 state: DownloadState::Paused,
             };
             app.emit("download-progress", &progress).ok();
+            app.emit("download-paused", &progress).ok();
 
             println!("[Downloader] Download paused at {} bytes", downloaded_bytes);
             return Err(DownloadError::Paused);
@@ -291,12 +455,12 @@ pub async fn download_file_with_resume(
 
         // Emit progress every 100ms to avoid spam
         if last_progress_time.elapsed().as_millis() >= 100 {
+            let interval = last_progress_time.elapsed().as_secs_f64();
             last_progress_time = std::time::Instant::now();
 
-            let elapsed = start_time.elapsed().as_secs_f64();
-            let actual_downloaded = downloaded_bytes - resume_bytes;
-            let speed_bps = if elapsed > 0.0 { actual_downloaded as f64 / elapsed } else { 0.0 };
-            let speed_mbps = speed_bps / (1024.0 * 1024.0);
+            let speed_bps = (downloaded_bytes - last_progress_bytes) as f64 / interval;
+            last_progress_bytes = downloaded_bytes;
+            let avg_speed_bps = speed.update(speed_bps);
 
             let percent = if total_bytes > 0 {
                 (downloaded_bytes as f64 / total_bytes as f64) * 100.0
@@ -305,8 +469,8 @@ pub async fn download_file_with_resume(
             };
 
             let remaining_bytes = total_bytes.saturating_sub(downloaded_bytes);
-            let eta_seconds = if speed_bps > 0.0 {
-                (remaining_bytes as f64 / speed_bps) as u64
+            let eta_seconds = if avg_speed_bps > 0.0 {
+                (remaining_bytes as f64 / avg_speed_bps) as u64
             } else {
                 0
             };
@@ -314,7 +478,8 @@ pub async fn download_file_with_resume(
             let progress = DownloadProgress {
                 step: step.to_string(),
                 percent,
-                speed_mbps,
+                speed_mbps: speed_bps / (1024.0 * 1024.0),
+                avg_speed_mbps: avg_speed_bps / (1024.0 * 1024.0),
                 eta_seconds,
                 downloaded_bytes,
                 total_bytes,
@@ -323,6 +488,19 @@ pub async fn download_file_with_resume(
 
             app.emit("download-progress", &progress).ok();
         }
+
+        let sample_interval = last_sample_time.elapsed().as_secs_f64();
+        if sample_interval >= SPEED_SAMPLE_INTERVAL_SECS {
+            let sample = SpeedSample {
+                timestamp_ms: chrono::Utc::now().timestamp_millis(),
+                bytes: downloaded_bytes,
+                speed_mbps: (downloaded_bytes - last_sample_bytes) as f64 / sample_interval / (1024.0 * 1024.0),
+            };
+            last_sample_time = std::time::Instant::now();
+            last_sample_bytes = downloaded_bytes;
+            app.emit("download-speed-sample", &sample).ok();
+            record_speed_sample(sample);
+        }
     }
 
     file.flush()
@@ -337,6 +515,7 @@ pub async fn download_file_with_resume(
         step: step.to_string(),
         percent: 100.0,
         speed_mbps: 0.0,
+        avg_speed_mbps: 0.0,
         eta_seconds: 0,
         downloaded_bytes: total_bytes,
         total_bytes,
@@ -364,6 +543,63 @@ pub async fn download_file(
     download_file_with_resume(url, dest, step, app, &controller, 0).await
 }
 
+/// Combined size of everything the setup flow will download
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadSizeEstimate {
+    pub binaries_bytes: u64,
+    pub model_bytes: u64,
+    pub tokenizer_bytes: u64,
+    pub total_bytes: u64,
+}
+
+/// Get the size of a remote file from a HEAD request's Content-Length
+pub async fn fetch_content_length(client: &Client, url: &str) -> Result<u64, DownloadError> {
+    let response = client
+        .head(url)
+        .header("User-Agent", "ZOX-Agent/1.0")
+        .send()
+        .await
+        .map_err(|e| DownloadError::Network(e.to_string()))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(DownloadError::InvalidResponse(format!(
+            "HTTP {}: {}",
+            status,
+            status.canonical_reason().unwrap_or("Unknown")
+        )));
+    }
+
+    // Read the header directly - the body of a HEAD response is always empty
+    response
+        .headers()
+        .get(reqwest::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .ok_or_else(|| DownloadError::InvalidResponse(format!("No Content-Length for {}", url)))
+}
+
+/// Preflight the setup downloads for a GPU type without fetching any content
+pub async fn get_total_download_size(gpu_type: &str) -> Result<DownloadSizeEstimate, DownloadError> {
+    let client = crate::state::settings::apply_proxy(Client::builder())
+        .timeout(std::time::Duration::from_secs(30))
+        .build()
+        .map_err(|e| DownloadError::Network(e.to_string()))?;
+
+    let (binaries_bytes, model_bytes, tokenizer_bytes) = futures::try_join!(
+        fetch_content_length(&client, get_binaries_url(gpu_type)),
+        fetch_content_length(&client, get_model_url()),
+        fetch_content_length(&client, get_tokenizer_url()),
+    )?;
+
+    Ok(DownloadSizeEstimate {
+        binaries_bytes,
+        model_bytes,
+        tokenizer_bytes,
+        total_bytes: binaries_bytes + model_bytes + tokenizer_bytes,
+    })
+}
+
 /// Get the download URL for GPU binaries based on type
 pub fn get_binaries_url(gpu_type: &str) -> &'static str {
     match gpu_type {
@@ -382,3 +618,70 @@ pub fn get_model_url() -> &'static str {
 pub fn get_tokenizer_url() -> &'static str {
     "https://huggingface.co/unsloth/Llama-3.2-3B-Instruct/resolve/main/tokenizer.json?download=true"
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_speed_estimator_smooths_spikes() {
+        let mut speed = SpeedEstimator::default();
+        assert_eq!(speed.update(100.0), 100.0);
+        
+        // A single spike moves the average only partway
+        let avg = speed.update(1000.0);
+        assert!(avg > 100.0 && avg < 300.0, "got {}", avg);
+    }
+
+    #[test]
+    fn test_speed_history_keeps_newest_samples() {
+        SPEED_HISTORY.lock().clear();
+        for i in 0..MAX_SPEED_SAMPLES as u64 + 3 {
+            record_speed_sample(SpeedSample { timestamp_ms: i as i64, bytes: i, speed_mbps: 1.0 });
+        }
+        let history = speed_history();
+        assert_eq!(history.len(), MAX_SPEED_SAMPLES);
+        assert_eq!(history[0].bytes, 3);
+        assert_eq!(history.last().unwrap().bytes, MAX_SPEED_SAMPLES as u64 + 2);
+    }
+
+    #[test]
+    fn test_cleanup_download_dir() {
+        let dir = std::env::temp_dir().join(format!("zox_cleanup_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let partial = dir.join("binaries.zip");
+        std::fs::write(&partial, vec![0u8; 64]).unwrap();
+        let live_state = DownloadStateFile {
+            url: "https://example.com/binaries.zip".to_string(),
+            dest: partial.clone(),
+            total_bytes: 128,
+            downloaded_bytes: 64,
+            step: "binaries".to_string(),
+            is_complete: false,
+        };
+        let orphan_state = DownloadStateFile {
+            dest: dir.join("missing.gguf"),
+            step: "model".to_string(),
+            ..live_state.clone()
+        };
+        let state_path = |step: &str| dir.join(format!("{}{}.json", STATE_FILE_PREFIX, step));
+        std::fs::write(state_path("binaries"), serde_json::to_string(&live_state).unwrap()).unwrap();
+        std::fs::write(state_path("model"), serde_json::to_string(&orphan_state).unwrap()).unwrap();
+
+        // Recent partial download and its state survive, the orphan goes
+        let reclaimed = cleanup_download_dir(&dir, std::time::Duration::from_secs(3600));
+        assert!(reclaimed > 0);
+        assert!(partial.exists());
+        assert!(state_path("binaries").exists());
+        assert!(!state_path("model").exists());
+
+        // Once stale, the partial file goes and takes its state with it
+        let reclaimed = cleanup_download_dir(&dir, std::time::Duration::ZERO);
+        assert!(reclaimed >= 64);
+        assert!(!partial.exists());
+        assert!(!state_path("binaries").exists());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -2,6 +2,7 @@
 //!
 //! Handles downloading and extracting binaries and models for offline mode.
 
+pub mod backup;
 pub mod downloader;
 pub mod extractor;
 pub mod paths;
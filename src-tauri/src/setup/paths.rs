@@ -1,19 +1,104 @@
 //! Path utilities for setup locations
 //!
-//! All files are stored in %APPDATA%/zox/
+//! All files are stored in %APPDATA%/zox/, except models and binaries which
+//! can be moved to another volume with the storage_dir setting
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use once_cell::sync::Lazy;
+use tauri::{AppHandle, Manager};
 
-/// Get the binaries directory: %APPDATA%/zox/binaries
+/// Free space required on a new storage directory before downloads go there
+const MIN_STORAGE_FREE_BYTES: u64 = 5 * 1024 * 1024 * 1024;
+
+/// %APPDATA%/zox - settings, downloads and temp files, which are needed before Tauri starts
+/// Only without any platform data directory does this move to the temp dir, never the working directory
+static PLATFORM_DATA_DIR: Lazy<PathBuf> = Lazy::new(|| match dirs::data_dir() {
+    Some(dir) => dir.join("zox"),
+    None => {
+        eprintln!("[Paths] Platform data directory unavailable, using the temp directory");
+        std::env::temp_dir().join("zox")
+    }
+});
+
+/// Default base directory for downloads: %APPDATA%/zox
+fn default_storage_dir() -> PathBuf {
+    PLATFORM_DATA_DIR.clone()
+}
+
+/// Get the base directory for models and binaries (the storage_dir setting, or %APPDATA%/zox)
+pub fn get_storage_dir() -> PathBuf {
+    crate::state::settings::get().storage_dir
+        .map(PathBuf::from)
+        .unwrap_or_else(default_storage_dir)
+}
+
+/// Check that a directory can hold the downloads
+/// Creates it if needed, probes that it is writable, and requires enough free space
+/// unless a model was already downloaded there
+pub fn validate_storage_dir(dir: &Path) -> Result<(), String> {
+    if !dir.is_absolute() {
+        return Err(format!("Storage directory must be an absolute path: {}", dir.display()));
+    }
+    ensure_writable_dir(dir)?;
+    
+    if dir.join("models").join("model.gguf").is_file() {
+        return Ok(());
+    }
+    
+    let available = fs2::available_space(dir)
+        .map_err(|e| format!("Cannot read free space on {}: {}", dir.display(), e))?;
+    if available < MIN_STORAGE_FREE_BYTES {
+        return Err(format!(
+            "Not enough free space on {} ({} MB free, {} MB needed)",
+            dir.display(),
+            available / (1024 * 1024),
+            MIN_STORAGE_FREE_BYTES / (1024 * 1024)
+        ));
+    }
+    
+    Ok(())
+}
+
+/// Create a directory if needed and probe that files can be written to it
+fn ensure_writable_dir(dir: &Path) -> Result<(), String> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    
+    let probe = dir.join(".zox-write-test");
+    std::fs::write(&probe, b"ok")
+        .map_err(|e| format!("{} is not writable: {}", dir.display(), e))?;
+    let _ = std::fs::remove_file(&probe);
+    Ok(())
+}
+
+/// Resolve the directory for history, RAG and other app data
+/// Tries Tauri's app data dir, then the platform data dir (%APPDATA%/zox), and errors
+/// with every reason if neither is writable instead of falling back to the working directory
+pub fn resolve_app_data_dir(app_handle: &AppHandle) -> Result<PathBuf, String> {
+    let mut failures = Vec::new();
+    
+    let candidates = [
+        app_handle.path().app_data_dir().map_err(|e| format!("App data directory unavailable: {}", e)),
+        Ok(PLATFORM_DATA_DIR.clone()),
+    ];
+    for candidate in candidates {
+        match candidate.and_then(|dir| ensure_writable_dir(&dir).map(|_| dir)) {
+            Ok(dir) => return Ok(dir),
+            Err(e) => failures.push(e),
+        }
+    }
+    
+    Err(format!("No writable data directory found ({})", failures.join("; ")))
+}
+
+/// Get the binaries directory: <storage>/binaries
 pub fn get_binaries_dir() -> PathBuf {
-    let data_dir = dirs::data_dir().unwrap_or_else(|| PathBuf::from("."));
-    data_dir.join("zox").join("binaries")
+    get_storage_dir().join("binaries")
 }
 
-/// Get the models directory: %APPDATA%/zox/models
+/// Get the models directory: <storage>/models
 pub fn get_models_dir() -> PathBuf {
-    let data_dir = dirs::data_dir().unwrap_or_else(|| PathBuf::from("."));
-    data_dir.join("zox").join("models")
+    get_storage_dir().join("models")
 }
 
 /// Get the path to the main model file
@@ -21,6 +106,33 @@ pub fn get_model_path() -> PathBuf {
     get_models_dir().join("model.gguf")
 }
 
+/// List all GGUF model files in the models directory
+pub fn list_model_files() -> Vec<PathBuf> {
+    let mut models: Vec<PathBuf> = std::fs::read_dir(get_models_dir())
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file() && path.extension().map_or(false, |e| e.eq_ignore_ascii_case("gguf")))
+                .collect()
+        })
+        .unwrap_or_default();
+    
+    models.sort();
+    models
+}
+
+/// Get the settings file: %APPDATA%/zox/settings.json
+pub fn get_settings_path() -> PathBuf {
+    PLATFORM_DATA_DIR.join("settings.json")
+}
+
+/// Get the API key store: %APPDATA%/zox/api_keys.json
+/// Kept beside settings.json but out of backups
+pub fn get_api_keys_path() -> PathBuf {
+    get_settings_path().with_file_name("api_keys.json")
+}
+
 /// Get the path to the tokenizer file
 pub fn get_tokenizer_path() -> PathBuf {
     get_models_dir().join("tokenizer.json")
@@ -28,14 +140,12 @@ pub fn get_tokenizer_path() -> PathBuf {
 
 /// Get the path for a temp download file
 pub fn get_temp_download_path(filename: &str) -> PathBuf {
-    let data_dir = dirs::data_dir().unwrap_or_else(|| PathBuf::from("."));
-    data_dir.join("zox").join("temp").join(filename)
+    get_temp_dir().join(filename)
 }
 
 /// Get the temp directory
 pub fn get_temp_dir() -> PathBuf {
-    let data_dir = dirs::data_dir().unwrap_or_else(|| PathBuf::from("."));
-    data_dir.join("zox").join("temp")
+    PLATFORM_DATA_DIR.join("temp")
 }
 
 /// Get the path to llama.dll (Windows) or libllama.so (Linux)
@@ -1,13 +1,23 @@
+use std::collections::BTreeMap;
 use tokio::sync::Mutex;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::oneshot;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use parking_lot::RwLock;
 use crate::agent::actor::AgentCommand;
 use crate::agent::workspace::WorkspaceManager;
+use crate::agent::memory::ImageAttachment;
 
 /// Channel for sending approval responses back to the agent
-pub type ApprovalSender = oneshot::Sender<bool>;
+pub type ApprovalSender = oneshot::Sender<ApprovalResponse>;
+
+/// Approval decision tagged with the request it answers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ApprovalResponse {
+    pub request_id: u64,
+    pub approved: bool,
+}
 
 /// Connection mode - cloud or offline
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -22,35 +32,83 @@ impl Default for ConnectionMode {
     }
 }
 
-/// Holds the pending approval sender if one is waiting
+impl ConnectionMode {
+    /// Mode to start in - the one last chosen in settings, or Cloud if none was saved
+    /// Offline needs the local binaries and model, so an incomplete setup falls back
+    /// to Cloud and returns a warning explaining why
+    pub fn from_settings() -> (Self, Option<String>) {
+        match crate::state::settings::get().last_connection_mode.as_deref() {
+            Some("offline") if crate::setup::paths::SetupStatus::check().is_complete() => (ConnectionMode::Offline, None),
+            Some("offline") => (
+                ConnectionMode::Cloud,
+                Some("Started in cloud mode because offline setup is incomplete. Download the binaries and model to use offline mode.".to_string()),
+            ),
+            _ => (ConnectionMode::Cloud, None),
+        }
+    }
+}
+
+/// Holds the senders of approval requests still waiting for the user
+/// Tool calls from one turn run concurrently, so several can wait at once; each request
+/// gets a new id and a response only resolves the request it names
 pub struct ApprovalState {
-    pub pending: Option<ApprovalSender>,
+    /// Keyed by request id, oldest first
+    pending: BTreeMap<u64, ApprovalSender>,
+    next_request_id: u64,
 }
 
 impl ApprovalState {
     pub fn new() -> Self {
-        Self { pending: None }
+        Self { pending: BTreeMap::new(), next_request_id: 0 }
+    }
+    
+    /// Register a new approval request alongside any already waiting
+    /// Returns the request id and the receiver to await
+    pub fn register(&mut self) -> (u64, oneshot::Receiver<ApprovalResponse>) {
+        self.next_request_id += 1;
+        let request_id = self.next_request_id;
+        let (sender, receiver) = oneshot::channel();
+        self.pending.insert(request_id, sender);
+        (request_id, receiver)
     }
     
-    /// Send approval response if there's a pending request
+    /// Answer the oldest pending request, for responses that carry no request id
     /// Returns true if there was a pending request, false otherwise
     pub fn respond(&mut self, approved: bool) -> bool {
-        if let Some(sender) = self.pending.take() {
-            match sender.send(approved) {
-                Ok(_) => {
-                    println!("[ApprovalState] Sent approval response: {}", approved);
-                    true
-                }
-                Err(_) => {
-                    println!("[ApprovalState] Failed to send - receiver dropped");
-                    false
-                }
+        match self.pending.keys().next().copied() {
+            Some(request_id) => self.respond_to(request_id, approved),
+            None => {
+                println!("[ApprovalState] No pending approval to respond to");
+                false
             }
-        } else {
-            println!("[ApprovalState] No pending approval to respond to");
-            false
         }
     }
+    
+    /// Respond to a specific request - ignored if it was already answered or cancelled
+    pub fn respond_to(&mut self, request_id: u64, approved: bool) -> bool {
+        let Some(sender) = self.pending.remove(&request_id) else {
+            println!("[ApprovalState] Ignoring response for request {} (not pending)", request_id);
+            return false;
+        };
+        
+        match sender.send(ApprovalResponse { request_id, approved }) {
+            Ok(_) => {
+                println!("[ApprovalState] Sent approval response for request {}: {}", request_id, approved);
+                true
+            }
+            Err(_) => {
+                println!("[ApprovalState] Failed to send - receiver dropped");
+                false
+            }
+        }
+    }
+    
+    /// Deny every pending request (used on cancel)
+    /// Returns true if any request was waiting
+    pub fn cancel(&mut self) -> bool {
+        let ids: Vec<u64> = self.pending.keys().copied().collect();
+        ids.into_iter().fold(false, |denied, id| self.respond_to(id, false) || denied)
+    }
 }
 
 /// Global application state managed by Tauri
@@ -66,6 +124,12 @@ pub struct AppState {
     
     /// Current connection mode (cloud or offline)
     pub connection_mode: RwLock<ConnectionMode>,
+    
+    /// Images attached by the user, sent with the next prompt
+    pub pending_images: RwLock<Vec<ImageAttachment>>,
+    
+    /// Set while the agent runs a task - guards against overlapping runs
+    pub agent_busy: AtomicBool,
 }
 
 impl AppState {
@@ -77,6 +141,8 @@ impl AppState {
             approval_state,
             workspace: Arc::new(RwLock::new(None)),
             connection_mode: RwLock::new(ConnectionMode::default()),
+            pending_images: RwLock::new(Vec::new()),
+            agent_busy: AtomicBool::new(false),
         }
     }
     
@@ -97,14 +163,118 @@ impl AppState {
         state.respond(approved)
     }
     
+    /// Send approval response for a specific request id
+    pub fn send_approval_for(&self, request_id: u64, approved: bool) -> bool {
+        let mut state = self.approval_state.write();
+        state.respond_to(request_id, approved)
+    }
+    
     /// Get current connection mode
     pub fn get_connection_mode(&self) -> ConnectionMode {
         *self.connection_mode.read()
     }
     
+    /// Queue an image to be sent with the next prompt
+    /// Returns the number of images now pending
+    pub fn attach_image(&self, image: ImageAttachment) -> usize {
+        let mut images = self.pending_images.write();
+        images.push(image);
+        images.len()
+    }
+    
+    /// Take all pending images, leaving none attached
+    pub fn take_pending_images(&self) -> Vec<ImageAttachment> {
+        std::mem::take(&mut *self.pending_images.write())
+    }
+    
     /// Check if in offline mode
     pub fn is_offline(&self) -> bool {
         matches!(*self.connection_mode.read(), ConnectionMode::Offline)
     }
+    
+    /// Claim the agent for a new task; false if a task is already running
+    pub fn try_begin_task(&self) -> bool {
+        self.agent_busy.compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst).is_ok()
+    }
+    
+    /// Release the agent once its task has finished
+    pub fn finish_task(&self) {
+        self.agent_busy.store(false, Ordering::SeqCst);
+    }
+    
+    /// Check if the agent is running a task
+    pub fn is_agent_busy(&self) -> bool {
+        self.agent_busy.load(Ordering::SeqCst)
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancel_then_late_approve_is_ignored() {
+        let mut state = ApprovalState::new();
+        
+        let (first_id, mut first_rx) = state.register();
+        assert!(state.cancel());
+        assert_eq!(first_rx.try_recv().unwrap(), ApprovalResponse { request_id: first_id, approved: false });
+        
+        // A new request arrives before the late approval for the cancelled one
+        let (second_id, mut second_rx) = state.register();
+        assert!(!state.respond_to(first_id, true));
+        assert!(second_rx.try_recv().is_err(), "stale approval must not resolve the new request");
+        
+        assert!(state.respond_to(second_id, true));
+        assert_eq!(second_rx.try_recv().unwrap(), ApprovalResponse { request_id: second_id, approved: true });
+    }
+
+    #[test]
+    fn test_concurrent_requests_are_answered_separately() {
+        let mut state = ApprovalState::new();
+        let (first_id, mut first_rx) = state.register();
+        let (second_id, mut second_rx) = state.register();
+        
+        // The second call's approval must not touch the first call's request
+        assert!(state.respond_to(second_id, true));
+        assert!(first_rx.try_recv().is_err());
+        assert_eq!(second_rx.try_recv().unwrap(), ApprovalResponse { request_id: second_id, approved: true });
+        
+        assert!(state.respond_to(first_id, false));
+        assert_eq!(first_rx.try_recv().unwrap(), ApprovalResponse { request_id: first_id, approved: false });
+    }
+
+    #[test]
+    fn test_cancel_denies_every_pending_request() {
+        let mut state = ApprovalState::new();
+        let (_, mut first_rx) = state.register();
+        let (_, mut second_rx) = state.register();
+        
+        assert!(state.cancel());
+        assert!(!first_rx.try_recv().unwrap().approved);
+        assert!(!second_rx.try_recv().unwrap().approved);
+        assert!(!state.cancel());
+    }
+
+    #[test]
+    fn test_duplicate_approval_is_ignored() {
+        let mut state = ApprovalState::new();
+        let (request_id, _rx) = state.register();
+        
+        assert!(state.respond_to(request_id, true));
+        assert!(!state.respond_to(request_id, true));
+        assert!(!state.cancel());
+    }
+
+    #[test]
+    fn test_overlapping_task_is_rejected() {
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        let state = AppState::new(tx, Arc::new(RwLock::new(ApprovalState::new())));
+        
+        assert!(state.try_begin_task());
+        assert!(!state.try_begin_task(), "second task must be rejected while the first runs");
+        
+        state.finish_task();
+        assert!(state.try_begin_task());
+    }
+}
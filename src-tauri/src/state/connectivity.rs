@@ -0,0 +1,101 @@
+//! Connectivity monitor
+//!
+//! Periodically probes the configured Gemini endpoint. When the network drops in cloud mode
+//! it emits `connection-lost` and, if enabled and a local model is installed,
+//! switches to offline mode. Reverts to cloud once connectivity returns.
+
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager};
+
+use crate::agent::actor::AgentCommand;
+use crate::setup::paths::SetupStatus;
+use crate::state::app_state::{AppState, ConnectionMode};
+
+/// Time between probes
+const PROBE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Probe timeout - kept short so a dead network is detected quickly
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Check whether the Gemini endpoint in use (a gateway, or Google's public API) is reachable
+/// Any HTTP response counts as online; only transport failures count as offline
+pub async fn is_online(client: &reqwest::Client) -> bool {
+    client.head(crate::providers::cloud::gemini_base_url()).send().await.is_ok()
+}
+
+/// Spawn the background connectivity monitor
+pub fn spawn_connectivity_monitor(app: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        let mut online = true;
+        // True while we are in offline mode because of an automatic switch
+        let mut auto_switched = false;
+        
+        loop {
+            tokio::time::sleep(PROBE_INTERVAL).await;
+            
+            // Rebuilt per probe so proxy changes take effect
+            let client = match crate::state::settings::apply_proxy(reqwest::Client::builder())
+                .timeout(PROBE_TIMEOUT)
+                .build()
+            {
+                Ok(client) => client,
+                Err(e) => {
+                    eprintln!("[Connectivity] Failed to create HTTP client: {}", e);
+                    continue;
+                }
+            };
+            
+            let now_online = is_online(&client).await;
+            if now_online == online {
+                continue;
+            }
+            online = now_online;
+            
+            let state = app.state::<AppState>();
+            
+            if !online {
+                println!("[Connectivity] Connection lost");
+                if state.get_connection_mode() != ConnectionMode::Cloud {
+                    continue;
+                }
+                
+                let can_switch = SetupStatus::check().is_complete();
+                app.emit("connection-lost", serde_json::json!({ "local_available": can_switch })).ok();
+                
+                if can_switch && crate::state::settings::get().auto_switch_offline {
+                    println!("[Connectivity] Switching to offline mode");
+                    if switch_mode(&app, &state, ConnectionMode::Offline).await {
+                        auto_switched = true;
+                    }
+                }
+            } else {
+                println!("[Connectivity] Connection restored");
+                app.emit("connection-restored", ()).ok();
+                
+                // Only revert a switch we made - leave manual offline mode alone
+                if auto_switched && state.get_connection_mode() == ConnectionMode::Offline {
+                    println!("[Connectivity] Reverting to cloud mode");
+                    switch_mode(&app, &state, ConnectionMode::Cloud).await;
+                }
+                auto_switched = false;
+            }
+        }
+    });
+}
+
+/// Tell the agent to switch provider and update shared state
+async fn switch_mode(app: &AppHandle, state: &AppState, mode: ConnectionMode) -> bool {
+    let is_offline = mode == ConnectionMode::Offline;
+    
+    {
+        let tx = state.agent_tx.lock().await;
+        if let Err(e) = tx.send(AgentCommand::SetConnectionMode { is_offline }).await {
+            eprintln!("[Connectivity] Failed to send mode switch: {}", e);
+            return false;
+        }
+    }
+    
+    *state.connection_mode.write() = mode;
+    app.emit("connection-mode-changed", if is_offline { "offline" } else { "cloud" }).ok();
+    true
+}
@@ -0,0 +1,120 @@
+//! Recent log capture
+//!
+//! A `tracing` layer that keeps the last few hundred formatted log lines in memory, so
+//! the Settings/Help UI can show them for bug reports without the user finding stdout.
+//! Which levels are kept follows the `log_capture_level` setting.
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+/// Lines kept before the oldest are dropped
+const MAX_LOG_LINES: usize = 2000;
+
+static LINES: Lazy<Mutex<VecDeque<String>>> = Lazy::new(|| Mutex::new(VecDeque::with_capacity(MAX_LOG_LINES)));
+
+static CAPTURE_LEVEL: Lazy<RwLock<LevelFilter>> = Lazy::new(|| RwLock::new(LevelFilter::INFO));
+
+/// Parse a level name ("off", "error", "warn", "info", "debug", "trace")
+pub fn parse_level(level: &str) -> Result<LevelFilter, String> {
+    level.trim().parse::<LevelFilter>().map_err(|_| format!("Unknown log level '{}'", level))
+}
+
+/// Change which levels are captured from now on
+pub fn set_capture_level(level: LevelFilter) {
+    *CAPTURE_LEVEL.write() = level;
+}
+
+/// The last `n` captured lines, oldest first
+pub fn recent(n: usize) -> Vec<String> {
+    let lines = LINES.lock();
+    lines.iter().skip(lines.len().saturating_sub(n)).cloned().collect()
+}
+
+fn push_line(line: String) {
+    let mut lines = LINES.lock();
+    if lines.len() >= MAX_LOG_LINES {
+        lines.pop_front();
+    }
+    lines.push_back(line);
+}
+
+/// Collects an event's message and fields into one line
+struct LineVisitor(String);
+
+impl Visit for LineVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, " {:?}", value);
+        } else {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            let _ = write!(self.0, " {}", value);
+        } else {
+            let _ = write!(self.0, " {}={}", field.name(), value);
+        }
+    }
+}
+
+/// Layer that copies events into the ring buffer
+struct RingBufferLayer;
+
+impl<S: Subscriber> Layer<S> for RingBufferLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        if *metadata.level() > *CAPTURE_LEVEL.read() {
+            return;
+        }
+        let mut visitor = LineVisitor(format!(
+            "{} {:>5} {}:",
+            chrono::Local::now().format("%H:%M:%S%.3f"),
+            metadata.level(),
+            metadata.target()
+        ));
+        event.record(&mut visitor);
+        push_line(visitor.0);
+    }
+}
+
+/// Install the global subscriber: stdout as before (RUST_LOG, default info) plus the ring buffer
+pub fn init() {
+    match parse_level(&crate::state::settings::get().log_capture_level) {
+        Ok(level) => set_capture_level(level),
+        Err(e) => eprintln!("[Logs] {}, capturing info", e),
+    }
+
+    let stdout = tracing_subscriber::fmt::layer()
+        .with_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")));
+    if let Err(e) = tracing_subscriber::registry().with(stdout).with(RingBufferLayer).try_init() {
+        eprintln!("[Logs] Failed to install log capture: {}", e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent_returns_newest_lines_in_order() {
+        for i in 0..MAX_LOG_LINES + 5 {
+            push_line(format!("line {}", i));
+        }
+        assert_eq!(LINES.lock().len(), MAX_LOG_LINES);
+        let last = MAX_LOG_LINES + 4;
+        assert_eq!(recent(2), vec![format!("line {}", last - 1), format!("line {}", last)]);
+        assert_eq!(recent(usize::MAX).len(), MAX_LOG_LINES);
+        assert!(parse_level("verbose").is_err());
+    }
+}
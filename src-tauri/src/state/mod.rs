@@ -1 +1,4 @@
 pub mod app_state;
+pub mod connectivity;
+pub mod logs;
+pub mod settings;
@@ -0,0 +1,381 @@
+//! Persisted user settings
+//!
+//! Stored as JSON in %APPDATA%/zox/settings.json and cached in memory. API keys
+//! from key profiles are kept apart in api_keys.json, so settings.json can be
+//! sent to the frontend or backed up without them.
+
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+
+use crate::agent::history::RetentionPolicy;
+use crate::agent::memory::ContextStrategy;
+use crate::providers::local::ChatTemplate;
+use crate::setup::paths::{get_api_keys_path, get_settings_path};
+
+/// Files kept from the agent unless the user changes `sensitive_patterns`
+const DEFAULT_SENSITIVE_PATTERNS: &[&str] = &[
+    ".env", ".env.*", "!.env.example", "*.pem", "*.key", "*.p12", "*.pfx",
+    "id_rsa*", "id_ed25519*", ".npmrc", ".netrc", "secrets/",
+];
+
+/// Longest `system_prompt_prefix` accepted, in characters
+pub const MAX_SYSTEM_PROMPT_PREFIX_CHARS: usize = 4_000;
+
+/// A named set of Gemini API keys, e.g. personal and work
+/// The keys are never serialized with the settings - they live in api_keys.json
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ApiKeyProfile {
+    pub name: String,
+    #[serde(skip)]
+    pub keys: Vec<String>,
+}
+
+// Settings are printed in logs, so the keys themselves are never shown
+impl std::fmt::Debug for ApiKeyProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApiKeyProfile")
+            .field("name", &self.name)
+            .field("keys", &format_args!("<{} redacted>", self.keys.len()))
+            .finish()
+    }
+}
+
+/// User-configurable application settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    /// Show a desktop notification when a turbo run finishes while the window is unfocused
+    pub notify_on_completion: bool,
+    /// Buffered characters that trigger a streaming update to the UI
+    pub stream_chunk_chars: usize,
+    /// Flush buffered stream text after this many milliseconds (0 = size-based only)
+    pub stream_flush_ms: u64,
+    /// Switch to the local model automatically when the network drops in cloud mode
+    pub auto_switch_offline: bool,
+    /// Custom system prompts keyed by mode ("chat" or "turbo"), replacing the defaults
+    pub system_prompt_overrides: HashMap<String, String>,
+    /// Proxy URL for all outbound HTTP (None = use HTTP_PROXY/HTTPS_PROXY from the environment)
+    /// The agent's fetch_url tool always connects directly, so its address checks can't be bypassed
+    pub proxy_url: Option<String>,
+    /// Ask the cloud summarizer for a short conversation title after the first exchange
+    pub generate_titles: bool,
+    /// Seconds to wait for a cloud API connection to open
+    pub http_connect_timeout_secs: u64,
+    /// Total timeout in seconds for short cloud calls (router classify, summarizer, titles)
+    pub http_request_timeout_secs: u64,
+    /// Seconds a streaming response may go without data before it is dropped (0 = never)
+    pub stream_read_timeout_secs: u64,
+    /// Times a turbo reply with no tool call and no <message> is re-prompted with the tool format (0 = never)
+    pub turbo_nudge_retries: u32,
+    /// Connection mode the user last switched to ("cloud" or "offline"), restored on launch
+    pub last_connection_mode: Option<String>,
+    /// Base directory for downloaded models and binaries (None = the OS data directory)
+    pub storage_dir: Option<String>,
+    /// Send agent tools as function declarations to `native_tool_model` instead of relying on XML tags alone
+    /// The Gemma tiers reject declarations, so they stay the XML-tool fallback
+    pub native_tool_calling: bool,
+    /// Gemini model used for agent runs when `native_tool_calling` is on
+    pub native_tool_model: String,
+    /// Limits on saved conversations, applied at startup (unlimited by default)
+    pub history_retention: RetentionPolicy,
+    /// Languages syntax-checked after each agent write ("rust", "javascript", "python")
+    pub syntax_check_languages: Vec<String>,
+    /// Seconds a post-write syntax check may run before it is skipped
+    pub syntax_check_timeout_secs: u64,
+    /// Also emit every streamed token unmodified as `agent-raw-chunk` (debugging only)
+    pub debug_raw_stream: bool,
+    /// Base URL for Gemini API calls, e.g. a gateway or regional endpoint (None = Google's public API)
+    pub gemini_endpoint: Option<String>,
+    /// Switch to a loaded conversation's preferred provider instead of asking first
+    pub auto_switch_conversation_provider: bool,
+    /// Prompt format for the local model (None = detect it from the GGUF metadata)
+    pub local_chat_template: Option<ChatTemplate>,
+    /// Summarize older context automatically as the window fills (uses summarizer quota)
+    pub auto_summarize: bool,
+    /// Total matches search_project returns (at most 5 per file)
+    pub search_max_matches: usize,
+    /// Gitignore-style patterns for files the agent may never list, search or read
+    pub sensitive_patterns: Vec<String>,
+    /// Most verbose level kept for `get_recent_logs` ("error", "warn", "info", "debug", "trace" or "off")
+    pub log_capture_level: String,
+    /// Stream the model's <thinking> as `agent-thinking` (it is always kept out of the final message)
+    pub show_thinking: bool,
+    /// Saved API key sets to switch between
+    pub key_profiles: Vec<ApiKeyProfile>,
+    /// Profile whose keys are in use (None = keys from GEMINI_API_KEYS / .env.local)
+    pub active_key_profile: Option<String>,
+    /// Standing rules put ahead of the system prompt in every mode, keeping the built-in instructions
+    pub system_prompt_prefix: Option<String>,
+    /// Mark tool output as untrusted data and flag instruction-like text in it (prompt-injection guard)
+    pub guard_tool_output: bool,
+    /// Which messages are sent once a conversation grows ("hybrid", "full", "recent" or "rag")
+    pub context_strategy: ContextStrategy,
+    /// Cloud requests per minute on each API key (None = GEMINI_RPM or 30, 0 = unlimited)
+    pub requests_per_minute: Option<u32>,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        Self {
+            notify_on_completion: true,
+            stream_chunk_chars: 100,
+            stream_flush_ms: 50,
+            auto_switch_offline: true,
+            system_prompt_overrides: HashMap::new(),
+            proxy_url: None,
+            generate_titles: false,
+            http_connect_timeout_secs: 10,
+            http_request_timeout_secs: 10,
+            stream_read_timeout_secs: 120,
+            turbo_nudge_retries: 1,
+            last_connection_mode: None,
+            storage_dir: None,
+            native_tool_calling: false,
+            native_tool_model: "gemini-2.0-flash".to_string(),
+            history_retention: RetentionPolicy::default(),
+            syntax_check_languages: Vec::new(),
+            syntax_check_timeout_secs: 10,
+            debug_raw_stream: false,
+            gemini_endpoint: None,
+            auto_switch_conversation_provider: false,
+            local_chat_template: None,
+            auto_summarize: true,
+            search_max_matches: 50,
+            sensitive_patterns: DEFAULT_SENSITIVE_PATTERNS.iter().map(|p| p.to_string()).collect(),
+            log_capture_level: "info".to_string(),
+            show_thinking: true,
+            key_profiles: Vec::new(),
+            active_key_profile: None,
+            system_prompt_prefix: None,
+            guard_tool_output: true,
+            context_strategy: ContextStrategy::default(),
+            requests_per_minute: None,
+        }
+    }
+}
+
+impl AppSettings {
+    /// Check every constrained field, so no path can save settings a setter would refuse
+    /// `update` runs this before writing. The storage directory is only probed when it
+    /// differs from `previous`, since the probe writes to disk and checks free space
+    pub fn validate(&self, previous: &AppSettings) -> Result<(), String> {
+        if let Some(prefix) = &self.system_prompt_prefix {
+            if prefix.chars().count() > MAX_SYSTEM_PROMPT_PREFIX_CHARS {
+                return Err(format!("System prompt prefix is limited to {} characters", MAX_SYSTEM_PROMPT_PREFIX_CHARS));
+            }
+        }
+        if let Some(endpoint) = &self.gemini_endpoint {
+            crate::providers::cloud::normalize_gemini_endpoint(endpoint)?;
+        }
+        if let Some(url) = &self.proxy_url {
+            reqwest::Proxy::all(url).map_err(|e| format!("Invalid proxy URL: {}", e))?;
+        }
+        if let Some(dir) = self.storage_dir.as_ref().filter(|dir| Some(*dir) != previous.storage_dir.as_ref()) {
+            crate::setup::paths::validate_storage_dir(std::path::Path::new(dir))?;
+        }
+        crate::agent::workspace::validate_sensitive_patterns(&self.sensitive_patterns)?;
+        crate::state::logs::parse_level(&self.log_capture_level)?;
+        Ok(())
+    }
+    
+    /// Keys of the active profile, if one is set and still exists
+    pub fn active_profile_keys(&self) -> Option<Vec<String>> {
+        let name = self.active_key_profile.as_ref()?;
+        self.key_profiles.iter().find(|p| &p.name == name).map(|p| p.keys.clone())
+    }
+}
+
+/// In-memory copy of the settings, loaded from disk on first access
+static SETTINGS: Lazy<RwLock<AppSettings>> = Lazy::new(|| RwLock::new(load()));
+
+/// Profile name -> keys, as stored in api_keys.json
+type StoredKeys = HashMap<String, Vec<String>>;
+
+/// Read settings from disk, falling back to defaults
+/// Keys still inside settings.json (written before they moved out) are migrated to api_keys.json
+fn load() -> AppSettings {
+    let Some(json) = std::fs::read_to_string(get_settings_path()).ok()
+        .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok()) else {
+        return AppSettings::default();
+    };
+    let legacy_keys = legacy_profile_keys(&json);
+    let mut settings: AppSettings = serde_json::from_value(json).unwrap_or_default();
+    
+    let mut stored: StoredKeys = std::fs::read_to_string(get_api_keys_path())
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_default();
+    let migrate = !legacy_keys.is_empty();
+    for (name, keys) in legacy_keys {
+        stored.entry(name).or_insert(keys);
+    }
+    attach_keys(&mut settings, &stored);
+    
+    if migrate {
+        match write_files(&settings) {
+            Ok(()) => println!("[Settings] Moved API keys from settings.json to api_keys.json"),
+            Err(e) => eprintln!("[Settings] Failed to migrate API keys: {}", e),
+        }
+    }
+    settings
+}
+
+/// Keys saved inside settings.json by older versions
+fn legacy_profile_keys(json: &serde_json::Value) -> StoredKeys {
+    json.get("key_profiles")
+        .and_then(|profiles| profiles.as_array())
+        .map(|profiles| profiles.iter()
+            .filter_map(|p| {
+                let name = p.get("name")?.as_str()?.to_string();
+                let keys: Vec<String> = serde_json::from_value(p.get("keys")?.clone()).ok()?;
+                Some((name, keys))
+            })
+            .collect())
+        .unwrap_or_default()
+}
+
+/// Fill each profile's keys from the key store
+fn attach_keys(settings: &mut AppSettings, stored: &StoredKeys) {
+    for profile in &mut settings.key_profiles {
+        profile.keys = stored.get(&profile.name).cloned().unwrap_or_default();
+    }
+}
+
+/// Write settings.json (without keys) and api_keys.json
+fn write_files(settings: &AppSettings) -> Result<(), String> {
+    let path = get_settings_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create settings directory: {}", e))?;
+    }
+    
+    let json = serde_json::to_string_pretty(settings)
+        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+    std::fs::write(&path, json)
+        .map_err(|e| format!("Failed to write settings: {}", e))?;
+    
+    let stored: StoredKeys = settings.key_profiles.iter()
+        .map(|p| (p.name.clone(), p.keys.clone()))
+        .collect();
+    write_api_keys(&stored)
+}
+
+/// Persist the key store, readable only by the current user where the platform allows it
+fn write_api_keys(stored: &StoredKeys) -> Result<(), String> {
+    let path = get_api_keys_path();
+    if stored.is_empty() {
+        return match std::fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(format!("Failed to remove API keys: {}", e)),
+            _ => Ok(()),
+        };
+    }
+    
+    let json = serde_json::to_string_pretty(stored)
+        .map_err(|e| format!("Failed to serialize API keys: {}", e))?;
+    std::fs::write(&path, json)
+        .map_err(|e| format!("Failed to write API keys: {}", e))?;
+    
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("Failed to restrict API key file: {}", e))?;
+    }
+    Ok(())
+}
+
+/// Get a copy of the current settings
+pub fn get() -> AppSettings {
+    SETTINGS.read().clone()
+}
+
+/// Re-read the settings file, e.g. after it was restored from a backup
+/// Settings that fail validation are not applied, and the current ones are written back
+pub fn reload() -> Result<(), String> {
+    let previous = get();
+    let loaded = load();
+    if let Err(e) = loaded.validate(&previous) {
+        write_files(&previous)?;
+        return Err(format!("Restored settings are invalid, keeping the current ones: {}", e));
+    }
+    
+    let requests_per_minute = loaded.requests_per_minute;
+    *SETTINGS.write() = loaded;
+    if requests_per_minute != previous.requests_per_minute {
+        crate::providers::cloud::set_requests_per_minute(requests_per_minute);
+    }
+    Ok(())
+}
+
+/// Validate the settings, then replace them and persist them to disk
+pub fn update(settings: AppSettings) -> Result<(), String> {
+    let previous = get();
+    settings.validate(&previous)?;
+    write_files(&settings)?;
+    let requests_per_minute = settings.requests_per_minute;
+    *SETTINGS.write() = settings;
+    
+    if requests_per_minute != previous.requests_per_minute {
+        crate::providers::cloud::set_requests_per_minute(requests_per_minute);
+    }
+    Ok(())
+}
+
+/// Apply the configured proxy to an HTTP client builder
+/// Without one, reqwest falls back to the HTTP_PROXY/HTTPS_PROXY environment variables
+pub fn apply_proxy(builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+    let Some(url) = get().proxy_url else {
+        return builder;
+    };
+    
+    match reqwest::Proxy::all(&url) {
+        Ok(proxy) => builder.proxy(proxy),
+        Err(e) => {
+            eprintln!("[Settings] Ignoring invalid proxy '{}': {}", url, e);
+            builder
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keys_are_not_serialized_and_legacy_keys_are_read() {
+        let mut settings = AppSettings::default();
+        settings.key_profiles.push(ApiKeyProfile { name: "work".to_string(), keys: vec!["AIza-secret".to_string()] });
+        
+        let json = serde_json::to_value(&settings).unwrap();
+        assert!(!json.to_string().contains("AIza-secret"));
+        assert!(legacy_profile_keys(&json).is_empty());
+        
+        let legacy = serde_json::json!({ "key_profiles": [{ "name": "work", "keys": ["AIza-old"] }] });
+        let stored = legacy_profile_keys(&legacy);
+        let mut loaded: AppSettings = serde_json::from_value(legacy).unwrap();
+        assert!(loaded.key_profiles[0].keys.is_empty());
+        attach_keys(&mut loaded, &stored);
+        assert_eq!(loaded.key_profiles[0].keys, vec!["AIza-old".to_string()]);
+    }
+
+    #[test]
+    fn test_validate_rejects_what_the_setters_refuse() {
+        let defaults = AppSettings::default();
+        assert!(defaults.validate(&defaults).is_ok());
+        
+        let invalid = [
+            AppSettings { system_prompt_prefix: Some("x".repeat(MAX_SYSTEM_PROMPT_PREFIX_CHARS + 1)), ..AppSettings::default() },
+            AppSettings { gemini_endpoint: Some("ftp://example.com/v1beta".to_string()), ..AppSettings::default() },
+            AppSettings { proxy_url: Some("not a url".to_string()), ..AppSettings::default() },
+            AppSettings { storage_dir: Some("relative/models".to_string()), ..AppSettings::default() },
+            AppSettings { sensitive_patterns: vec!["[".to_string()], ..AppSettings::default() },
+            AppSettings { log_capture_level: "loud".to_string(), ..AppSettings::default() },
+        ];
+        for settings in invalid {
+            assert!(settings.validate(&defaults).is_err(), "{:?} should be rejected", settings);
+        }
+    }
+}
@@ -25,7 +25,7 @@ import { listen } from '@tauri-apps/api/event';
 
 export default function ChatPanel() {
     const [input, setInput] = useState('');
-    const { messages, addMessage, status, mode, pendingTool, setPendingTool, setStatus, thinkingText, conversations, clearHistory } = useAgentStore();
+    const { messages, addMessage, status, mode, pendingTool, nextPendingTool, setStatus, thinkingText, conversations, clearHistory } = useAgentStore();
     const { startAgent, cancelAgent } = useAgent();
     const inputRef = useRef<HTMLTextAreaElement>(null);
     const [showHistory, setShowHistory] = useState(false);
@@ -110,24 +110,24 @@ export default function ChatPanel() {
 
     const handleApproveTool = useCallback(async () => {
         try {
-            await invoke('send_user_feedback', { approved: true });
-            setPendingTool(null);
+            await invoke('send_user_feedback', { approved: true, request_id: pendingTool?.requestId });
+            nextPendingTool();
             setStatus('executing');
         } catch (err) {
             console.error('Failed to approve:', err);
             toast.error('Failed to send approval');
         }
-    }, [setPendingTool, setStatus]);
+    }, [pendingTool, nextPendingTool, setStatus]);
 
     const handleDenyTool = useCallback(async () => {
         try {
-            await invoke('send_user_feedback', { approved: false });
-            setPendingTool(null);
+            await invoke('send_user_feedback', { approved: false, request_id: pendingTool?.requestId });
+            nextPendingTool();
             setStatus('thinking');
         } catch (err) {
             console.error('Failed to deny:', err);
         }
-    }, [setPendingTool, setStatus]);
+    }, [pendingTool, nextPendingTool, setStatus]);
 
     const handleNewChat = () => {
         clearHistory();
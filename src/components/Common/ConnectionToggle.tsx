@@ -150,11 +150,19 @@ export default function ConnectionToggle() {
                 unlistenProgress();
             });
 
-            // Start binary download
-            await invoke('download_binaries', { gpu_type: detectedGpuLocal.gpu_type });
+            // Start binary download - a pause returns normally and the user resumes later
+            const binaries = await invoke<string>('download_binaries', { gpu_type: detectedGpuLocal.gpu_type });
+            if (binaries === 'paused') {
+                unlistenComplete();
+                return;
+            }
 
             // Start model download
-            await invoke('download_model');
+            const model = await invoke<string>('download_model');
+            if (model === 'paused') {
+                unlistenComplete();
+                return;
+            }
 
             // Now switch to offline mode
             await handleConfirmSwitch();
@@ -370,7 +378,7 @@ export default function ConnectionToggle() {
                             <span>
                                 {downloadProgress.state === 'paused'
                                     ? 'Paused'
-                                    : `${downloadProgress.speed_mbps.toFixed(1)} MB/s`}
+                                    : `${downloadProgress.avg_speed_mbps.toFixed(1)} MB/s`}
                             </span>
                             <span>
                                 {downloadProgress.state === 'paused'
@@ -2,6 +2,7 @@ import { useEffect, useRef, useCallback } from 'react';
 import { invoke } from '@tauri-apps/api/core';
 import { listen, UnlistenFn } from '@tauri-apps/api/event';
 import { useAgentStore } from '@/stores/useAgentStore';
+import type { AgentStatus } from '@/lib/eventBus';
 
 interface FileAccessEvent {
     action: 'read' | 'write';
@@ -11,6 +12,7 @@ interface FileAccessEvent {
 interface ApprovalRequest {
     tool: string;
     parameters: string;
+    request_id: number;
 }
 
 // Streaming timeout in milliseconds (2 minutes)
@@ -24,6 +26,7 @@ export function useAgent() {
         setStatus,
         setStreaming,
         setPendingTool,
+        queueTool,
         setThinkingText,
         openFile,
     } = useAgentStore();
@@ -94,21 +97,28 @@ export function useAgent() {
         }));
 
         // Listen for Status Updates
-        listeners.push(listen<string>('agent-status', (event) => {
+        listeners.push(listen<AgentStatus>('agent-status', (event) => {
             if (!isMounted) return;
-            const status = event.payload.toLowerCase();
             const { setStatus, setStreaming } = storeRef.current;
 
-            if (status.includes('idle') || status.includes('ready') || status.includes('complete')) {
-                setStatus('idle');
-                setStreaming(false);
-            } else if (status.includes('thinking')) {
-                setStatus('thinking');
-            } else if (status.includes('executing')) {
-                setStatus('executing');
-            } else if (status.includes('cancel') || status.includes('denied')) {
-                setStatus('idle');
-                setStreaming(false);
+            switch (event.payload.state) {
+                case 'ready':
+                case 'cancelled':
+                case 'max_steps':
+                case 'error':
+                    setStatus('idle');
+                    setStreaming(false);
+                    break;
+                case 'thinking':
+                case 'streaming':
+                case 'nudging':
+                case 'retrying':
+                    setStatus('thinking');
+                    break;
+                case 'executing_tool':
+                case 'executing_tools':
+                    setStatus('executing');
+                    break;
             }
         }));
 
@@ -131,9 +141,10 @@ export function useAgent() {
         listeners.push(listen<ApprovalRequest>('agent-approval-request', (event) => {
             if (!isMounted) return;
             console.log('[useAgent] Approval request:', event.payload);
-            setPendingTool({
+            queueTool({
                 name: event.payload.tool,
-                args: event.payload.parameters
+                args: event.payload.parameters,
+                requestId: event.payload.request_id
             });
         }));
 
@@ -182,7 +193,7 @@ export function useAgent() {
             unlistenRef.current = [];
             clearStreamingTimeout();
         };
-    }, [openFile, setPendingTool]);
+    }, [openFile, queueTool]);
 
     // Start agent task
     const startAgent = useCallback(async (prompt: string) => {
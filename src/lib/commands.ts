@@ -3,6 +3,7 @@
 //! Typed command wrappers for Tauri invoke calls.
 
 import { invoke } from '@tauri-apps/api/core';
+import type { SpeedSample } from './eventBus';
 
 // --- Response Types ---
 
@@ -17,6 +18,9 @@ export interface GpuDetectionResult {
     vram_mb?: number;
 }
 
+/** A paused download resolves normally; call the download command again to resume */
+export type DownloadOutcome = 'completed' | 'paused';
+
 export interface ConversationMeta {
     id: string;
     title: string;
@@ -24,6 +28,15 @@ export interface ConversationMeta {
     updated_at: string;
     message_count: number;
     mode: string;
+    /** The file couldn't be read; repairConversation may recover part of it */
+    corrupt?: boolean;
+}
+
+export interface WorkspaceInfo {
+    root: string;
+    workspace: string;
+    data: string;
+    history: string;
 }
 
 export interface UpdateInfo {
@@ -44,10 +57,17 @@ export const Commands = {
     cancelAgentTask: (): Promise<void> =>
         invoke('cancel_agent_task'),
 
-    sendUserFeedback: (approved: boolean): Promise<void> =>
-        invoke('send_user_feedback', { approved }),
+    sendUserFeedback: (approved: boolean, requestId?: number): Promise<void> =>
+        invoke('send_user_feedback', { approved, request_id: requestId }),
+
+    /** Drop the agent's context but stay in the current conversation */
+    clearContext: (): Promise<void> =>
+        invoke('clear_context'),
 
     // Workspace Commands
+    getWorkspaceInfo: (): Promise<WorkspaceInfo> =>
+        invoke('get_workspace_info'),
+
     readWorkspaceFile: (path: string): Promise<string> =>
         invoke('read_workspace_file', { path }),
 
@@ -64,6 +84,9 @@ export const Commands = {
     deleteConversation: (id: string): Promise<void> =>
         invoke('delete_conversation', { id }),
 
+    repairConversation: (id: string): Promise<ConversationMeta> =>
+        invoke('repair_conversation', { id }),
+
     // Setup Commands
     detectGpu: (): Promise<GpuDetectionResult> =>
         invoke('detect_gpu_cmd'),
@@ -71,10 +94,10 @@ export const Commands = {
     checkSetupStatus: (): Promise<SetupStatus> =>
         invoke('check_setup_status'),
 
-    downloadBinaries: (gpuType: string): Promise<void> =>
+    downloadBinaries: (gpuType: string): Promise<DownloadOutcome> =>
         invoke('download_binaries', { gpu_type: gpuType }),
 
-    downloadModel: (): Promise<void> =>
+    downloadModel: (): Promise<DownloadOutcome> =>
         invoke('download_model'),
 
     setConnectionMode: (mode: 'cloud' | 'offline'): Promise<void> =>
@@ -89,6 +112,12 @@ export const Commands = {
     cancelDownload: (): Promise<void> =>
         invoke('cancel_download'),
 
+    getDownloadSpeedHistory: (): Promise<SpeedSample[]> =>
+        invoke('get_download_speed_history'),
+
+    cancelModelLoad: (): Promise<void> =>
+        invoke('cancel_model_load'),
+
     // Update Commands
     checkForUpdates: (): Promise<UpdateInfo | null> =>
         invoke('check_for_updates'),
@@ -13,8 +13,25 @@ export interface AgentStreamChunk {
 export interface AgentApprovalRequest {
     tool: string;
     parameters: string;
+    request_id: number;
 }
 
+export type AgentStatus =
+    | { state: 'thinking' }
+    | { state: 'streaming' }
+    | { state: 'executing_tool'; name: string }
+    | { state: 'executing_tools'; count: number }
+    | { state: 'waiting_approval'; tool: string }
+    | { state: 'nudging' }
+    | { state: 'retrying' }
+    | { state: 'loading_model' }
+    | { state: 'benchmarking' }
+    | { state: 'ready' }
+    | { state: 'cancelled' }
+    | { state: 'max_steps' }
+    | { state: 'error'; message: string }
+    | { state: 'notice'; message: string };
+
 export interface AgentToolResult {
     tool: string;
     parameters: unknown;
@@ -34,6 +51,17 @@ export interface DownloadProgress {
     state: 'downloading' | 'paused' | 'resuming' | 'completed' | 'error';
 }
 
+export interface SpeedSample {
+    timestamp_ms: number;
+    bytes: number;
+    speed_mbps: number;
+}
+
+export interface DownloadFailure {
+    step: string;
+    error: string;
+}
+
 export interface UpdateProgress {
     downloaded: number;
     total: number;
@@ -47,6 +75,13 @@ export interface UpdateError {
     retryable: boolean;
 }
 
+// A loaded conversation was last used with the other connection mode
+export interface ProviderPreferenceMismatch {
+    conversation_id: string;
+    preferred: 'cloud' | 'offline';
+    active: 'cloud' | 'offline';
+}
+
 // --- Event Bus ---
 
 export const EventBus = {
@@ -54,14 +89,18 @@ export const EventBus = {
     onStreamChunk: (callback: (chunk: string) => void): Promise<UnlistenFn> =>
         listen<string>('agent-stream-chunk', (e) => callback(e.payload)),
 
+    // Unmodified model tokens - only sent when the debug_raw_stream setting is on
+    onRawChunk: (callback: (token: string) => void): Promise<UnlistenFn> =>
+        listen<string>('agent-raw-chunk', (e) => callback(e.payload)),
+
     onThinking: (callback: (text: string) => void): Promise<UnlistenFn> =>
         listen<string>('agent-thinking', (e) => callback(e.payload)),
 
     onStreaming: (callback: (isStreaming: boolean) => void): Promise<UnlistenFn> =>
         listen<boolean>('agent-streaming', (e) => callback(e.payload)),
 
-    onStatus: (callback: (status: string) => void): Promise<UnlistenFn> =>
-        listen<string>('agent-status', (e) => callback(e.payload)),
+    onStatus: (callback: (status: AgentStatus) => void): Promise<UnlistenFn> =>
+        listen<AgentStatus>('agent-status', (e) => callback(e.payload)),
 
     onApprovalRequest: (callback: (req: AgentApprovalRequest) => void): Promise<UnlistenFn> =>
         listen<AgentApprovalRequest>('agent-approval-request', (e) => callback(e.payload)),
@@ -92,6 +131,15 @@ export const EventBus = {
     onDownloadProgress: (callback: (progress: DownloadProgress) => void): Promise<UnlistenFn> =>
         listen<DownloadProgress>('download-progress', (e) => callback(e.payload)),
 
+    onDownloadPaused: (callback: (progress: DownloadProgress) => void): Promise<UnlistenFn> =>
+        listen<DownloadProgress>('download-paused', (e) => callback(e.payload)),
+
+    onDownloadError: (callback: (failure: DownloadFailure) => void): Promise<UnlistenFn> =>
+        listen<DownloadFailure>('download-error', (e) => callback(e.payload)),
+
+    onDownloadSpeedSample: (callback: (sample: SpeedSample) => void): Promise<UnlistenFn> =>
+        listen<SpeedSample>('download-speed-sample', (e) => callback(e.payload)),
+
     onSetupComplete: (callback: () => void): Promise<UnlistenFn> =>
         listen('setup-complete', () => callback()),
 
@@ -109,6 +157,16 @@ export const EventBus = {
     onConnectionModeChanged: (callback: (mode: string) => void): Promise<UnlistenFn> =>
         listen<string>('connection-mode-changed', (e) => callback(e.payload)),
 
+    onProviderPreferenceMismatch: (callback: (mismatch: ProviderPreferenceMismatch) => void): Promise<UnlistenFn> =>
+        listen<ProviderPreferenceMismatch>('provider-preference-mismatch', (e) => callback(e.payload)),
+
+    onLocalModelMissing: (callback: () => void): Promise<UnlistenFn> =>
+        listen('local-model-missing', () => callback()),
+
+    // Context summary text so far, while the summarizer streams
+    onContextSummaryChunk: (callback: (partial: string) => void): Promise<UnlistenFn> =>
+        listen<string>('context-summary-chunk', (e) => callback(e.payload)),
+
     // Emit helpers
     emitFrontendLoaded: (): Promise<void> =>
         emit('frontend_loaded', true),
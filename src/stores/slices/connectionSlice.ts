@@ -18,6 +18,7 @@ export interface DownloadProgress {
     step: 'binaries' | 'model';
     percent: number;
     speed_mbps: number;
+    avg_speed_mbps: number;
     eta_seconds: number;
     state: DownloadState;
 }
@@ -28,6 +28,7 @@ export interface DownloadProgress {
     step: 'binaries' | 'model';
     percent: number;
     speed_mbps: number;
+    avg_speed_mbps: number;
     eta_seconds: number;
     state: DownloadState;
 }
@@ -42,6 +43,14 @@ export interface EditorFile {
     source: 'user' | 'agent';
 }
 
+// Tool call waiting for the user's approval
+export interface PendingTool {
+    name: string;
+    args: string;
+    /** Answers go to this request, so concurrent calls can't resolve each other */
+    requestId?: number;
+}
+
 // Conversation metadata
 export interface ConversationMeta {
     id: string;
@@ -68,7 +77,9 @@ interface AgentState {
 
     // Messages
     messages: Message[];
-    pendingTool: { name: string; args: string } | null;
+    pendingTool: PendingTool | null;
+    /** Approval requests waiting behind pendingTool - a turn's tool calls can ask at once */
+    queuedTools: PendingTool[];
     thinkingText: string; // Current thinking/reasoning text from model
 
     // Multi-file editor
@@ -95,7 +106,11 @@ interface AgentState {
     // Message actions
     addMessage: (msg: Omit<Message, 'id' | 'timestamp'>) => void;
     updateStreamingMessage: (content: string) => void;
-    setPendingTool: (tool: { name: string; args: string } | null) => void;
+    setPendingTool: (tool: PendingTool | null) => void;
+    /** Show an approval request, or queue it behind the one being shown */
+    queueTool: (tool: PendingTool) => void;
+    /** Move on to the next queued approval request after the shown one is answered */
+    nextPendingTool: () => void;
     setThinkingText: (text: string) => void;
     clearHistory: () => void;
 
@@ -144,6 +159,7 @@ export const useAgentStore = create<AgentState>()(
         detectedGpu: null,
         messages: [],
         pendingTool: null,
+        queuedTools: [],
         thinkingText: '',
         openFiles: [],
         activeFileIndex: -1,
@@ -153,7 +169,14 @@ export const useAgentStore = create<AgentState>()(
         setMode: (mode) => set({ mode }),
         setStatus: (status) => set({ status, thinkingText: status === 'idle' ? '' : get().thinkingText }),
         setStreaming: (isStreaming) => set({ isStreaming }),
-        setPendingTool: (pendingTool) => set({ pendingTool }),
+        setPendingTool: (pendingTool) => set(pendingTool ? { pendingTool } : { pendingTool: null, queuedTools: [] }),
+        queueTool: (tool) => set((state) => state.pendingTool
+            ? { queuedTools: [...state.queuedTools, tool] }
+            : { pendingTool: tool }),
+        nextPendingTool: () => set((state) => ({
+            pendingTool: state.queuedTools[0] ?? null,
+            queuedTools: state.queuedTools.slice(1),
+        })),
         setThinkingText: (thinkingText) => set({ thinkingText }),
 
         // Connection mode actions